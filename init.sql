@@ -2,7 +2,15 @@ CREATE TABLE IF NOT EXISTS projects (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
     auto_load BOOLEAN DEFAULT 1,
     name TEXT NOT NULL,
-    description TEXT NOT NULL
+    description TEXT NOT NULL,
+    index_type TEXT NOT NULL DEFAULT 'vptree',
+    max_file_versions INTEGER NOT NULL DEFAULT 10,
+    embedding_provider TEXT NOT NULL DEFAULT 'openai',
+    embedding_base_url TEXT,
+    embedding_model TEXT,
+    embedding_dimensions INTEGER,
+    chunking TEXT,
+    monthly_token_quota INTEGER
 );
 
 CREATE TABLE IF NOT EXISTS users (
@@ -16,9 +24,29 @@ CREATE TABLE IF NOT EXISTS file_entry (
     name TEXT NOT NULL,
     path TEXT NOT NULL,
     project_id INTEGER NOT NULL,
+    content_hash TEXT,
+    embedded_hash TEXT,
+    current_version INTEGER NOT NULL DEFAULT 1,
+    external_id TEXT,
+    metadata TEXT,
     FOREIGN KEY (project_id) REFERENCES projects(id)
 );
 
+CREATE TABLE IF NOT EXISTS file_version (
+    file_id INTEGER NOT NULL,
+    version INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    chunking TEXT,
+    format TEXT,
+    text_path TEXT,
+    sections TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (file_id, version),
+    FOREIGN KEY (file_id) REFERENCES file_entry(id)
+);
+
 CREATE TABLE if NOT EXISTS user_project (
     user_id INTEGER NOT NULL,
     project_id INTEGER NOT NULL,
@@ -30,14 +58,75 @@ CREATE TABLE if NOT EXISTS user_project (
 
 CREATE TABLE IF NOT EXISTS file_embedding (
     file_id INTEGER NOT NULL,
+    version INTEGER NOT NULL DEFAULT 1,
     start_byte INTEGER NOT NULL,
     end_byte INTEGER NOT NULL,
+    chunk_hash TEXT,
+    symbol TEXT,
+    start_line INTEGER,
+    end_line INTEGER,
     embedding BLOB NOT NULL,
     FOREIGN KEY (file_id) REFERENCES file_entry(id),
-    PRIMARY KEY (file_id, start_byte, end_byte)
+    PRIMARY KEY (file_id, version, start_byte, end_byte)
+);
+
+CREATE TABLE IF NOT EXISTS file_chunk (
+    file_id INTEGER NOT NULL,
+    version INTEGER NOT NULL,
+    start_byte INTEGER NOT NULL,
+    end_byte INTEGER NOT NULL,
+    chunk_hash TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    error TEXT,
+    FOREIGN KEY (file_id) REFERENCES file_entry(id),
+    PRIMARY KEY (file_id, version, start_byte, end_byte)
+);
+
+CREATE TABLE IF NOT EXISTS embedding_cache (
+    model TEXT NOT NULL,
+    text_hash TEXT NOT NULL,
+    embedding BLOB NOT NULL,
+    hits INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_used_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (model, text_hash)
+);
+
+CREATE TABLE IF NOT EXISTS token_usage (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    project_id INTEGER NOT NULL,
+    user_id INTEGER,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    tokens INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS embedding_job (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    project_id INTEGER NOT NULL,
+    file_id INTEGER NOT NULL,
+    status TEXT NOT NULL DEFAULT 'queued',
+    chunks_done INTEGER NOT NULL DEFAULT 0,
+    chunks_total INTEGER NOT NULL DEFAULT 0,
+    tokens INTEGER NOT NULL DEFAULT 0,
+    force BOOLEAN NOT NULL DEFAULT 0,
+    user_id INTEGER,
+    error TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    started_at TEXT,
+    finished_at TEXT,
+    FOREIGN KEY (project_id) REFERENCES projects(id)
 );
 
 CREATE INDEX idx_file_entry_project_id ON file_entry(project_id);
+CREATE UNIQUE INDEX idx_file_entry_external_id ON file_entry(project_id, external_id);
+CREATE UNIQUE INDEX idx_file_entry_name ON file_entry(project_id, name);
 CREATE INDEX idx_user_project_user_id ON user_project(user_id);
 CREATE INDEX idx_user_project_project_id ON user_project(project_id);
 CREATE INDEX idx_file_embedding_file_id ON file_embedding(file_id);
+CREATE INDEX idx_embedding_job_status ON embedding_job(status);
+CREATE INDEX idx_embedding_cache_last_used_at ON embedding_cache(last_used_at);
+CREATE INDEX idx_token_usage_project_id ON token_usage(project_id, created_at);
+CREATE INDEX idx_token_usage_user_id ON token_usage(user_id, created_at);
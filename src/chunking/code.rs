@@ -0,0 +1,262 @@
+//! Chunks source code along its syntax tree, so a function, class or impl block ends up in a chunk
+//! of its own instead of being cut in half by a token window.
+//!
+//! The top level of the file is split into definitions and whatever lies between them (imports,
+//! statements). Comments and attributes directly above a definition stay with it. A definition that
+//! is over budget is split along its members, and one without members by tokens.
+
+use std::ops::Range;
+use serde::Serialize;
+use tree_sitter::{Node, Parser};
+use super::token_aware::{self, TokenChunk};
+use super::tokens::TokenCounter;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    Rust,
+    Python,
+    TypeScript,
+    Tsx,
+    Go,
+    Java,
+}
+
+/// Where a chunk sits in the source: the definition it belongs to, if any, and its 1-based lines.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CodeSpan {
+    pub symbol: Option<String>,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+impl Language {
+    pub fn from_path(path: &str) -> Option<Language> {
+        let extension = std::path::Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "rs" => Some(Language::Rust),
+            "py" | "pyi" => Some(Language::Python),
+            "ts" | "mts" | "cts" => Some(Language::TypeScript),
+            "tsx" => Some(Language::Tsx),
+            "go" => Some(Language::Go),
+            "java" => Some(Language::Java),
+            _ => None,
+        }
+    }
+
+    fn grammar(&self) -> tree_sitter::Language {
+        match self {
+            Language::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Language::Python => tree_sitter_python::LANGUAGE.into(),
+            Language::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Language::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Language::Go => tree_sitter_go::LANGUAGE.into(),
+            Language::Java => tree_sitter_java::LANGUAGE.into(),
+        }
+    }
+
+    fn is_definition(&self, kind: &str) -> bool {
+        match self {
+            Language::Rust => matches!(kind, "function_item" | "impl_item" | "trait_item" | "struct_item" | "enum_item"
+                | "union_item" | "mod_item" | "macro_definition"),
+            Language::Python => matches!(kind, "function_definition" | "class_definition" | "decorated_definition"),
+            Language::TypeScript | Language::Tsx => matches!(kind, "function_declaration" | "generator_function_declaration"
+                | "class_declaration" | "abstract_class_declaration" | "interface_declaration" | "enum_declaration"
+                | "type_alias_declaration" | "internal_module" | "module" | "method_definition" | "export_statement"
+                | "lexical_declaration"),
+            Language::Go => matches!(kind, "function_declaration" | "method_declaration" | "type_declaration"),
+            Language::Java => matches!(kind, "class_declaration" | "interface_declaration" | "enum_declaration"
+                | "record_declaration" | "annotation_type_declaration" | "method_declaration" | "constructor_declaration"),
+        }
+    }
+
+    /// Nodes that belong to the definition that follows them.
+    fn is_leading(&self, kind: &str) -> bool {
+        match self {
+            Language::Rust => matches!(kind, "line_comment" | "block_comment" | "attribute_item"),
+            Language::Java => matches!(kind, "line_comment" | "block_comment"),
+            Language::Python | Language::TypeScript | Language::Tsx | Language::Go => kind == "comment",
+        }
+    }
+
+    fn separator(&self) -> &'static str {
+        match self {
+            Language::Rust => "::",
+            _ => ".",
+        }
+    }
+}
+
+/// Splits `text` along its syntax tree into chunks of at most `max_tokens`. Like every chunker here, the
+/// chunks cover the text end to end. Returns `None` when the text can't be parsed at all.
+pub fn chunk(text: &str, language: Language, counter: &dyn TokenCounter, max_tokens: usize) -> Option<Vec<(TokenChunk, CodeSpan)>> {
+    let mut parser = Parser::new();
+    parser.set_language(&language.grammar()).ok()?;
+    let tree = parser.parse(text, None)?;
+
+    let mut chunker = Chunker {
+        text,
+        language,
+        counter,
+        max_tokens: max_tokens.max(1),
+        line_starts: std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect(),
+        chunks: Vec::new(),
+    };
+    chunker.split(tree.root_node(), 0..text.len(), None);
+    Some(chunker.chunks)
+}
+
+struct Chunker<'a> {
+    text: &'a str,
+    language: Language,
+    counter: &'a dyn TokenCounter,
+    max_tokens: usize,
+    line_starts: Vec<usize>,
+    chunks: Vec<(TokenChunk, CodeSpan)>,
+}
+
+impl<'a> Chunker<'a> {
+    /// Covers `range` with chunks: one per definition among `container`'s children, one for each run of
+    /// other code between them, which belongs to `parent`.
+    fn split(&mut self, container: Node, range: Range<usize>, parent: Option<&str>) {
+        let mut position = range.start;
+        let mut leading: Option<usize> = None;
+        let mut cursor = container.walk();
+        for child in container.named_children(&mut cursor) {
+            if child.start_byte() < position || child.end_byte() > range.end {
+                continue;
+            }
+            if let Some(name) = self.definition_name(child) {
+                let symbol = parent.map_or(name.clone(), |parent| format!("{}{}{}", parent, self.language.separator(), name));
+                let mut start = leading.unwrap_or(child.start_byte());
+                // Blank lines before a definition go with it; code before it is a chunk of its own
+                if self.text[position..start].trim().is_empty() {
+                    start = position;
+                } else {
+                    self.plain(position..start, parent);
+                }
+                self.definition(child, start..child.end_byte(), &symbol);
+                position = child.end_byte();
+                leading = None;
+            } else if self.language.is_leading(child.kind()) {
+                leading.get_or_insert(child.start_byte());
+            } else {
+                leading = None;
+            }
+        }
+        if position < range.end {
+            self.plain(position..range.end, parent);
+        }
+    }
+
+    fn definition(&mut self, node: Node, range: Range<usize>, symbol: &str) {
+        let tokens = self.counter.count(&self.text[range.clone()]);
+        if tokens <= self.max_tokens {
+            self.push(range, tokens, Some(symbol));
+            return;
+        }
+        match unwrap(node).child_by_field_name("body") {
+            Some(body) => self.split(body, range, Some(symbol)),
+            None => self.plain(range, Some(symbol)),
+        }
+    }
+
+    /// Code that isn't a definition of its own, chunked by tokens.
+    fn plain(&mut self, range: Range<usize>, symbol: Option<&str>) {
+        if range.is_empty() {
+            return;
+        }
+        // Trailing whitespace joins the chunk before it when it fits
+        if self.text[range.clone()].trim().is_empty() {
+            if let Some((last, _)) = self.chunks.last() {
+                let extended = last.start..range.end;
+                let tokens = self.counter.count(&self.text[extended.clone()]);
+                if last.end == range.start && tokens <= self.max_tokens {
+                    let (last, _) = self.chunks.last_mut().unwrap();
+                    last.end = range.end;
+                    last.tokens = tokens;
+                    return;
+                }
+            }
+        }
+        let max_tokens = self.max_tokens;
+        for chunk in token_aware::chunk_with_budget(&self.text[range.clone()], self.counter, max_tokens / 4, max_tokens / 2, max_tokens) {
+            self.push(range.start + chunk.start..range.start + chunk.end, chunk.tokens, symbol);
+        }
+    }
+
+    fn push(&mut self, range: Range<usize>, tokens: usize, symbol: Option<&str>) {
+        // Lines are those of the code in the chunk, not of the blank lines around it
+        let code = &self.text[range.clone()];
+        let first = range.start + (code.len() - code.trim_start().len());
+        let last = range.start + code.trim_end().len();
+        let (first, last) = if first < last { (first, last) } else { (range.start, range.end) };
+        let span = CodeSpan {
+            symbol: symbol.map(String::from),
+            start_line: self.line(first),
+            end_line: self.line(last - 1),
+        };
+        self.chunks.push((TokenChunk { start: range.start, end: range.end, tokens }, span));
+    }
+
+    /// 1-based line of the byte at `offset`.
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|start| *start <= offset)
+    }
+
+    fn node_text(&self, node: Node) -> &'a str {
+        &self.text[node.byte_range()]
+    }
+
+    /// The name of the symbol `node` defines, or `None` when it isn't a definition.
+    fn definition_name(&self, node: Node) -> Option<String> {
+        if !self.language.is_definition(node.kind()) {
+            return None;
+        }
+        let node = unwrap(node);
+        let name = match node.kind() {
+            "impl_item" => {
+                let target = self.node_text(node.child_by_field_name("type")?);
+                match node.child_by_field_name("trait") {
+                    Some(trait_name) => format!("<{} as {}>", target, self.node_text(trait_name)),
+                    None => target.to_string(),
+                }
+            },
+            // Only `const f = () => ...` and the like count as definitions
+            "lexical_declaration" => {
+                let mut cursor = node.walk();
+                let declarator = node.named_children(&mut cursor).find(|declarator| {
+                    declarator.child_by_field_name("value")
+                        .is_some_and(|value| matches!(value.kind(), "arrow_function" | "function_expression" | "function" | "class"))
+                })?;
+                self.node_text(declarator.child_by_field_name("name")?).to_string()
+            },
+            "type_declaration" => {
+                let mut cursor = node.walk();
+                let spec = node.named_children(&mut cursor).find(|spec| matches!(spec.kind(), "type_spec" | "type_alias"))?;
+                self.node_text(spec.child_by_field_name("name")?).to_string()
+            },
+            "method_declaration" if self.language == Language::Go => {
+                let name = self.node_text(node.child_by_field_name("name")?);
+                let receiver = node.child_by_field_name("receiver")
+                    .and_then(|receiver| receiver.named_child(0))
+                    .and_then(|parameter| parameter.child_by_field_name("type"));
+                match receiver {
+                    Some(receiver) => format!("{}.{}", self.node_text(receiver).trim_start_matches('*'), name),
+                    None => name.to_string(),
+                }
+            },
+            _ => self.node_text(node.child_by_field_name("name")?).to_string(),
+        };
+        Some(name)
+    }
+}
+
+/// The definition a wrapper node (decorators, `export`) stands for.
+fn unwrap(node: Node) -> Node {
+    match node.kind() {
+        "decorated_definition" => node.child_by_field_name("definition").unwrap_or(node),
+        "export_statement" => node.child_by_field_name("declaration").unwrap_or(node),
+        _ => node,
+    }
+}
@@ -0,0 +1,68 @@
+//! Content-defined chunking with a gear rolling hash (the scheme used by FastCDC).
+//!
+//! Boundaries depend only on the bytes around them, so an edit early in a file shifts the
+//! boundaries near the edit but leaves later chunks byte-for-byte identical. That is what lets
+//! re-embedding reuse the vectors of unchanged chunks.
+
+pub const MIN_CHUNK_SIZE: usize = 512;
+pub const AVG_CHUNK_SIZE: usize = 1024;
+pub const MAX_CHUNK_SIZE: usize = 2048;
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64, so the table is fixed across builds and boundaries stay stable
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+static GEAR: [u64; 256] = gear_table();
+
+fn is_utf8_continuation(byte: u8) -> bool {
+    byte & 0xC0 == 0x80
+}
+
+/// Splits `data` into `(start, end)` byte ranges using the default sizes.
+pub fn chunk(data: &[u8]) -> Vec<(usize, usize)> {
+    chunk_with_sizes(data, MIN_CHUNK_SIZE, AVG_CHUNK_SIZE, MAX_CHUNK_SIZE)
+}
+
+/// Splits `data` into `(start, end)` byte ranges. A boundary is placed where the rolling hash
+/// matches the mask (roughly every `avg_size` bytes), never before `min_size` and always by
+/// `max_size`. Boundaries are pushed past UTF-8 continuation bytes so text chunks stay valid.
+pub fn chunk_with_sizes(data: &[u8], min_size: usize, avg_size: usize, max_size: usize) -> Vec<(usize, usize)> {
+    let bits = avg_size.next_power_of_two().trailing_zeros();
+    let mask: u64 = if bits == 0 { 0 } else { !0u64 << (64 - bits) };
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut hash: u64 = 0;
+    let mut i = 0;
+    while i < data.len() {
+        hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+        i += 1;
+
+        let size = i - start;
+        if (size >= min_size && hash & mask == 0) || size >= max_size {
+            while i < data.len() && is_utf8_continuation(data[i]) {
+                i += 1;
+            }
+            chunks.push((start, i));
+            start = i;
+            hash = 0;
+        }
+    }
+
+    if start < data.len() {
+        chunks.push((start, data.len()));
+    }
+    chunks
+}
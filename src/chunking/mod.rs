@@ -0,0 +1,36 @@
+pub mod content_defined;
+pub mod tokens;
+pub mod token_aware;
+pub mod strategy;
+pub mod code;
+
+use serde::Serialize;
+
+/// Where a chunk sits in its text: the 1-based line and column (counted in characters) of its first and last
+/// character.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+pub fn locate(text: &[u8], start: usize, end: usize) -> Location {
+    let (start_line, start_column) = position(text, start);
+    // The last character starts at the last byte that isn't a UTF-8 continuation byte
+    let mut last = end.min(text.len()).max(start + 1) - 1;
+    while last > start && text.get(last).is_some_and(|byte| byte & 0xC0 == 0x80) {
+        last -= 1;
+    }
+    let (end_line, end_column) = position(text, last);
+    Location { start_line, start_column, end_line, end_column }
+}
+
+fn position(text: &[u8], offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.iter().rposition(|byte| *byte == b'\n').map_or(0, |newline| newline + 1);
+    let line = 1 + before.iter().filter(|byte| **byte == b'\n').count();
+    let column = 1 + String::from_utf8_lossy(&before[line_start..]).chars().count();
+    (line, column)
+}
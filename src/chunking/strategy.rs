@@ -0,0 +1,349 @@
+//! The chunking strategies a project, or a single upload, can choose from.
+//!
+//! Every strategy works within a token budget and returns exact byte ranges on character boundaries.
+//! Apart from the sliding window, whose windows overlap on purpose, the chunks cover the text end to end.
+//! A piece of text that is over budget on its own (a sentence, a paragraph, a section) is split further
+//! with the next finer strategy instead of being cut at an arbitrary byte.
+
+use std::ops::Range;
+use serde::{Deserialize, Serialize};
+use super::code::{self, CodeSpan, Language};
+use super::token_aware::{self, push_checked, split_long, words, TokenChunk};
+use super::tokens::TokenCounter;
+
+pub const DEFAULT_MAX_TOKENS: usize = token_aware::MAX_TOKENS;
+/// OpenAI rejects inputs longer than this.
+pub const MAX_INPUT_TOKENS: usize = 8191;
+
+fn default_max_tokens() -> usize {
+    DEFAULT_MAX_TOKENS
+}
+
+fn default_separators() -> Vec<String> {
+    vec![String::from("\n\n"), String::from("\n"), String::from(". "), String::from(" ")]
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum ChunkingStrategy {
+    /// Content-defined chunks of whole words, see `token_aware`.
+    Tokens {
+        #[serde(default = "default_max_tokens")]
+        max_tokens: usize,
+    },
+    /// Whole sentences, as many as fit.
+    Sentence {
+        #[serde(default = "default_max_tokens")]
+        max_tokens: usize,
+    },
+    /// Whole paragraphs (separated by blank lines), as many as fit.
+    Paragraph {
+        #[serde(default = "default_max_tokens")]
+        max_tokens: usize,
+    },
+    /// Splits on the first separator, and pieces that are still too long on the next one.
+    Recursive {
+        #[serde(default = "default_max_tokens")]
+        max_tokens: usize,
+        #[serde(default = "default_separators")]
+        separators: Vec<String>,
+    },
+    /// One or more chunks per Markdown section; a chunk never spans a heading.
+    Markdown {
+        #[serde(default = "default_max_tokens")]
+        max_tokens: usize,
+    },
+    /// Source files are split per function, class or impl block, see `code`. Other files as with `Tokens`.
+    Code {
+        #[serde(default = "default_max_tokens")]
+        max_tokens: usize,
+    },
+    /// Windows of `window_tokens` where each window repeats the last `overlap_tokens` of the one before.
+    SlidingWindow {
+        #[serde(default = "default_max_tokens")]
+        window_tokens: usize,
+        #[serde(default)]
+        overlap_tokens: usize,
+    },
+}
+
+impl Default for ChunkingStrategy {
+    fn default() -> Self {
+        ChunkingStrategy::Tokens { max_tokens: DEFAULT_MAX_TOKENS }
+    }
+}
+
+impl ChunkingStrategy {
+    /// Parses a strategy as stored in the database or sent by a client, e.g.
+    /// `{"strategy": "sliding_window", "window_tokens": 256, "overlap_tokens": 32}`.
+    pub fn parse(json: &str) -> Result<ChunkingStrategy, String> {
+        let strategy: ChunkingStrategy = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        strategy.validate()?;
+        Ok(strategy)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let budget = match self {
+            ChunkingStrategy::Tokens { max_tokens }
+            | ChunkingStrategy::Sentence { max_tokens }
+            | ChunkingStrategy::Paragraph { max_tokens }
+            | ChunkingStrategy::Recursive { max_tokens, .. }
+            | ChunkingStrategy::Markdown { max_tokens }
+            | ChunkingStrategy::Code { max_tokens } => *max_tokens,
+            ChunkingStrategy::SlidingWindow { window_tokens, .. } => *window_tokens,
+        };
+        if !(1..=MAX_INPUT_TOKENS).contains(&budget) {
+            return Err(format!("token budget must be between 1 and {}", MAX_INPUT_TOKENS));
+        }
+        match self {
+            ChunkingStrategy::SlidingWindow { window_tokens, overlap_tokens } if overlap_tokens >= window_tokens => {
+                Err(String::from("overlap_tokens must be smaller than window_tokens"))
+            },
+            ChunkingStrategy::Recursive { separators, .. } if separators.iter().any(|separator| separator.is_empty()) => {
+                Err(String::from("separators can't be empty"))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("chunking strategy serializes")
+    }
+
+    /// Chunks the contents of the file called `name`. Only the `code` strategy looks at the name: files in a
+    /// language it can parse come back with the symbol and lines of every chunk.
+    pub fn chunk_file(&self, name: &str, text: &str, counter: &dyn TokenCounter) -> Vec<(TokenChunk, Option<CodeSpan>)> {
+        if let ChunkingStrategy::Code { max_tokens } = self {
+            let chunks = Language::from_path(name).and_then(|language| code::chunk(text, language, counter, *max_tokens));
+            if let Some(chunks) = chunks {
+                return chunks.into_iter().map(|(chunk, span)| (chunk, Some(span))).collect();
+            }
+        }
+        self.chunk(text, counter).into_iter().map(|chunk| (chunk, None)).collect()
+    }
+
+    pub fn chunk(&self, text: &str, counter: &dyn TokenCounter) -> Vec<TokenChunk> {
+        let all = 0..text.len();
+        match self {
+            ChunkingStrategy::Tokens { max_tokens } | ChunkingStrategy::Code { max_tokens } => {
+                token_aware::chunk_with_budget(text, counter, max_tokens / 4, max_tokens / 2, *max_tokens)
+            },
+            ChunkingStrategy::Sentence { max_tokens } => by_sentence(text, all, counter, *max_tokens),
+            ChunkingStrategy::Paragraph { max_tokens } => by_paragraph(text, all, counter, *max_tokens),
+            ChunkingStrategy::Recursive { max_tokens, separators } => {
+                let mut chunks = Vec::new();
+                recursive(text, all, separators, counter, *max_tokens, &mut chunks);
+                chunks
+            },
+            ChunkingStrategy::Markdown { max_tokens } => {
+                markdown_sections(text).into_iter()
+                    .flat_map(|section| by_paragraph(text, section, counter, *max_tokens))
+                    .collect()
+            },
+            ChunkingStrategy::SlidingWindow { window_tokens, overlap_tokens } => {
+                sliding_window(text, counter, *window_tokens, *overlap_tokens)
+            },
+        }
+    }
+}
+
+/// Greedily merges consecutive `units` into chunks of up to `max_tokens`. A unit that is over budget
+/// on its own is handed to `split`.
+fn pack(text: &str, units: Vec<Range<usize>>, counter: &dyn TokenCounter, max_tokens: usize, split: &dyn Fn(Range<usize>, &mut Vec<TokenChunk>)) -> Vec<TokenChunk> {
+    let mut chunks = Vec::new();
+    let mut current: Option<Range<usize>> = None;
+    let mut tokens = 0;
+    for unit in units {
+        let unit_tokens = counter.count(&text[unit.clone()]);
+        if unit_tokens > max_tokens || (current.is_some() && tokens + unit_tokens > max_tokens) {
+            if let Some(range) = current.take() {
+                push_checked(&mut chunks, text, counter, range, max_tokens);
+            }
+            tokens = 0;
+        }
+        if unit_tokens > max_tokens {
+            split(unit, &mut chunks);
+            continue;
+        }
+        current = Some(current.map_or(unit.clone(), |range| range.start..unit.end));
+        tokens += unit_tokens;
+    }
+    if let Some(range) = current {
+        push_checked(&mut chunks, text, counter, range, max_tokens);
+    }
+    chunks
+}
+
+fn by_words(text: &str, range: Range<usize>, counter: &dyn TokenCounter, max_tokens: usize, chunks: &mut Vec<TokenChunk>) {
+    let units = words(&text[range.clone()]).into_iter()
+        .map(|word| range.start + word.start..range.start + word.end)
+        .collect();
+    let split = |word: Range<usize>, chunks: &mut Vec<TokenChunk>| split_long(chunks, text, counter, word, max_tokens);
+    chunks.extend(pack(text, units, counter, max_tokens, &split));
+}
+
+fn by_sentence(text: &str, range: Range<usize>, counter: &dyn TokenCounter, max_tokens: usize) -> Vec<TokenChunk> {
+    let split = |sentence: Range<usize>, chunks: &mut Vec<TokenChunk>| by_words(text, sentence, counter, max_tokens, chunks);
+    pack(text, sentences(text, range), counter, max_tokens, &split)
+}
+
+fn by_paragraph(text: &str, range: Range<usize>, counter: &dyn TokenCounter, max_tokens: usize) -> Vec<TokenChunk> {
+    let split = |paragraph: Range<usize>, chunks: &mut Vec<TokenChunk>| chunks.extend(by_sentence(text, paragraph, counter, max_tokens));
+    pack(text, paragraphs(text, range), counter, max_tokens, &split)
+}
+
+fn recursive(text: &str, range: Range<usize>, separators: &[String], counter: &dyn TokenCounter, max_tokens: usize, chunks: &mut Vec<TokenChunk>) {
+    let tokens = counter.count(&text[range.clone()]);
+    if tokens <= max_tokens {
+        if !range.is_empty() {
+            chunks.push(TokenChunk { start: range.start, end: range.end, tokens });
+        }
+        return;
+    }
+    let (separator, rest) = match separators.split_first() {
+        Some(first) => first,
+        None => return split_long(chunks, text, counter, range, max_tokens),
+    };
+    let pieces = split_after(text, range.clone(), separator);
+    if pieces.len() < 2 {
+        return recursive(text, range, rest, counter, max_tokens, chunks);
+    }
+    let split = |piece: Range<usize>, chunks: &mut Vec<TokenChunk>| recursive(text, piece, rest, counter, max_tokens, chunks);
+    chunks.extend(pack(text, pieces, counter, max_tokens, &split));
+}
+
+/// Splits `range` after every occurrence of `separator`, which stays with the piece before it.
+fn split_after(text: &str, range: Range<usize>, separator: &str) -> Vec<Range<usize>> {
+    let mut pieces = Vec::new();
+    let mut start = range.start;
+    for (i, matched) in text[range.clone()].match_indices(separator) {
+        let end = range.start + i + matched.len();
+        pieces.push(start..end);
+        start = end;
+    }
+    if start < range.end {
+        pieces.push(start..range.end);
+    }
+    pieces
+}
+
+/// Sentences end after `.`, `!` or `?` followed by whitespace, after CJK full stops, and at line breaks.
+/// The whitespace after the end stays with the sentence.
+fn sentences(text: &str, range: Range<usize>) -> Vec<Range<usize>> {
+    let slice = &text[range.clone()];
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut after_terminal = false;
+    let mut chars = slice.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let ends = c == '\n' || matches!(c, '。' | '！' | '？') || (after_terminal && c.is_whitespace());
+        after_terminal = matches!(c, '.' | '!' | '?');
+        if !ends {
+            continue;
+        }
+        let mut end = i + c.len_utf8();
+        while let Some(&(j, next)) = chars.peek() {
+            if !next.is_whitespace() {
+                break;
+            }
+            end = j + next.len_utf8();
+            chars.next();
+        }
+        sentences.push(range.start + start..range.start + end);
+        start = end;
+        after_terminal = false;
+    }
+    if start < slice.len() {
+        sentences.push(range.start + start..range.end);
+    }
+    sentences
+}
+
+/// Paragraphs end after one or more blank lines.
+fn paragraphs(text: &str, range: Range<usize>) -> Vec<Range<usize>> {
+    let mut paragraphs = Vec::new();
+    let mut start = range.start;
+    let mut position = range.start;
+    let mut in_blank_run = false;
+    for line in text[range.clone()].split_inclusive('\n') {
+        let blank = line.trim().is_empty();
+        if !blank && in_blank_run {
+            paragraphs.push(start..position);
+            start = position;
+        }
+        in_blank_run = blank && position > start;
+        position += line.len();
+    }
+    if start < range.end {
+        paragraphs.push(start..range.end);
+    }
+    paragraphs
+}
+
+/// Sections start at ATX headings (`#` to `######`). Headings inside fenced code blocks don't count.
+fn markdown_sections(text: &str) -> Vec<Range<usize>> {
+    let mut sections = Vec::new();
+    let mut start = 0;
+    let mut position = 0;
+    let mut fence: Option<&str> = None;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        match fence {
+            Some(marker) if trimmed.starts_with(marker) => fence = None,
+            Some(_) => {},
+            None if trimmed.starts_with("```") => fence = Some("```"),
+            None if trimmed.starts_with("~~~") => fence = Some("~~~"),
+            None => {
+                let level = trimmed.chars().take_while(|c| *c == '#').count();
+                let heading = (1..=6).contains(&level) && trimmed[level..].chars().next().is_none_or(char::is_whitespace);
+                if heading && position > start {
+                    sections.push(start..position);
+                    start = position;
+                }
+            },
+        }
+        position += line.len();
+    }
+    if start < text.len() {
+        sections.push(start..text.len());
+    }
+    sections
+}
+
+fn sliding_window(text: &str, counter: &dyn TokenCounter, window_tokens: usize, overlap_tokens: usize) -> Vec<TokenChunk> {
+    let step = window_tokens.saturating_sub(overlap_tokens).max(1);
+    let words = words(text);
+    let word_tokens: Vec<usize> = words.iter().map(|word| counter.count(&text[word.clone()])).collect();
+
+    let mut chunks = Vec::new();
+    let mut first = 0;
+    while first < words.len() {
+        let mut last = first;
+        let mut tokens = 0;
+        while last < words.len() && tokens + word_tokens[last] <= window_tokens {
+            tokens += word_tokens[last];
+            last += 1;
+        }
+        if last == first {
+            // A single word longer than the window
+            split_long(&mut chunks, text, counter, words[first].clone(), window_tokens);
+            first += 1;
+            continue;
+        }
+        push_checked(&mut chunks, text, counter, words[first].start..words[last - 1].end, window_tokens);
+        if last == words.len() {
+            break;
+        }
+        // The next window starts `step` tokens in, so it shares about `overlap_tokens` with this one
+        let mut advanced = 0;
+        let mut next = first;
+        while next < last && advanced < step {
+            advanced += word_tokens[next];
+            next += 1;
+        }
+        first = next.max(first + 1);
+    }
+    // A window that had to be split can end up with a piece identical to the start of the next window
+    chunks.dedup_by(|next, previous| next.start == previous.start && next.end == previous.end);
+    chunks
+}
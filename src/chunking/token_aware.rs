@@ -0,0 +1,132 @@
+//! Chunks text by token count instead of bytes.
+//!
+//! The text is cut into words (a run of whitespace plus the word after it) and words are packed into
+//! chunks of at most `max_tokens`. Chunk ends are content-defined like in `content_defined`: once a chunk
+//! holds `min_tokens`, a word whose hash hits ends it, so an edit only moves the boundaries around it.
+//! Every boundary is a character boundary and every range is an exact byte range of the input.
+
+use std::ops::Range;
+use super::tokens::TokenCounter;
+
+pub const MAX_TOKENS: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenChunk {
+    pub start: usize,
+    pub end: usize,
+    pub tokens: usize,
+}
+
+/// Byte ranges of the words of `text`: leading whitespace plus the non-whitespace run after it. Trailing
+/// whitespace is added to the last word, so the ranges cover `text` exactly.
+pub fn words(text: &str) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut in_word = false;
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() {
+            if in_word {
+                words.push(start..i);
+                start = i;
+                in_word = false;
+            }
+        } else {
+            in_word = true;
+        }
+    }
+    if in_word || words.is_empty() {
+        if start < text.len() {
+            words.push(start..text.len());
+        }
+    } else if let Some(last) = words.last_mut() {
+        last.end = text.len();
+    }
+    words
+}
+
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+pub fn chunk_with_budget(text: &str, counter: &dyn TokenCounter, min_tokens: usize, avg_tokens: usize, max_tokens: usize) -> Vec<TokenChunk> {
+    let max_tokens = max_tokens.max(1);
+    let spread = avg_tokens.saturating_sub(min_tokens).max(1) as u64;
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut tokens = 0;
+
+    let mut pending = words(text).into_iter().peekable();
+    while let Some(word) = pending.next() {
+        let word_tokens = counter.count(&text[word.clone()]);
+        if word_tokens > max_tokens {
+            push_checked(&mut chunks, text, counter, start..word.start, max_tokens);
+            split_long(&mut chunks, text, counter, word.clone(), max_tokens);
+            start = word.end;
+            tokens = 0;
+            continue;
+        }
+        if tokens + word_tokens > max_tokens {
+            push_checked(&mut chunks, text, counter, start..word.start, max_tokens);
+            start = word.start;
+            tokens = 0;
+        }
+        tokens += word_tokens;
+
+        // Each token of the word has a 1 in `spread` chance of ending the chunk here
+        let at_boundary = fnv1a(text[word.clone()].as_bytes()) % spread < word_tokens as u64;
+        if tokens >= min_tokens && at_boundary && pending.peek().is_some() {
+            push_checked(&mut chunks, text, counter, start..word.end, max_tokens);
+            start = word.end;
+            tokens = 0;
+        }
+    }
+    push_checked(&mut chunks, text, counter, start..text.len(), max_tokens);
+    chunks
+}
+
+/// Word counts don't always add up exactly: a few tokens span a word boundary (punctuation followed
+/// by newlines, for instance). The chunk is counted as a whole and gives up words until it fits.
+pub(crate) fn push_checked(chunks: &mut Vec<TokenChunk>, text: &str, counter: &dyn TokenCounter, range: Range<usize>, max_tokens: usize) {
+    if range.is_empty() {
+        return;
+    }
+    let tokens = counter.count(&text[range.clone()]);
+    if tokens <= max_tokens {
+        chunks.push(TokenChunk { start: range.start, end: range.end, tokens });
+        return;
+    }
+    let words = words(&text[range.clone()]);
+    if words.len() < 2 {
+        split_long(chunks, text, counter, range, max_tokens);
+        return;
+    }
+    let split = range.start + words[words.len() / 2].start;
+    push_checked(chunks, text, counter, range.start..split, max_tokens);
+    push_checked(chunks, text, counter, split..range.end, max_tokens);
+}
+
+/// Cuts a single word that is over budget on its own (a long URL, base64, minified code) into the
+/// longest pieces that fit, always at character boundaries.
+pub(crate) fn split_long(chunks: &mut Vec<TokenChunk>, text: &str, counter: &dyn TokenCounter, range: Range<usize>, max_tokens: usize) {
+    let mut start = range.start;
+    while start < range.end {
+        let boundaries: Vec<usize> = text[start..range.end].char_indices()
+            .map(|(i, _)| start + i)
+            .skip(1)
+            .chain(std::iter::once(range.end))
+            .collect();
+        // Binary search for the longest prefix within budget; one character always goes
+        let (mut low, mut high) = (0, boundaries.len() - 1);
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+            if counter.count(&text[start..boundaries[mid]]) <= max_tokens {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        let end = boundaries[low];
+        chunks.push(TokenChunk { start, end, tokens: counter.count(&text[start..end]) });
+        start = end;
+    }
+}
@@ -0,0 +1,25 @@
+use std::sync::OnceLock;
+use tiktoken_rs::CoreBPE;
+
+pub trait TokenCounter {
+    fn count(&self, text: &str) -> usize;
+}
+
+/// Counts tokens the way OpenAI's `cl100k_base` encoding does (ada-002 and the text-embedding-3 models).
+/// Other providers use their own tokenizers, for which this is a close estimate.
+pub struct Cl100k;
+
+fn cl100k() -> &'static CoreBPE {
+    static BPE: OnceLock<CoreBPE> = OnceLock::new();
+    BPE.get_or_init(|| tiktoken_rs::cl100k_base().expect("cl100k_base encoding is bundled"))
+}
+
+impl TokenCounter for Cl100k {
+    fn count(&self, text: &str) -> usize {
+        cl100k().encode_ordinary(text).len()
+    }
+}
+
+pub fn count_tokens(text: &str) -> usize {
+    Cl100k.count(text)
+}
@@ -0,0 +1,62 @@
+use actix_web::{web, HttpResponse};
+use sqlx::{SqlitePool};
+use crate::memory_management::project_manager::ProjectManager;
+use crate::memory_management::consistency_checker;
+use crate::providers::cache;
+use std::sync::{Arc, Mutex};
+
+async fn run_fsck(project_manager: web::Data<Arc<Mutex<ProjectManager>>>, db_pool: web::Data<SqlitePool>, repair: bool) -> HttpResponse {
+    match consistency_checker::run(&db_pool, Some(&project_manager), repair).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+}
+
+pub async fn fsck(project_manager: web::Data<Arc<Mutex<ProjectManager>>>, db_pool: web::Data<SqlitePool>) -> HttpResponse {
+    run_fsck(project_manager, db_pool, false).await
+}
+
+pub async fn fsck_repair(project_manager: web::Data<Arc<Mutex<ProjectManager>>>, db_pool: web::Data<SqlitePool>) -> HttpResponse {
+    run_fsck(project_manager, db_pool, true).await
+}
+
+pub async fn embedding_cache_stats(db_pool: web::Data<SqlitePool>) -> HttpResponse {
+    match cache::stats(&db_pool).await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+}
+
+pub async fn clear_embedding_cache(db_pool: web::Data<SqlitePool>) -> HttpResponse {
+    match cache::clear(&db_pool).await {
+        Ok(removed) => HttpResponse::Ok().json(serde_json::json!({ "removed": removed })),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/admin/fsck")
+            .route(web::get().to(fsck))
+    );
+
+    cfg.service(
+        web::resource("/admin/fsck/repair")
+            .route(web::post().to(fsck_repair))
+    );
+
+    cfg.service(
+        web::resource("/admin/embedding-cache")
+            .route(web::get().to(embedding_cache_stats))
+            .route(web::delete().to(clear_embedding_cache))
+    );
+}
@@ -0,0 +1,244 @@
+use actix_web::{web, HttpResponse};
+use sqlx::{SqlitePool};
+use serde::{Deserialize, Serialize};
+use crate::models::document::{DocumentUpdate, NewDocument};
+use crate::memory_management::project_manager::ProjectManager;
+use crate::handlers::project_handler::{delete_file, forbidden, project_unavailable};
+use crate::jobs::JobQueue;
+use crate::parsing::rows::{self, RowFormat};
+use crate::utils::documents::{self, DocumentError, RowSelection, TextDocument};
+use crate::utils::file_versions::{StoreStatus, StoredFile};
+use crate::chunking::strategy::ChunkingStrategy;
+use std::sync::{Arc, Mutex};
+
+/// Row imports may be far larger than JSON bodies.
+const MAX_IMPORT_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Deserialize, Debug)]
+pub struct RowImportQuery {
+    /// `csv` or `jsonl`; told apart by the content when missing.
+    format: Option<String>,
+    id_column: Option<String>,
+    /// Comma separated column names.
+    text_columns: Option<String>,
+    metadata_columns: Option<String>,
+    #[serde(default)]
+    embed: bool,
+}
+
+fn column_list(columns: &Option<String>) -> Vec<String> {
+    columns.as_deref()
+        .unwrap_or("")
+        .split(',')
+        .map(|column| column.trim().to_string())
+        .filter(|column| !column.is_empty())
+        .collect()
+}
+
+/// Imports a CSV (with a header line) or JSON Lines body, storing each row as a document whose external id
+/// comes from `id_column` (`id` by default). Re-importing rows with the same ids updates them in place.
+pub async fn import_rows(
+    project_manager: web::Data<Arc<Mutex<ProjectManager>>>,
+    db_pool: web::Data<SqlitePool>,
+    job_queue: web::Data<JobQueue>,
+    project_id: web::Path<i64>,
+    query: web::Query<RowImportQuery>,
+    body: web::Bytes,
+    user_id: Option<web::ReqData<i64>>,
+) -> HttpResponse {
+    if let Some(response) = project_unavailable(&project_manager.lock().unwrap(), *project_id) {
+        return response;
+    }
+    if let Some(response) = forbidden(&db_pool, *project_id, user_id.as_deref().copied()).await {
+        return response;
+    }
+
+    let format = match &query.format {
+        Some(format) => match RowFormat::parse(format) {
+            Some(format) => format,
+            None => return HttpResponse::BadRequest().body(format!("Unknown format {}", format)),
+        },
+        None => RowFormat::detect(&body),
+    };
+    let rows = match rows::read(format, &body) {
+        Ok(rows) => rows,
+        Err(e) => return HttpResponse::UnprocessableEntity().body(format!("Could not read rows: {}", e)),
+    };
+    let selection = RowSelection {
+        id_column: query.id_column.clone().unwrap_or_else(|| String::from("id")),
+        text_columns: column_list(&query.text_columns),
+        metadata_columns: column_list(&query.metadata_columns),
+    };
+    let selection = match selection.resolve(&rows.columns) {
+        Ok(selection) => selection,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    let mut import = match documents::import_rows(&project_manager, &db_pool, *project_id, rows, &selection).await {
+        Ok(import) => import,
+        Err(e) => {
+            eprintln!("Failed to import rows: {:?}", e); // Log the error
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    };
+    if query.embed {
+        let user_id = user_id.map(|user_id| user_id.into_inner());
+        for row in import.rows.iter().filter(|row| row.stored.status != StoreStatus::Unchanged) {
+            match job_queue.enqueue(*project_id, row.stored.id, false, user_id).await {
+                Ok(job_id) => import.job_ids.push(job_id),
+                Err(e) => {
+                    eprintln!("Failed to queue embedding of file {}: {}", row.stored.id, e);
+                    return HttpResponse::InternalServerError().body("Something went wrong")
+                }
+            }
+        }
+    }
+    HttpResponse::Ok().json(import)
+}
+
+#[derive(Serialize, Debug)]
+pub struct StoredDocument {
+    #[serde(flatten)]
+    stored: StoredFile,
+    /// The embedding job of the new version, when `embed` was set and the text changed.
+    job_id: Option<i64>,
+}
+
+/// Stores a document sent as JSON like an upload of a text file. Returns the response to send instead when it
+/// can't be stored.
+async fn store_document(
+    project_manager: &Arc<Mutex<ProjectManager>>,
+    db_pool: &SqlitePool,
+    project_id: i64,
+    document: &TextDocument,
+    chunking: Option<&ChunkingStrategy>,
+) -> Result<StoredFile, HttpResponse> {
+    if let Some(Err(e)) = chunking.map(|chunking| chunking.validate()) {
+        return Err(HttpResponse::BadRequest().body(format!("Invalid chunking: {}", e)));
+    }
+    match documents::upsert(project_manager, db_pool, project_id, document, chunking).await {
+        Ok(stored) => Ok(stored),
+        Err(DocumentError::NameTaken(name)) => Err(HttpResponse::Conflict().body(format!("{} is the name of another file", name))),
+        Err(DocumentError::Failed(e)) => {
+            eprintln!("Failed to store document: {}", e); // Log the error
+            Err(HttpResponse::InternalServerError().body("Something went wrong"))
+        }
+    }
+}
+
+async fn stored_response(job_queue: &JobQueue, project_id: i64, stored: StoredFile, embed: bool, user_id: Option<web::ReqData<i64>>) -> HttpResponse {
+    let mut job_id = None;
+    if embed && stored.status != StoreStatus::Unchanged {
+        let user_id = user_id.map(|user_id| user_id.into_inner());
+        match job_queue.enqueue(project_id, stored.id, false, user_id).await {
+            Ok(id) => job_id = Some(id),
+            Err(e) => {
+                eprintln!("Failed to queue embedding of file {}: {}", stored.id, e);
+                return HttpResponse::InternalServerError().body("Something went wrong")
+            }
+        }
+    }
+    HttpResponse::Ok().json(StoredDocument { stored, job_id })
+}
+
+/// Creates a text document. Answers `409` when the project already has a file of that name or a document with
+/// that external id; those are replaced with `PUT`.
+pub async fn create_document(
+    project_manager: web::Data<Arc<Mutex<ProjectManager>>>,
+    db_pool: web::Data<SqlitePool>,
+    job_queue: web::Data<JobQueue>,
+    project_id: web::Path<i64>,
+    new_document: web::Json<NewDocument>,
+    user_id: Option<web::ReqData<i64>>,
+) -> HttpResponse {
+    if let Some(response) = project_unavailable(&project_manager.lock().unwrap(), *project_id) {
+        return response;
+    }
+    if let Some(response) = forbidden(&db_pool, *project_id, user_id.as_deref().copied()).await {
+        return response;
+    }
+    let new_document = new_document.into_inner();
+    let name = match new_document.name.as_ref().or(new_document.external_id.as_ref()) {
+        Some(name) if !name.trim().is_empty() => name.trim().to_string(),
+        _ => return HttpResponse::BadRequest().body("A document needs a name or an external_id"),
+    };
+
+    let existing: Result<Option<i64>, sqlx::Error> = sqlx::query_scalar("SELECT id FROM file_entry WHERE project_id = ? AND (name = ? OR external_id = ?)")
+        .bind(*project_id)
+        .bind(&name)
+        .bind(&new_document.external_id)
+        .fetch_optional(db_pool.get_ref())
+        .await;
+    match existing {
+        Ok(None) => {},
+        Ok(Some(file_id)) => return HttpResponse::Conflict().body(format!("Document already exists as file {}", file_id)),
+        Err(e) => {
+            eprintln!("Database error: {}", e); // Log the error
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+
+    let document = TextDocument { name, external_id: new_document.external_id, text: new_document.text, metadata: new_document.metadata };
+    match store_document(&project_manager, &db_pool, *project_id, &document, new_document.chunking.as_ref()).await {
+        Ok(stored) => stored_response(&job_queue, *project_id, stored, new_document.embed, user_id).await,
+        Err(response) => response,
+    }
+}
+
+/// Replaces the text of a document, or of any file of the project, with a new version.
+pub async fn replace_document(
+    project_manager: web::Data<Arc<Mutex<ProjectManager>>>,
+    db_pool: web::Data<SqlitePool>,
+    job_queue: web::Data<JobQueue>,
+    path: web::Path<(i64, i64)>,
+    update: web::Json<DocumentUpdate>,
+    user_id: Option<web::ReqData<i64>>,
+) -> HttpResponse {
+    let (project_id, file_id) = path.into_inner();
+    if let Some(response) = project_unavailable(&project_manager.lock().unwrap(), project_id) {
+        return response;
+    }
+    if let Some(response) = forbidden(&db_pool, project_id, user_id.as_deref().copied()).await {
+        return response;
+    }
+    let file: Result<Option<(String, Option<String>)>, sqlx::Error> = sqlx::query_as("SELECT name, external_id FROM file_entry WHERE id = ? AND project_id = ?")
+        .bind(file_id)
+        .bind(project_id)
+        .fetch_optional(db_pool.get_ref())
+        .await;
+    let (name, external_id) = match file {
+        Ok(Some(file)) => file,
+        Ok(None) => return HttpResponse::NotFound().body("File not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e); // Log the error
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    };
+
+    let update = update.into_inner();
+    let document = TextDocument { name, external_id, text: update.text, metadata: update.metadata };
+    match store_document(&project_manager, &db_pool, project_id, &document, update.chunking.as_ref()).await {
+        Ok(stored) => stored_response(&job_queue, project_id, stored, update.embed, user_id).await,
+        Err(response) => response,
+    }
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/projects/{id}/rows")
+            .app_data(web::PayloadConfig::new(MAX_IMPORT_BYTES))
+            .route(web::post().to(import_rows))
+    );
+
+    cfg.service(
+        web::resource("/projects/{id}/documents")
+            .route(web::post().to(create_document))
+    );
+
+    // Documents are deleted like any other file
+    cfg.service(
+        web::resource("/projects/{id}/documents/{file_id}")
+            .route(web::put().to(replace_document))
+            .route(web::delete().to(delete_file))
+    );
+}
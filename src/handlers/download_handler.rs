@@ -0,0 +1,196 @@
+use actix_files::{HttpRange, NamedFile};
+use actix_web::http::header::{self, ContentDisposition};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use std::fs;
+use crate::utils::hash::sha256_hex;
+use crate::handlers::project_handler::forbidden;
+
+#[derive(Deserialize, Debug)]
+pub struct DownloadQuery {
+    /// The current version when missing.
+    pub version: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChunkQuery {
+    pub version: Option<i64>,
+    pub start_byte: i64,
+    pub end_byte: i64,
+}
+
+struct StoredVersion {
+    name: String,
+    version: i64,
+    path: String,
+    text_path: Option<String>,
+}
+
+async fn find_version(db_pool: &SqlitePool, project_id: i64, file_id: i64, version: Option<i64>) -> Result<Option<StoredVersion>, sqlx::Error> {
+    let row: Option<(String, i64, String, Option<String>)> = sqlx::query_as(
+        r#"
+        SELECT file_entry.name, file_version.version, file_version.path, file_version.text_path
+        FROM file_entry
+        JOIN file_version ON file_version.file_id = file_entry.id
+        WHERE file_entry.id = ? AND file_entry.project_id = ? AND file_version.version = COALESCE(?, file_entry.current_version)
+        "#,
+    )
+    .bind(file_id)
+    .bind(project_id)
+    .bind(version)
+    .fetch_optional(db_pool)
+    .await?;
+    Ok(row.map(|(name, version, path, text_path)| StoredVersion { name, version, path, text_path }))
+}
+
+/// File names may hold the directories of an archive upload; downloads are saved under the last part.
+fn download_name(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+/// Serves a stored version of a file as uploaded, the current one unless `version` is given. The content type
+/// follows the file's extension, and `ETag`, `If-None-Match` and `Range` requests are handled like static files.
+pub async fn download_file(
+    req: HttpRequest,
+    db_pool: web::Data<SqlitePool>,
+    path: web::Path<(i64, i64)>,
+    query: web::Query<DownloadQuery>,
+    user_id: Option<web::ReqData<i64>>,
+) -> HttpResponse {
+    let (project_id, file_id) = path.into_inner();
+    if let Some(response) = forbidden(&db_pool, project_id, user_id.as_deref().copied()).await {
+        return response;
+    }
+    let stored = match find_version(&db_pool, project_id, file_id, query.version).await {
+        Ok(Some(stored)) => stored,
+        Ok(None) => return HttpResponse::NotFound().body("File not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e); // Log the error
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    };
+
+    match NamedFile::open_async(&stored.path).await {
+        Ok(file) => file
+            .set_content_disposition(ContentDisposition::attachment(download_name(&stored.name)))
+            .into_response(&req),
+        Err(e) => {
+            eprintln!("couldn't open {}: {}", stored.path, e);
+            HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+}
+
+/// Serves the text of one chunk of a file version, named by its byte range. Ranges refer to the extracted text
+/// for parsed documents, as in search results.
+pub async fn download_chunk(
+    req: HttpRequest,
+    db_pool: web::Data<SqlitePool>,
+    path: web::Path<(i64, i64)>,
+    query: web::Query<ChunkQuery>,
+    user_id: Option<web::ReqData<i64>>,
+) -> HttpResponse {
+    let (project_id, file_id) = path.into_inner();
+    if let Some(response) = forbidden(&db_pool, project_id, user_id.as_deref().copied()).await {
+        return response;
+    }
+    let stored = match find_version(&db_pool, project_id, file_id, query.version).await {
+        Ok(Some(stored)) => stored,
+        Ok(None) => return HttpResponse::NotFound().body("File not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e); // Log the error
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    };
+
+    // Chunks are known from their embeddings, or from the chunk list while they are still being embedded
+    let chunk: Result<Option<i64>, sqlx::Error> = sqlx::query_scalar(
+        r#"
+        SELECT 1 FROM file_chunk WHERE file_id = ? AND version = ? AND start_byte = ? AND end_byte = ?
+        UNION
+        SELECT 1 FROM file_embedding WHERE file_id = ? AND version = ? AND start_byte = ? AND end_byte = ?
+        "#,
+    )
+    .bind(file_id)
+    .bind(stored.version)
+    .bind(query.start_byte)
+    .bind(query.end_byte)
+    .bind(file_id)
+    .bind(stored.version)
+    .bind(query.start_byte)
+    .bind(query.end_byte)
+    .fetch_optional(db_pool.get_ref())
+    .await;
+    match chunk {
+        Ok(Some(_)) => {},
+        Ok(None) => return HttpResponse::NotFound().body("Chunk not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e); // Log the error
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+
+    let text_path = stored.text_path.unwrap_or(stored.path);
+    let contents = match fs::read(&text_path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("couldn't open {}: {}", text_path, e);
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    };
+    let start = (query.start_byte.max(0) as usize).min(contents.len());
+    let end = (query.end_byte.max(0) as usize).clamp(start, contents.len());
+    let name = format!("{}-{}-{}.txt", download_name(&stored.name), query.start_byte, query.end_byte);
+    ranged_response(&req, contents[start..end].to_vec(), &name)
+}
+
+/// Answers a request for `body` with a strong ETag taken from its hash, honouring `If-None-Match` and a single
+/// byte `Range` (checked against `If-Range`). Requests for several ranges get the whole body.
+fn ranged_response(req: &HttpRequest, body: Vec<u8>, name: &str) -> HttpResponse {
+    let etag = format!("\"{}\"", sha256_hex(&body));
+    let header_value = |name: header::HeaderName| req.headers().get(name).and_then(|value| value.to_str().ok());
+
+    let not_modified = header_value(header::IF_NONE_MATCH).is_some_and(|tags| {
+        tags.split(',').map(|tag| tag.trim()).any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+    });
+    if not_modified {
+        return HttpResponse::NotModified().insert_header((header::ETAG, etag)).finish();
+    }
+
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type("text/plain; charset=utf-8")
+        .insert_header((header::ETAG, etag.clone()))
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header(ContentDisposition::attachment(name));
+
+    let range = header_value(header::RANGE).filter(|_| header_value(header::IF_RANGE).is_none_or(|tag| tag == etag));
+    let size = body.len() as u64;
+    match range.map(|range| HttpRange::parse(range, size)) {
+        Some(Ok(ranges)) if ranges.len() == 1 => {
+            let (start, end) = (ranges[0].start, ranges[0].start + ranges[0].length);
+            response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .insert_header((header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end - 1, size)))
+                .body(body[start as usize..end as usize].to_vec())
+        },
+        Some(Err(_)) => HttpResponse::RangeNotSatisfiable()
+            .insert_header((header::CONTENT_RANGE, format!("bytes */{}", size)))
+            .finish(),
+        _ => response.body(body),
+    }
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/projects/{id}/files/{file_id}/content")
+            .route(web::get().to(download_file))
+    );
+
+    cfg.service(
+        web::resource("/projects/{id}/files/{file_id}/chunk")
+            .route(web::get().to(download_chunk))
+    );
+}
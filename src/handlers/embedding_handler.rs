@@ -1,218 +1,595 @@
 use actix_web::{web, Error, HttpResponse};
 use sqlx::{SqlitePool};
-use sqlx::Acquire;
-use reqwest::{self, header::{HeaderMap, HeaderValue, CONTENT_TYPE, AUTHORIZATION}};
+use sqlx::types::Json;
+use sqlx::{Acquire, Sqlite, Transaction};
 use serde::{Deserialize, Serialize};
 use crate::models::file::File;
-use serde_json::json;
+use serde_json::{json, Map, Value};
 use tokio;
 use std::fs;
 use std::io::SeekFrom;
 use std::io::prelude::*;
-use futures::future::join_all;
+use futures::StreamExt;
 use crate::models::embedding_entry::EmbeddingEntry;
-use crate::memory_management::project_manager::ProjectManager;
+use crate::memory_management::project_manager::{ProjectManager, LoadStatus, search_file_version, search_filtered};
+use crate::memory_management::project_store::{cosine_similarity, Embedding};
+use crate::chunking::{self, content_defined, Location};
+use crate::chunking::strategy::ChunkingStrategy;
+use crate::chunking::code::CodeSpan;
+use crate::chunking::tokens::Cl100k;
+use crate::utils::hash::sha256_hex;
+use crate::utils::{file_store, file_versions};
+use crate::parsing::{self, DocumentFormat};
+use std::collections::{HashMap, HashSet};
+use crate::handlers::project_handler::{forbidden, project_unavailable};
+use crate::providers::scheduler;
+use crate::jobs::JobHandle;
+use crate::providers::{estimate_tokens, pack_batches, provider_for_project, ProviderError};
+use crate::providers::usage::{self, QuotaExceeded, UsageKind};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct Embedding {
-    embedding: Vec<f64>,
-    index: i32,
-    object: String,
+pub struct similiar_text_request {
+    text: String,
+    file_id: Option<i64>,
+    version: Option<i64>,
+    /// Only searches files whose metadata has these values, e.g. `{"category": "billing"}`.
+    filter: Option<Map<String, Value>>,
+    /// Adds the matching chunk's vector to the result.
+    #[serde(default)]
+    include_embedding: bool
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Usage {
-    prompt_tokens: i32,
-    total_tokens: i32,
+/// A search result: the matching chunk's text and where it sits in its file. `score` is the cosine similarity
+/// to the query; `symbol` is set for chunks from the code chunker.
+#[derive(Serialize, Debug)]
+pub struct SearchHit {
+    file_id: i64,
+    file_name: String,
+    version: i64,
+    start_byte: i64,
+    end_byte: i64,
+    #[serde(flatten)]
+    location: Location,
+    text: String,
+    score: f64,
+    symbol: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embedding: Option<Vec<f64>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Response {
-    data: Vec<Embedding>,
-    model: String,
-    object: String,
-    usage: Usage,
-    
-}
+/// Reads the text of the chunk back from the version it was embedded from, the extracted text for documents.
+async fn search_hit(db_pool: &SqlitePool, embedding: Embedding, score: f64, include_embedding: bool) -> Result<Option<SearchHit>, String> {
+    let row: Option<(String, String, Option<String>)> = sqlx::query_as(
+        r#"
+            SELECT file_entry.name, COALESCE(file_version.text_path, file_version.path, file_entry.path), file_embedding.symbol
+            FROM file_embedding
+            JOIN file_entry ON file_entry.id = file_embedding.file_id
+            LEFT JOIN file_version ON file_version.file_id = file_embedding.file_id AND file_version.version = file_embedding.version
+            WHERE file_embedding.file_id = ? AND file_embedding.version = ? AND file_embedding.start_byte = ? AND file_embedding.end_byte = ?
+        "#,
+    )
+    .bind(embedding.file_id)
+    .bind(embedding.version)
+    .bind(embedding.start_byte)
+    .bind(embedding.end_byte)
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let (file_name, path, symbol) = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Request {
-    input: String,
-    model: String,
+    let contents = fs::read(&path).map_err(|e| format!("{}: {}", path, e))?;
+    let start = (embedding.start_byte.max(0) as usize).min(contents.len());
+    let end = (embedding.end_byte.max(0) as usize).clamp(start, contents.len());
+    Ok(Some(SearchHit {
+        file_id: embedding.file_id,
+        file_name,
+        version: embedding.version,
+        start_byte: embedding.start_byte,
+        end_byte: embedding.end_byte,
+        location: chunking::locate(&contents, start, end),
+        text: String::from_utf8_lossy(&contents[start..end]).to_string(),
+        score,
+        symbol,
+        embedding: if include_embedding { Some(embedding.embedding) } else { None },
+    }))
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct similiar_text_request {
-    text: String
+/// Provider outages and rate limits that outlasted the retries are reported as 503 with a `Retry-After`,
+/// so clients back off too.
+fn provider_error_response(provider: &str, e: ProviderError) -> HttpResponse {
+    eprintln!("{} error: {}", provider, e);
+    match e {
+        ProviderError::RateLimited { .. } | ProviderError::Unavailable { .. } | ProviderError::CircuitOpen { .. } | ProviderError::Request(_) => {
+            let retry_after = e.retry_after().map_or(30, |retry_after| retry_after.as_secs().max(1));
+            HttpResponse::ServiceUnavailable()
+                .insert_header(("Retry-After", retry_after.to_string()))
+                .body("Embedding provider unavailable")
+        },
+        _ => HttpResponse::InternalServerError().body("Something went wrong"),
+    }
 }
 
-pub async fn get_embedding(input_string: String) -> Result<Response, reqwest::Error> {
-    let api_key = std::env::var("OPENAI_API_TOKEN").expect("OPENAI_API_TOKEN must be set.");
-    let data = Request {
-        input: input_string,
-        model: String::from("text-embedding-ada-002"),
-    };
+struct ChunkRow {
+    start_byte: i64,
+    end_byte: i64,
+    chunk_hash: String,
+    span: Option<CodeSpan>,
+    embedding: Vec<f64>,
+}
 
-    let client = reqwest::Client::new();
-    let mut res = client.post("https://api.openai.com/v1/embeddings")
-        .header(AUTHORIZATION, format!("Bearer {}", api_key))
-        .json(&data)
-        .send()
+async fn insert_chunk_rows(transaction: &mut Transaction<'_, Sqlite>, file_id: i64, version: i64, rows: &[ChunkRow]) -> Result<(), sqlx::Error> {
+    for row in rows {
+        let data = serde_json::to_vec(&row.embedding)
+            .map_err(|_| sqlx::Error::Protocol("Failed to serialize embedding data".into()))?;
+        sqlx::query(
+            r#"
+                INSERT INTO file_embedding (file_id, version, start_byte, end_byte, chunk_hash, symbol, start_line, end_line, embedding)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+        )
+        .bind(file_id)
+        .bind(version)
+        .bind(row.start_byte)
+        .bind(row.end_byte)
+        .bind(&row.chunk_hash)
+        .bind(row.span.as_ref().and_then(|span| span.symbol.clone()))
+        .bind(row.span.as_ref().map(|span| span.start_line as i64))
+        .bind(row.span.as_ref().map(|span| span.end_line as i64))
+        .bind(&data)
+        .execute(&mut *transaction)
         .await?;
-        
-    if !res.status().is_success() {
-        let server_error: Result<Response, _> = res.json().await;
-        return server_error;
     }
-    
-    let response_body: Result<Response, _> = res.json().await;
-    response_body
+    Ok(())
 }
 
-//pub async fn run_embeddings_and_store(db_pool: web::Data<SqlitePool>, input_string: String, )
-
-fn read_bytes_range(mut file: &std::fs::File, start: u64, end: u64) -> Vec<u8> {
-    let mut buffer = vec![0; (end - start) as usize]; // Create a buffer to hold the bytes
+/// Sets the state of the chunks of a file version that have the given hashes.
+async fn mark_chunks(transaction: &mut Transaction<'_, Sqlite>, file_id: i64, version: i64, chunk_hashes: &[&str], status: &str, error: Option<&str>) -> Result<(), sqlx::Error> {
+    for chunk_hash in chunk_hashes {
+        sqlx::query("UPDATE file_chunk SET status = ?, error = ? WHERE file_id = ? AND version = ? AND chunk_hash = ?")
+            .bind(status)
+            .bind(error)
+            .bind(file_id)
+            .bind(version)
+            .bind(chunk_hash)
+            .execute(&mut *transaction)
+            .await?;
+    }
+    Ok(())
+}
 
-    file.seek(std::io::SeekFrom::Start(start));
-    file.read_exact(&mut buffer); // Read the specified range of bytes into the buffer
+/// Fails with `RowNotFound` once the file has been deleted, so a run that overlaps a delete doesn't write rows for
+/// a file that no longer exists.
+async fn check_file_exists(transaction: &mut Transaction<'_, Sqlite>, file_id: i64) -> Result<(), sqlx::Error> {
+    let _: i64 = sqlx::query_scalar("SELECT 1 FROM file_entry WHERE id = ?")
+        .bind(file_id)
+        .fetch_one(&mut *transaction)
+        .await?;
+    Ok(())
+}
 
-    buffer
+#[derive(Deserialize, Debug, Default)]
+pub struct EmbedOptions {
+    /// Embed every chunk again, even when the file's content is embedded already.
+    #[serde(default)]
+    pub force: bool,
 }
 
-async fn embed_and_store(db_pool: web::Data<SqlitePool>, project_id: i64, file: &std::fs::File, start: u64, end: u64, file_id: i64) -> Result<(), sqlx::Error> {
-    let bytes = read_bytes_range(&file, start, end);
-    let input_string = String::from_utf8_lossy(&bytes).to_string();
-    match get_embedding(input_string).await {
-        Ok(embedding) => {
-            let embedding_data = embedding.data[0].embedding.clone();
-            let data = match serde_json::to_vec(&embedding_data) {
-                Ok(data) => data,
-                Err(_) => return Err(sqlx::Error::Protocol("Failed to serialize embedding data".into())),
-            };
+#[derive(Debug)]
+pub enum EmbedError {
+    NotFound,
+    ProjectUnavailable(i64),
+    Provider { provider: &'static str, error: ProviderError },
+    QuotaExceeded(QuotaExceeded),
+    Cancelled,
+    Internal(String),
+}
 
-            let mut conn = db_pool.acquire().await.unwrap();
+impl std::fmt::Display for EmbedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EmbedError::NotFound => write!(f, "file not found"),
+            EmbedError::ProjectUnavailable(project_id) => write!(f, "project {} is not loaded", project_id),
+            EmbedError::Provider { provider, error } => write!(f, "{} error: {}", provider, error),
+            EmbedError::QuotaExceeded(quota) => write!(f, "{}", quota),
+            EmbedError::Cancelled => write!(f, "cancelled"),
+            EmbedError::Internal(e) => write!(f, "{}", e),
+        }
+    }
+}
 
-            let mut transaction = conn.begin().await.unwrap(); // Start a new transaction
-        
-            let result = sqlx::query(
-                r#"
-                    INSERT INTO file_embedding (file_id, start_byte, end_byte, embedding) VALUES ($1, $2, $3, $4)
-                "#,
-            )
-            .bind(file_id)
-            .bind(start as i64)
-            .bind(end as i64)
-            .bind(&data)
-            .execute(&mut transaction);
-            
-            match result.await {
-                Ok(_) => {
-                    transaction.commit().await?;
-                    Ok(())
-                },
-                Err(e) => Err(e),
-            }
-            
+fn internal(e: impl std::fmt::Display) -> EmbedError {
+    EmbedError::Internal(e.to_string())
+}
 
-        },
-        Err(e) => {
-            eprintln!("OpenAI error: {}", e); 
-            Err(sqlx::Error::Protocol("Error while getting embedding".into()))
-        }
+/// For the transactions that store a run's results: the file was deleted while it was being embedded.
+fn stored(e: sqlx::Error) -> EmbedError {
+    match e {
+        sqlx::Error::RowNotFound => EmbedError::NotFound,
+        e => internal(e),
     }
 }
 
-pub async fn embed_file(mut project_manager: web::Data<Arc<Mutex<ProjectManager>>>, db_pool: web::Data<SqlitePool>, file_id: web::Path<i64>) -> HttpResponse {
-    let mut conn = db_pool.acquire().await.unwrap();
-    let mut project_manager = project_manager.lock().unwrap();
-    let api_key = std::env::var("OPENAI_API_TOKEN").expect("OPENAI_API_TOKEN must be set.");
-    // Check if there are any embeddings for the file
-    let embeddings_count: (i64,) = sqlx::query_as(
+#[derive(Serialize, Debug)]
+pub struct EmbedReport {
+    pub chunks: usize,
+    pub reused: usize,
+    pub embedded: usize,
+    pub batches: usize,
+    pub chunking: ChunkingStrategy,
+}
+
+/// Chunks the current version of a file, embeds the chunks that have no vector yet and stores them, then swaps
+/// the file's vectors in memory. Returns `None` when that version is embedded already, unless `force` asks for
+/// every chunk to be embedded again. When run as a job, the job's progress is recorded after every batch and the
+/// work stops at the next batch once it is cancelled.
+///
+/// The state of each chunk is kept in `file_chunk`: `embedded` once its vector is stored, `failed` with the
+/// provider's error when its batch was rejected. A later run only sends the chunks that aren't embedded.
+///
+/// The tokens of every batch are recorded against the project and `user_id`, including the batches still in flight
+/// when the run fails or stops. The run doesn't start, and stops between batches, once a monthly quota of either is
+/// used up.
+pub async fn run_embeddings_and_store(project_manager: &Arc<Mutex<ProjectManager>>, db_pool: &SqlitePool, file_id: i64, force: bool, user_id: Option<i64>, job: Option<&JobHandle>) -> Result<Option<EmbedReport>, EmbedError> {
+    let mut conn = db_pool.acquire().await.map_err(internal)?;
+
+    let result: Result<File, sqlx::Error> = sqlx::query_as(
         r#"
-            SELECT COUNT(*) FROM file_embedding WHERE file_id = ?
+            SELECT id, name, path, project_id, content_hash, embedded_hash, current_version FROM file_entry WHERE id = ?
         "#,
     )
-    .bind(file_id.clone())
+    .bind(file_id)
     .fetch_one(&mut conn)
-    .await
-    .unwrap();
-    
-    if embeddings_count.0 > 0 {
-        return HttpResponse::Ok().body("File already embedded");
+    .await;
+    let file = match result {
+        Ok(file) => file,
+        Err(sqlx::Error::RowNotFound) => return Err(EmbedError::NotFound),
+        Err(e) => return Err(internal(e)),
+    };
+    if project_manager.lock().unwrap().get_load_status(file.project_id) != Some(LoadStatus::Ready) {
+        return Err(EmbedError::ProjectUnavailable(file.project_id));
     }
 
-    let result: Result<File, sqlx::Error> = sqlx::query_as(
+    let provider = provider_for_project(db_pool, file.project_id).await
+        .map_err(|e| internal(format!("embedding provider error: {}", e)))?;
+
+    let bytes = std::fs::read(&file.path)
+        .map_err(|e| internal(format!("couldn't open {}: {}", file.path, e)))?;
+    let content_hash = sha256_hex(&bytes);
+    if !force && file.embedded_hash.as_deref() == Some(content_hash.as_str()) {
+        return Ok(None);
+    }
+    if let Some(quota) = usage::check_quota(db_pool, file.project_id, user_id).await.map_err(internal)? {
+        return Err(EmbedError::QuotaExceeded(quota));
+    }
+
+    // A version keeps the strategy it was uploaded or first embedded with, so re-embedding it gives the same chunks
+    let (chunking, format, text_path): (Option<Json<ChunkingStrategy>>, Option<String>, Option<String>) = sqlx::query_as(
         r#"
-            SELECT id, name, path, project_id FROM file_entry WHERE id = ?
+            SELECT COALESCE(file_version.chunking, projects.chunking), file_version.format, file_version.text_path
+            FROM file_entry
+            JOIN projects ON projects.id = file_entry.project_id
+            LEFT JOIN file_version ON file_version.file_id = file_entry.id AND file_version.version = file_entry.current_version
+            WHERE file_entry.id = ?
         "#,
     )
-    .bind(file_id.clone())
+    .bind(file.id)
     .fetch_one(&mut conn)
-    .await;
-    match result {
-        Ok(file) => {
-            let file_path = file.path;
-            let path = std::path::Path::new(&file_path);
-            let display = path.display();
-            match std::fs::File::open(&file_path) {
-                Err(why) => panic!("couldn't open {}: {}", display, why),
-                Ok(mut file_data) => {
-                    let chunk_size: u64 = 1024;
-                    let metadata = match file_data.metadata() {
-                        Err(why) => panic!("couldn't get metadata for {}: {}", display, why),
-                        Ok(metadata) => metadata,
-                    };
-                    let file_size = metadata.len();
-                    let mut start: u64 = 0;
-                    let mut futures = Vec::new();
-                    while start < file_size {
-                        let end = std::cmp::min(start + chunk_size, file_size);
-                        futures.push(embed_and_store(db_pool.clone(), file.project_id, &file_data, start, end, file.id));
-                        start += chunk_size;
-                    }
-                    let results = join_all(futures).await;
-                    for result in results {
-                        match result {
-                            Ok(_) => {
-                                eprintln!("Successfully embedded chunk");
-                            },
-                            Err(e) => {
-                                eprintln!("Database error: {}", e); 
-                                return HttpResponse::InternalServerError().body("Something went wrong")
-                            }
-                        }
-                    }
-                    project_manager.update_embeddings(file.project_id, file_id.into_inner()).await;
-                    return HttpResponse::Ok().body("Successfully embedded file")
-                },
-            }
+    .await
+    .map_err(internal)?;
+    let strategy = chunking.map(|chunking| chunking.0).unwrap_or_default();
+
+    // Documents are chunked by their extracted text. Versions stored before they were parsed on upload are
+    // parsed now.
+    let text_path = match format {
+        Some(_) => text_path,
+        None => {
+            let format = DocumentFormat::detect(&file.name, &bytes);
+            let parsed = parsing::parse(format, &bytes)
+                .map_err(|e| internal(format!("couldn't read {} as {}: {}", file.path, format.as_str(), e)))?;
+            file_versions::save_text(db_pool, file.project_id, file.id, file.current_version, format, parsed.as_ref()).await
+                .map_err(internal)?;
+            parsed.map(|_| file_store::text_path(file.project_id, file.id, file.current_version).to_string_lossy().to_string())
         },
+    };
+    let bytes = match text_path {
+        Some(text_path) => std::fs::read(&text_path)
+            .map_err(|e| internal(format!("couldn't open {}: {}", text_path, e)))?,
+        None => bytes,
+    };
+
+    // Vectors of chunks already embedded for any version of this file are reused as-is, unless forced
+    let existing: Vec<(String, Vec<u8>)> = sqlx::query_as(
+        r#"
+            SELECT chunk_hash, embedding FROM file_embedding WHERE file_id = ? AND chunk_hash IS NOT NULL AND NOT ?
+        "#,
+    )
+    .bind(file.id)
+    .bind(force)
+    .fetch_all(&mut conn)
+    .await
+    .map_err(internal)?;
+    let vectors: HashMap<String, Vec<f64>> = existing.into_iter()
+        .filter_map(|(chunk_hash, blob)| serde_json::from_slice(&blob).ok().map(|embedding| (chunk_hash, embedding)))
+        .collect();
+
+    // Text is chunked by the strategy; files that aren't valid UTF-8 fall back to byte-level content-defined chunks
+    let ranges: Vec<(usize, usize, Option<CodeSpan>)> = match std::str::from_utf8(&bytes) {
+        Ok(text) => strategy.chunk_file(&file.name, text, &Cl100k).into_iter().map(|(chunk, span)| (chunk.start, chunk.end, span)).collect(),
+        Err(_) => content_defined::chunk(&bytes).into_iter().map(|(start, end)| (start, end, None)).collect(),
+    };
+    let chunks: Vec<(usize, usize, String, Option<CodeSpan>)> = ranges.into_iter()
+        .map(|(start, end, span)| (start, end, sha256_hex(&bytes[start..end]), span))
+        .collect();
+    let reused_rows: Vec<ChunkRow> = chunks.iter()
+        .filter_map(|(start, end, chunk_hash, span)| vectors.get(chunk_hash).map(|embedding| ChunkRow {
+            start_byte: *start as i64,
+            end_byte: *end as i64,
+            chunk_hash: chunk_hash.clone(),
+            span: span.clone(),
+            embedding: embedding.clone(),
+        }))
+        .collect();
+    let reused = reused_rows.len();
+
+    // Each distinct new chunk is sent once, however often it occurs in the file
+    let mut seen: HashSet<&str> = HashSet::new();
+    let pending: Vec<(&str, String)> = chunks.iter()
+        .filter(|(_, _, chunk_hash, _)| !vectors.contains_key(chunk_hash) && seen.insert(chunk_hash.as_str()))
+        .map(|(start, end, chunk_hash, _)| (chunk_hash.as_str(), String::from_utf8_lossy(&bytes[*start..*end]).to_string()))
+        .collect();
+
+    // The old rows of this version are replaced by the reused chunks first, then each batch of new chunks is
+    // written in its own transaction. embedded_hash is only set once every batch is in, and a retry reuses the
+    // batches that made it.
+    let mut transaction = conn.begin().await.map_err(internal)?;
+    let result = async {
+        check_file_exists(&mut transaction, file.id).await?;
+        sqlx::query("DELETE FROM file_embedding WHERE file_id = ? AND version = ?")
+            .bind(file.id)
+            .bind(file.current_version)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("DELETE FROM file_chunk WHERE file_id = ? AND version = ?")
+            .bind(file.id)
+            .bind(file.current_version)
+            .execute(&mut transaction)
+            .await?;
+        for (start, end, chunk_hash, _) in &chunks {
+            let status = if vectors.contains_key(chunk_hash) { "embedded" } else { "pending" };
+            sqlx::query("INSERT INTO file_chunk (file_id, version, start_byte, end_byte, chunk_hash, status) VALUES (?, ?, ?, ?, ?, ?)")
+                .bind(file.id)
+                .bind(file.current_version)
+                .bind(*start as i64)
+                .bind(*end as i64)
+                .bind(chunk_hash)
+                .bind(status)
+                .execute(&mut transaction)
+                .await?;
+        }
+        insert_chunk_rows(&mut transaction, file.id, file.current_version, &reused_rows).await?;
+        sqlx::query("UPDATE file_entry SET embedded_hash = NULL WHERE id = ?")
+            .bind(file.id)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await
+    }.await;
+    result.map_err(stored)?;
+
+    // Waiting for a scheduler slot can take a while, don't sit on a pool connection meanwhile
+    drop(conn);
+
+    let mut done = reused;
+    let mut tokens = 0;
+    if let Some(job) = job {
+        if !job.report(done, chunks.len(), tokens).await.map_err(internal)? {
+            return Err(EmbedError::Cancelled);
+        }
+    }
+
+    let token_counts: Vec<usize> = pending.iter().map(|(_, text)| estimate_tokens(text)).collect();
+    let batches = pack_batches(&token_counts, provider.max_batch_inputs(), provider.max_batch_tokens());
+    let batch_count = batches.len();
+    let scheduler = scheduler::global();
+    let provider = provider.as_ref();
+    let project_id = file.project_id;
+    let pending = &pending;
+
+    // Once the run has failed, batches that haven't been sent yet are skipped, but the ones already sent are
+    // still written: their tokens are billed either way
+    let stopped = AtomicBool::new(false);
+    let stopped = &stopped;
+    let mut failure: Option<EmbedError> = None;
+
+    // Up to the scheduler's limit of batches are requested at once, each attempt waiting for a slot inside the
+    // provider; results come back in batch order and are written one by one
+    let mut results = futures::stream::iter(batches)
+        .map(|batch| async move {
+            let batch = &pending[batch];
+            let inputs: Vec<String> = batch.iter().map(|(_, text)| text.clone()).collect();
+            if stopped.load(Ordering::SeqCst) {
+                return (batch, None);
+            }
+            (batch, Some(provider.embed_batch_metered(&inputs).await))
+        })
+        .buffered(scheduler.limit());
+
+    let mut rows = reused_rows;
+    while let Some((batch, result)) = results.next().await {
+        let chunk_hashes: Vec<&str> = batch.iter().map(|(chunk_hash, _)| *chunk_hash).collect();
+        let metered = match result {
+            // Skipped batches stay pending
+            None => continue,
+            Some(Ok(metered)) => metered,
+            Some(Err(error)) => {
+                let mut transaction = db_pool.begin().await.map_err(internal)?;
+                let message = error.to_string();
+                let result = async {
+                    mark_chunks(&mut transaction, file.id, file.current_version, &chunk_hashes, "failed", Some(&message)).await?;
+                    transaction.commit().await
+                }.await;
+                result.map_err(internal)?;
+                if failure.is_none() {
+                    failure = Some(EmbedError::Provider { provider: provider.name(), error });
+                    stopped.store(true, Ordering::SeqCst);
+                }
+                continue;
+            }
+        };
+        let batch_tokens = metered.tokens;
+        let batch_vectors: HashMap<&str, Vec<f64>> = chunk_hashes.iter().copied().zip(metered.embeddings).collect();
+        let batch_rows: Vec<ChunkRow> = chunks.iter()
+            .filter_map(|(start, end, chunk_hash, span)| batch_vectors.get(chunk_hash.as_str()).map(|embedding| ChunkRow {
+                start_byte: *start as i64,
+                end_byte: *end as i64,
+                chunk_hash: chunk_hash.clone(),
+                span: span.clone(),
+                embedding: embedding.clone(),
+            }))
+            .collect();
+
+        let mut transaction = db_pool.begin().await.map_err(internal)?;
+        let result = async {
+            check_file_exists(&mut transaction, file.id).await?;
+            insert_chunk_rows(&mut transaction, file.id, file.current_version, &batch_rows).await?;
+            mark_chunks(&mut transaction, file.id, file.current_version, &chunk_hashes, "embedded", None).await?;
+            transaction.commit().await
+        }.await;
+        usage::record(db_pool, project_id, user_id, provider.name(), provider.model(), UsageKind::Ingestion, batch_tokens).await
+            .map_err(internal)?;
+        tokens += batch_tokens;
+        match result.map_err(stored) {
+            Ok(()) => {},
+            Err(EmbedError::NotFound) => {
+                if failure.is_none() {
+                    failure = Some(EmbedError::NotFound);
+                    stopped.store(true, Ordering::SeqCst);
+                }
+                continue;
+            },
+            Err(e) => return Err(e),
+        }
+
+        done += batch_rows.len();
+        rows.extend(batch_rows);
+        if failure.is_some() {
+            continue;
+        }
+        if let Some(job) = job {
+            if !job.report(done, chunks.len(), tokens).await.map_err(internal)? {
+                failure = Some(EmbedError::Cancelled);
+                stopped.store(true, Ordering::SeqCst);
+                continue;
+            }
+        }
+        if done < chunks.len() {
+            if let Some(quota) = usage::check_quota(db_pool, project_id, user_id).await.map_err(internal)? {
+                failure = Some(EmbedError::QuotaExceeded(quota));
+                stopped.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+    if let Some(failure) = failure {
+        return Err(failure);
+    }
+
+    let mut transaction = db_pool.begin().await.map_err(internal)?;
+    let result = async {
+        check_file_exists(&mut transaction, file.id).await?;
+        sqlx::query("UPDATE file_entry SET content_hash = ?, embedded_hash = ? WHERE id = ?")
+            .bind(&content_hash)
+            .bind(&content_hash)
+            .bind(file.id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("UPDATE file_version SET chunking = ? WHERE file_id = ? AND version = ?")
+            .bind(strategy.to_json())
+            .bind(file.id)
+            .bind(file.current_version)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await
+    }.await;
+    result.map_err(stored)?;
+    rows.sort_by_key(|row| row.start_byte);
+
+    let embeddings: Vec<Embedding> = rows.iter()
+        .map(|row| Embedding {
+            embedding: row.embedding.clone(),
+            start_byte: row.start_byte,
+            end_byte: row.end_byte,
+            file_id: file.id,
+            version: file.current_version,
+        })
+        .collect();
+    let chunk_count = embeddings.len();
+    ProjectManager::replace_file_embeddings(project_manager, file.project_id, file.id, embeddings).await;
+
+    Ok(Some(EmbedReport {
+        chunks: chunk_count,
+        reused,
+        embedded: chunk_count - reused,
+        batches: batch_count,
+        chunking: strategy,
+    }))
+}
+
+/// Embeds a file within the request. Large files are better embedded through a job, see `job_handler`.
+pub async fn embed_file(project_manager: web::Data<Arc<Mutex<ProjectManager>>>, db_pool: web::Data<SqlitePool>, file_id: web::Path<i64>, options: web::Query<EmbedOptions>, user_id: Option<web::ReqData<i64>>) -> HttpResponse {
+    let user_id = user_id.map(|user_id| user_id.into_inner());
+    match run_embeddings_and_store(&project_manager, &db_pool, *file_id, options.force, user_id, None).await {
+        Ok(Some(report)) => HttpResponse::Ok().json(report),
+        Ok(None) => HttpResponse::Ok().body("File already embedded"),
+        Err(EmbedError::NotFound) => HttpResponse::NotFound().body("File not found"),
+        Err(EmbedError::ProjectUnavailable(project_id)) => project_unavailable(&project_manager.lock().unwrap(), project_id)
+            .unwrap_or_else(|| HttpResponse::ServiceUnavailable().body("Project is not loaded")),
+        Err(EmbedError::Provider { provider, error }) => provider_error_response(provider, error),
+        Err(EmbedError::QuotaExceeded(quota)) => HttpResponse::TooManyRequests().body(quota.to_string()),
         Err(e) => {
-            eprintln!("Database error: {}", e); 
-            HttpResponse::InternalServerError().body(e.to_string())
+            eprintln!("Failed to embed file {}: {}", file_id, e);
+            HttpResponse::InternalServerError().body("Something went wrong")
         }
     }
 }
 
-pub async fn get_embeddings(db_pool: web::Data<SqlitePool>, file_id: web::Path<i64>) -> HttpResponse {
+#[derive(Deserialize, Debug)]
+pub struct VersionQuery {
+    version: Option<i64>
+}
+
+#[derive(sqlx::FromRow)]
+struct StoredEmbedding {
+    start_byte: i64,
+    end_byte: i64,
+    symbol: Option<String>,
+    start_line: Option<i64>,
+    end_line: Option<i64>,
+    embedding: Vec<u8>,
+}
+
+pub async fn get_embeddings(db_pool: web::Data<SqlitePool>, file_id: web::Path<i64>, query: web::Query<VersionQuery>) -> HttpResponse {
     let mut conn = db_pool.acquire().await.unwrap();
-    let result: Result<Vec<(i64, i64, Vec<u8>)>, sqlx::Error> = sqlx::query_as(
+    let result: Result<Vec<StoredEmbedding>, sqlx::Error> = sqlx::query_as(
         r#"
-            SELECT start_byte, end_byte, embedding
+            SELECT start_byte, end_byte, symbol, start_line, end_line, embedding
             FROM file_embedding
             WHERE file_id = ?
+                AND version = COALESCE(?, (SELECT current_version FROM file_entry WHERE id = file_embedding.file_id))
         "#,
     )
     .bind(file_id.into_inner())
+    .bind(query.version)
     .fetch_all(&mut conn)
     .await;
 
     match result {
         Ok(rows) => {
             let mut embeddings = Vec::new();
-            for (start_byte, end_byte, blob) in rows {
+            for StoredEmbedding { start_byte, end_byte, symbol, start_line, end_line, embedding: blob } in rows {
                 let embedding: Vec<f64> = match serde_json::from_slice(&blob) {
                     Ok(embedding) => embedding,
                     Err(e) => {
@@ -220,7 +597,7 @@ pub async fn get_embeddings(db_pool: web::Data<SqlitePool>, file_id: web::Path<i
                         return HttpResponse::InternalServerError().body("Something went wrong");
                     },
                 };
-                embeddings.push(EmbeddingEntry { start_byte, end_byte, embedding });
+                embeddings.push(EmbeddingEntry { start_byte, end_byte, symbol, start_line, end_line, embedding });
             }
             HttpResponse::Ok().json(embeddings)
         },
@@ -231,26 +608,94 @@ pub async fn get_embeddings(db_pool: web::Data<SqlitePool>, file_id: web::Path<i
     }
 }
 
-pub async fn get_similiar_text(project_manager: web::Data<Arc<Mutex<ProjectManager>>>, project_id: web::Path<i64>, similiar_text_request: web::Json<similiar_text_request>) -> HttpResponse  {
-    let mut project_manager = project_manager.lock().unwrap();
-    match get_embedding(similiar_text_request.text.clone()).await {
-        Ok(embedding) => {
-            let embedding = embedding.data[0].embedding.clone();
+pub async fn get_similiar_text(project_manager: web::Data<Arc<Mutex<ProjectManager>>>, db_pool: web::Data<SqlitePool>, project_id: web::Path<i64>, similiar_text_request: web::Json<similiar_text_request>, user_id: Option<web::ReqData<i64>>) -> HttpResponse  {
+    if let Some(response) = project_unavailable(&project_manager.lock().unwrap(), *project_id) {
+        return response;
+    }
+    let user_id = user_id.map(|user_id| user_id.into_inner());
+    if let Some(response) = forbidden(&db_pool, *project_id, user_id).await {
+        return response;
+    }
+    if similiar_text_request.version.is_some() && similiar_text_request.file_id.is_none() {
+        return HttpResponse::BadRequest().body("Searching a version requires a file_id");
+    }
+    if let Some(filter) = &similiar_text_request.filter {
+        if similiar_text_request.version.is_some() {
+            return HttpResponse::BadRequest().body("A filter only applies to current versions");
+        }
+        if filter.keys().any(|key| key.is_empty() || key.contains('"')) {
+            return HttpResponse::BadRequest().body("Invalid filter key");
+        }
+    }
+    match usage::check_quota(&db_pool, *project_id, user_id).await {
+        Ok(None) => {},
+        Ok(Some(quota)) => return HttpResponse::TooManyRequests().body(quota.to_string()),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+    let provider = match provider_for_project(&db_pool, *project_id).await {
+        Ok(provider) => provider,
+        Err(e) => {
+            eprintln!("Embedding provider error: {}", e);
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    };
+    match provider.embed_batch_metered(std::slice::from_ref(&similiar_text_request.text)).await {
+        Ok(metered) => {
+            if let Err(e) = usage::record(&db_pool, *project_id, user_id, provider.name(), provider.model(), UsageKind::Search, metered.tokens).await {
+                eprintln!("Failed to record token usage: {}", e);
+            }
+            let embedding = match metered.embeddings.into_iter().next() {
+                Some(embedding) => embedding,
+                None => return provider_error_response(provider.name(), ProviderError::InvalidResponse(String::from("no embedding returned"))),
+            };
             let input_embedding = crate::memory_management::project_store::Embedding {
-                embedding: embedding.clone(),
+                embedding,
                 start_byte: -1,
                 end_byte: -1,
-                file_id: -1
+                file_id: -1,
+                version: -1
             };
-            
-            let most_similiar_index = project_manager.get_most_similiar_embedding(*project_id, input_embedding);
-    
-            HttpResponse::Ok().json(most_similiar_index)
-        }
-        Err(e) => {
-            eprintln!("OpenAI error: {}", e); 
-            HttpResponse::InternalServerError().body("Something went wrong")
+
+            // Searches cover the current version of every file unless a specific version is asked for
+            let most_similiar_index = match (similiar_text_request.file_id, similiar_text_request.version) {
+                (Some(file_id), Some(version)) => match search_file_version(&db_pool, *project_id, file_id, version, &input_embedding).await {
+                    Ok(most_similiar_index) => most_similiar_index,
+                    Err(e) => {
+                        eprintln!("Database error: {}", e);
+                        return HttpResponse::InternalServerError().body("Something went wrong")
+                    }
+                },
+                _ => match &similiar_text_request.filter {
+                    Some(filter) => match search_filtered(&db_pool, *project_id, filter, &input_embedding).await {
+                        Ok(most_similiar_index) => most_similiar_index,
+                        Err(e) => {
+                            eprintln!("Database error: {}", e);
+                            return HttpResponse::InternalServerError().body("Something went wrong")
+                        }
+                    },
+                    None => project_manager.lock().unwrap().get_most_similiar_embedding(*project_id, &input_embedding),
+                },
+            };
+
+            let embedding = match most_similiar_index {
+                Some(embedding) => embedding,
+                None => return HttpResponse::NotFound().body("Project has no embeddings")
+            };
+            let score = cosine_similarity(&input_embedding.embedding, &embedding.embedding);
+            match search_hit(&db_pool, embedding, score, similiar_text_request.include_embedding).await {
+                Ok(Some(hit)) => HttpResponse::Ok().json(hit),
+                // The file was deleted since the search
+                Ok(None) => HttpResponse::NotFound().body("Project has no embeddings"),
+                Err(e) => {
+                    eprintln!("Failed to read search hit: {}", e);
+                    HttpResponse::InternalServerError().body("Something went wrong")
+                }
+            }
         }
+        Err(e) => provider_error_response(provider.name(), e)
     }
 }
 
@@ -258,6 +703,7 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/file/{id}/embed")
             .route(web::get().to(embed_file))
+            .route(web::post().to(super::job_handler::enqueue_file))
     );
 
     cfg.service(
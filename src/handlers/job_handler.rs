@@ -0,0 +1,133 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use sqlx::{SqlitePool};
+use crate::handlers::embedding_handler::EmbedOptions;
+use crate::jobs::JobQueue;
+
+/// Queues embedding of a file and answers right away with the job to poll.
+pub async fn enqueue_file(job_queue: web::Data<JobQueue>, db_pool: web::Data<SqlitePool>, file_id: web::Path<i64>, options: web::Query<EmbedOptions>, user_id: Option<web::ReqData<i64>>) -> HttpResponse {
+    let file_id = file_id.into_inner();
+    let user_id = user_id.map(|user_id| user_id.into_inner());
+    let result: Result<Option<i64>, sqlx::Error> = sqlx::query_scalar("SELECT project_id FROM file_entry WHERE id = ?")
+        .bind(file_id)
+        .fetch_optional(db_pool.get_ref())
+        .await;
+    let project_id = match result {
+        Ok(Some(project_id)) => project_id,
+        Ok(None) => return HttpResponse::NotFound().body("File not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    };
+
+    match job_queue.enqueue(project_id, file_id, options.force, user_id).await {
+        Ok(job_id) => HttpResponse::Accepted().json(json!({ "job_id": job_id })),
+        Err(e) => {
+            eprintln!("Failed to queue embedding of file {}: {}", file_id, e);
+            HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+}
+
+/// Queues embedding of every file in the project whose current content hasn't been embedded, or of every
+/// file when forced.
+pub async fn enqueue_project(job_queue: web::Data<JobQueue>, db_pool: web::Data<SqlitePool>, project_id: web::Path<i64>, options: web::Query<EmbedOptions>, user_id: Option<web::ReqData<i64>>) -> HttpResponse {
+    let project_id = project_id.into_inner();
+    let user_id = user_id.map(|user_id| user_id.into_inner());
+    let result: Result<Vec<i64>, sqlx::Error> = sqlx::query_scalar(
+        r#"
+        SELECT id FROM file_entry
+        WHERE project_id = ? AND (? OR embedded_hash IS NULL OR embedded_hash IS NOT content_hash)
+        ORDER BY id
+        "#,
+    )
+    .bind(project_id)
+    .bind(options.force)
+    .fetch_all(db_pool.get_ref())
+    .await;
+    let file_ids = match result {
+        Ok(file_ids) => file_ids,
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    };
+
+    let mut job_ids = Vec::with_capacity(file_ids.len());
+    for file_id in file_ids {
+        match job_queue.enqueue(project_id, file_id, options.force, user_id).await {
+            Ok(job_id) => job_ids.push(job_id),
+            Err(e) => {
+                eprintln!("Failed to queue embedding of file {}: {}", file_id, e);
+                return HttpResponse::InternalServerError().body("Something went wrong")
+            }
+        }
+    }
+    HttpResponse::Accepted().json(json!({ "job_ids": job_ids }))
+}
+
+pub async fn get_job(job_queue: web::Data<JobQueue>, job_id: web::Path<i64>) -> HttpResponse {
+    match job_queue.get(*job_id).await {
+        Ok(Some(job)) => HttpResponse::Ok().json(job),
+        Ok(None) => HttpResponse::NotFound().body("Job not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+}
+
+pub async fn get_project_jobs(job_queue: web::Data<JobQueue>, project_id: web::Path<i64>) -> HttpResponse {
+    match job_queue.list(*project_id).await {
+        Ok(jobs) => HttpResponse::Ok().json(jobs),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+}
+
+pub async fn cancel_job(job_queue: web::Data<JobQueue>, job_id: web::Path<i64>) -> HttpResponse {
+    let job_id = job_id.into_inner();
+    let cancelled = match job_queue.cancel(job_id).await {
+        Ok(cancelled) => cancelled,
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    };
+
+    match job_queue.get(job_id).await {
+        Ok(Some(job)) if cancelled => HttpResponse::Ok().json(job),
+        Ok(Some(_)) => HttpResponse::Conflict().body("Job already finished"),
+        Ok(None) => HttpResponse::NotFound().body("Job not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    // POST /file/{id}/embed is registered next to the synchronous GET in embedding_handler
+    cfg.service(
+        web::resource("/projects/{id}/embed")
+            .route(web::post().to(enqueue_project))
+    );
+
+    cfg.service(
+        web::resource("/projects/{id}/jobs")
+            .route(web::get().to(get_project_jobs))
+    );
+
+    cfg.service(
+        web::resource("/jobs/{id}")
+            .route(web::get().to(get_job))
+    );
+
+    cfg.service(
+        web::resource("/jobs/{id}/cancel")
+            .route(web::post().to(cancel_job))
+    );
+}
@@ -1,3 +1,8 @@
 pub mod embedding_handler;
 pub mod user_handler;
-pub mod project_handler;
\ No newline at end of file
+pub mod project_handler;
+pub mod admin_handler;
+pub mod job_handler;
+pub mod usage_handler;
+pub mod document_handler;
+pub mod download_handler;
\ No newline at end of file
@@ -2,33 +2,81 @@ use actix_web::{web, Error, HttpResponse};
 use sqlx::{SqlitePool};
 use sqlx::Acquire;
 use crate::models::project::Project;
+use crate::models::project_update::ProjectUpdate;
+use crate::models::file_version::FileVersion;
+use similar::TextDiff;
+use crate::memory_management::project_store::IndexType;
 use futures::io::AsyncWriteExt;
 use std::fs;
+use std::path::PathBuf;
 use actix_multipart::{Field, Multipart};
 use actix_web::http::header::ContentDisposition;
 use actix_web::Responder;
 use async_std::fs::File;
 use async_std::prelude::*;  // Import prelude for write_all
 use futures::TryStreamExt;
-use serde::Deserialize;
-use crate::memory_management::project_manager::ProjectManager;
+use serde::{Deserialize, Serialize};
+use crate::memory_management::project_manager::{ProjectManager, LoadStatus};
+use crate::utils::{file_store, permissions};
+use crate::utils::file_versions::{self, PendingFile, StoreStatus, StoredFile};
+use crate::utils::archive::{self, ArchiveError, ArchiveFormat, ArchiveLimits, Skipped};
+use crate::utils::uploads::{self, UploadLimits};
+use crate::jobs::JobQueue;
+use crate::parsing::{self, DocumentFormat};
 use std::sync::{Arc, Mutex};
+use sha2::{Digest, Sha256};
+use crate::providers::{self, scheduler, ProviderConfig};
+use crate::chunking::strategy::ChunkingStrategy;
+
+const DEFAULT_MAX_FILE_VERSIONS: i64 = 10;
 
 pub async fn add_project(project_manager: web::Data<Arc<Mutex<ProjectManager>>>, db_pool: web::Data<SqlitePool>, new_project: web::Json<Project>
 ) -> HttpResponse {
-    let mut project_manager = project_manager.lock().unwrap();
+    if new_project.max_file_versions.is_some_and(|max| max < 1) {
+        return HttpResponse::BadRequest().body("max_file_versions must be at least 1");
+    }
+    let index_type = new_project.index_type.clone().unwrap_or_else(|| String::from("vptree"));
+    let parsed_index_type = match IndexType::parse(&index_type) {
+        Some(parsed_index_type) => parsed_index_type,
+        None => return HttpResponse::BadRequest().body("Unknown index type"),
+    };
+    let embedding_provider = new_project.embedding_provider.clone().unwrap_or_else(|| String::from(providers::OPENAI));
+    if !providers::is_known_provider(&embedding_provider) {
+        return HttpResponse::BadRequest().body("Unknown embedding provider");
+    }
+    if embedding_provider == providers::OPENAI_COMPATIBLE && new_project.embedding_base_url.is_none() {
+        return HttpResponse::BadRequest().body("openai_compatible requires an embedding_base_url");
+    }
+    if new_project.embedding_dimensions.is_some_and(|dimensions| dimensions < 1) {
+        return HttpResponse::BadRequest().body("embedding_dimensions must be at least 1");
+    }
+    if let Some(Err(e)) = new_project.chunking.as_ref().map(|chunking| chunking.validate()) {
+        return HttpResponse::BadRequest().body(format!("Invalid chunking: {}", e));
+    }
+    if new_project.monthly_token_quota.is_some_and(|quota| quota < 0) {
+        return HttpResponse::BadRequest().body("monthly_token_quota can't be negative");
+    }
     let mut conn = db_pool.acquire().await.unwrap();
 
     let mut transaction = conn.begin().await.unwrap(); // Start a new transaction
 
     let result = sqlx::query(
         r#"
-        INSERT INTO projects (name, description)
-        VALUES (?, ?);
+        INSERT INTO projects (name, description, auto_load, index_type, max_file_versions, embedding_provider, embedding_base_url, embedding_model, embedding_dimensions, chunking, monthly_token_quota)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);
         "#,
     )
     .bind(&new_project.name)
     .bind(&new_project.description)
+    .bind(new_project.auto_load.unwrap_or(true))
+    .bind(&index_type)
+    .bind(new_project.max_file_versions.unwrap_or(DEFAULT_MAX_FILE_VERSIONS))
+    .bind(&embedding_provider)
+    .bind(&new_project.embedding_base_url)
+    .bind(&new_project.embedding_model)
+    .bind(new_project.embedding_dimensions)
+    .bind(new_project.chunking.as_ref().map(|chunking| chunking.to_json()))
+    .bind(new_project.monthly_token_quota)
     .execute(&mut transaction)
     .await;
 
@@ -36,9 +84,17 @@ pub async fn add_project(project_manager: web::Data<Arc<Mutex<ProjectManager>>>,
         Ok(_) => {
             let id: i64 = sqlx::query_scalar("SELECT LAST_INSERT_ROWID()").fetch_one(&mut transaction).await.unwrap();
             transaction.commit().await.unwrap(); // Commit the transaction
-            project_manager.add_blank_project(id, new_project.name.clone());
+            {
+                let mut project_manager = project_manager.lock().unwrap();
+                project_manager.add_blank_project(id, new_project.name.clone());
+                project_manager.update_project(id, None, Some(parsed_index_type));
+            }
             let mut new_project_with_id = new_project.into_inner(); // Get the inner Project from Json<Project>
             new_project_with_id.id = Some(id); // Set the id to the newly inserted id
+            new_project_with_id.auto_load = Some(new_project_with_id.auto_load.unwrap_or(true));
+            new_project_with_id.index_type = Some(index_type);
+            new_project_with_id.max_file_versions = Some(new_project_with_id.max_file_versions.unwrap_or(DEFAULT_MAX_FILE_VERSIONS));
+            new_project_with_id.embedding_provider = Some(embedding_provider);
             fs::create_dir(format!("./project_data/{}", id));
             HttpResponse::Ok().json(new_project_with_id) // Respond with the new project with id
         },
@@ -56,7 +112,7 @@ pub async fn get_project_by_id(
     let mut conn = db_pool.acquire().await.unwrap();
     let result: Result<Project, sqlx::Error> = sqlx::query_as(
         r#"
-        SELECT id, name, description FROM projects WHERE id = ?
+        SELECT id, name, description, auto_load, index_type, max_file_versions, embedding_provider, embedding_base_url, embedding_model, embedding_dimensions, chunking, monthly_token_quota FROM projects WHERE id = ?
         "#,
     )
     .bind(project_id.into_inner())
@@ -77,67 +133,536 @@ pub async fn get_project_by_id(
     }
 }
 
+pub async fn update_project(
+    project_manager: web::Data<Arc<Mutex<ProjectManager>>>,
+    db_pool: web::Data<SqlitePool>,
+    project_id: web::Path<i64>,
+    update: web::Json<ProjectUpdate>,
+) -> HttpResponse {
+    let index_type = match &update.index_type {
+        Some(index_type) => match IndexType::parse(index_type) {
+            Some(index_type) => Some(index_type),
+            None => return HttpResponse::BadRequest().body("Unknown index type"),
+        },
+        None => None,
+    };
+
+    if update.max_file_versions.is_some_and(|max| max < 1) {
+        return HttpResponse::BadRequest().body("max_file_versions must be at least 1");
+    }
+    if update.embedding_provider.as_deref().is_some_and(|provider| !providers::is_known_provider(provider)) {
+        return HttpResponse::BadRequest().body("Unknown embedding provider");
+    }
+    if update.embedding_dimensions.flatten().is_some_and(|dimensions| dimensions < 1) {
+        return HttpResponse::BadRequest().body("embedding_dimensions must be at least 1");
+    }
+    if let Some(Err(e)) = update.chunking.as_ref().and_then(Option::as_ref).map(|chunking| chunking.validate()) {
+        return HttpResponse::BadRequest().body(format!("Invalid chunking: {}", e));
+    }
+    if update.monthly_token_quota.flatten().is_some_and(|quota| quota < 0) {
+        return HttpResponse::BadRequest().body("monthly_token_quota can't be negative");
+    }
+
+    let mut conn = db_pool.acquire().await.unwrap();
+    let mut transaction = conn.begin().await.unwrap();
+
+    // Vectors from different models can't be compared, so the model can only change while the project has none.
+    // The check runs in the update's transaction so no embeddings can be stored in between.
+    if update.embedding_provider.is_some() || update.embedding_base_url.is_some()
+        || update.embedding_model.is_some() || update.embedding_dimensions.is_some() {
+        let current: Result<Option<ProviderConfig>, sqlx::Error> = sqlx::query_as(
+            "SELECT embedding_provider, embedding_base_url, embedding_model, embedding_dimensions FROM projects WHERE id = ?"
+        )
+        .bind(*project_id)
+        .fetch_optional(&mut transaction)
+        .await;
+        let current = match current {
+            Ok(Some(current)) => current,
+            Ok(None) => return HttpResponse::NotFound().body("Project not found"),
+            Err(e) => {
+                eprintln!("Database error: {}", e); // Log the error
+                return HttpResponse::InternalServerError().body("Something went wrong")
+            }
+        };
+        let provider = update.embedding_provider.as_ref().unwrap_or(&current.embedding_provider);
+        let base_url = update.embedding_base_url.as_ref().unwrap_or(&current.embedding_base_url);
+        if provider == providers::OPENAI_COMPATIBLE && base_url.is_none() {
+            return HttpResponse::BadRequest().body("openai_compatible requires an embedding_base_url");
+        }
+        let changed = *provider != current.embedding_provider
+            || *base_url != current.embedding_base_url
+            || update.embedding_model.as_ref().is_some_and(|model| *model != current.embedding_model)
+            || update.embedding_dimensions.is_some_and(|dimensions| dimensions != current.embedding_dimensions);
+        if changed {
+            let embedded: Result<i64, sqlx::Error> = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM file_embedding JOIN file_entry ON file_entry.id = file_embedding.file_id WHERE file_entry.project_id = ?"
+            )
+            .bind(*project_id)
+            .fetch_one(&mut transaction)
+            .await;
+            match embedded {
+                Ok(0) => {},
+                Ok(_) => return HttpResponse::Conflict().body("Project already has embeddings from its current model"),
+                Err(e) => {
+                    eprintln!("Database error: {}", e); // Log the error
+                    return HttpResponse::InternalServerError().body("Something went wrong")
+                }
+            }
+        }
+    }
+
+    // Nullable settings are only written when the field was sent, so an explicit null clears them
+    let result = sqlx::query(
+        r#"
+        UPDATE projects
+        SET name = COALESCE(?, name),
+            description = COALESCE(?, description),
+            auto_load = COALESCE(?, auto_load),
+            index_type = COALESCE(?, index_type),
+            max_file_versions = COALESCE(?, max_file_versions),
+            embedding_provider = COALESCE(?, embedding_provider),
+            embedding_base_url = CASE WHEN ? THEN ? ELSE embedding_base_url END,
+            embedding_model = CASE WHEN ? THEN ? ELSE embedding_model END,
+            embedding_dimensions = CASE WHEN ? THEN ? ELSE embedding_dimensions END,
+            chunking = CASE WHEN ? THEN ? ELSE chunking END,
+            monthly_token_quota = CASE WHEN ? THEN ? ELSE monthly_token_quota END
+        WHERE id = ?
+        "#,
+    )
+    .bind(&update.name)
+    .bind(&update.description)
+    .bind(update.auto_load)
+    .bind(&update.index_type)
+    .bind(update.max_file_versions)
+    .bind(&update.embedding_provider)
+    .bind(update.embedding_base_url.is_some())
+    .bind(update.embedding_base_url.clone().flatten())
+    .bind(update.embedding_model.is_some())
+    .bind(update.embedding_model.clone().flatten())
+    .bind(update.embedding_dimensions.is_some())
+    .bind(update.embedding_dimensions.flatten())
+    .bind(update.chunking.is_some())
+    .bind(update.chunking.as_ref().and_then(Option::as_ref).map(|chunking| chunking.to_json()))
+    .bind(update.monthly_token_quota.is_some())
+    .bind(update.monthly_token_quota.flatten())
+    .bind(*project_id)
+    .execute(&mut transaction)
+    .await;
+
+    match result {
+        Ok(result) if result.rows_affected() == 0 => HttpResponse::NotFound().body("Project not found"),
+        Ok(_) => {
+            if let Err(e) = transaction.commit().await {
+                eprintln!("Database error: {}", e); // Log the error
+                return HttpResponse::InternalServerError().body("Something went wrong")
+            }
+            project_manager.lock().unwrap().update_project(*project_id, update.name.clone(), index_type);
+            get_project_by_id(db_pool, project_id).await
+        },
+        Err(e) => {
+            eprintln!("Database error: {}", e); // Log the error
+            HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+}
+
+pub async fn delete_project(
+    project_manager: web::Data<Arc<Mutex<ProjectManager>>>,
+    db_pool: web::Data<SqlitePool>,
+    project_id: web::Path<i64>,
+) -> HttpResponse {
+    let project_id = project_id.into_inner();
+    // A project that is still loading would be put back into memory once its load finishes
+    if project_manager.lock().unwrap().get_load_status(project_id) == Some(LoadStatus::Loading) {
+        return HttpResponse::ServiceUnavailable().body("Project is still loading");
+    }
+
+    let mut conn = db_pool.acquire().await.unwrap();
+    let exists: Result<Option<i64>, sqlx::Error> = sqlx::query_scalar("SELECT id FROM projects WHERE id = ?")
+        .bind(project_id)
+        .fetch_optional(&mut conn)
+        .await;
+
+    match exists {
+        Ok(Some(_)) => {},
+        Ok(None) => return HttpResponse::NotFound().body("Project not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e); // Log the error
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+
+    // Same approach as delete_file: park the directory until the rows are gone
+    let _trash_guard = file_store::TRASH_LOCK.read().await;
+    let trashed = match file_store::move_project_to_trash(project_id) {
+        Ok(trashed) => trashed,
+        Err(e) => {
+            eprintln!("Failed to move project {} to trash: {}", project_id, e);
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    };
+
+    let mut transaction = conn.begin().await.unwrap();
+    let result = async {
+        sqlx::query("DELETE FROM embedding_job WHERE project_id = ?")
+            .bind(project_id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("DELETE FROM file_embedding WHERE file_id IN (SELECT id FROM file_entry WHERE project_id = ?)")
+            .bind(project_id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("DELETE FROM file_chunk WHERE file_id IN (SELECT id FROM file_entry WHERE project_id = ?)")
+            .bind(project_id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("DELETE FROM file_version WHERE file_id IN (SELECT id FROM file_entry WHERE project_id = ?)")
+            .bind(project_id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("DELETE FROM file_entry WHERE project_id = ?")
+            .bind(project_id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("DELETE FROM user_project WHERE project_id = ?")
+            .bind(project_id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("DELETE FROM projects WHERE id = ?")
+            .bind(project_id)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await
+    }.await;
+
+    if let Err(e) = result {
+        eprintln!("Database error: {}", e); // Log the error
+        if trashed {
+            if let Err(e) = file_store::restore_project_from_trash(project_id) {
+                eprintln!("Failed to restore project {}: {}", project_id, e);
+            }
+        }
+        return HttpResponse::InternalServerError().body("Something went wrong")
+    }
+
+    if trashed {
+        file_store::empty_project_trash(project_id);
+    }
+    project_manager.lock().unwrap().remove_project(project_id);
+    HttpResponse::Ok().body("Project deleted")
+}
+
+/// Form fields other than the payload are short; longer ones are ignored.
+const MAX_FIELD_BYTES: usize = 64 * 1024;
+
 async fn read_string(field: &mut Field) -> Option<String> {
-    let bytes = field.try_next().await;
+    let mut bytes = Vec::new();
+    loop {
+        match field.try_next().await {
+            Ok(Some(chunk)) if bytes.len() + chunk.len() <= MAX_FIELD_BYTES => bytes.extend_from_slice(&chunk),
+            Ok(Some(_)) | Err(_) => return None,
+            Ok(None) => return String::from_utf8(bytes).ok(),
+        }
+    }
+}
 
-    if let Ok(Some(bytes)) = bytes {
-        String::from_utf8(bytes.to_vec()).ok()
-    } else {
-        None
+enum UploadError {
+    /// A document that couldn't be parsed.
+    Unreadable(String),
+    Failed(String),
+}
+
+/// Parses a fully written upload and stores it as the newest version of its name, then makes the project's
+/// in-memory store follow it.
+async fn store_upload(
+    project_manager: &Arc<Mutex<ProjectManager>>,
+    db_pool: &SqlitePool,
+    project_id: i64,
+    upload: &PendingFile,
+    chunking: Option<&ChunkingStrategy>,
+) -> Result<StoredFile, UploadError> {
+    // Documents are parsed before they are stored, so one that can't be read is turned away
+    let bytes = match fs::read(&upload.temp_path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            let _ = fs::remove_file(&upload.temp_path);
+            return Err(UploadError::Failed(e.to_string()));
+        }
+    };
+    let format = DocumentFormat::detect(&upload.name, &bytes);
+    let parsed = match parsing::parse(format, &bytes) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            let _ = fs::remove_file(&upload.temp_path);
+            return Err(UploadError::Unreadable(format!("Could not read {} as {}: {}", upload.name, format.as_str(), e)));
+        }
+    };
+    drop(bytes);
+
+    // A re-upload under an existing name becomes a new version of that file
+    let stored = file_versions::store_version(db_pool, project_id, upload, chunking, None).await
+        .map_err(UploadError::Failed)?;
+    if stored.status != StoreStatus::Unchanged {
+        if let Err(e) = file_versions::save_text(db_pool, project_id, stored.id, stored.version, format, parsed.as_ref()).await {
+            // The text is extracted again when the version is embedded
+            eprintln!("Failed to save the text of file {}: {}", stored.id, e);
+        }
+    }
+    ProjectManager::track_version(project_manager, project_id, stored.id, stored.status).await;
+    Ok(stored)
+}
+
+#[derive(Serialize, Debug)]
+pub struct StoredMember {
+    name: String,
+    #[serde(flatten)]
+    stored: StoredFile,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ArchiveUpload {
+    files: Vec<StoredMember>,
+    skipped: Vec<Skipped>,
+    job_ids: Vec<i64>,
+}
+
+/// Stores every file of an uploaded archive under its path inside the archive. Documents that can't be parsed
+/// are skipped like unsafe entries. Returns the response to send instead when the archive is rejected.
+async fn upload_archive(
+    project_manager: &Arc<Mutex<ProjectManager>>,
+    db_pool: &SqlitePool,
+    project_id: i64,
+    format: ArchiveFormat,
+    temp_path: &std::path::Path,
+    chunking: Option<&ChunkingStrategy>,
+    limits: &UploadLimits,
+) -> Result<ArchiveUpload, HttpResponse> {
+    let expanded = archive::expand(format, temp_path, project_id, &ArchiveLimits::from_env());
+    let _ = fs::remove_file(temp_path);
+    let expanded = match expanded {
+        Ok(expanded) => expanded,
+        Err(e @ ArchiveError::Unreadable(_)) => return Err(HttpResponse::UnprocessableEntity().body(e.to_string())),
+        Err(e @ ArchiveError::TooLarge(_)) => return Err(HttpResponse::PayloadTooLarge().body(e.to_string())),
+        Err(ArchiveError::Io(e)) => {
+            eprintln!("Failed to expand archive: {}", e); // Log the error
+            return Err(HttpResponse::InternalServerError().body("Something went wrong"))
+        }
+    };
+    let incoming = expanded.members.iter().map(|member| member.size).sum();
+    if let Some(response) = project_limit_reached(db_pool, project_id, incoming, limits).await {
+        archive::discard(expanded.members);
+        return Err(response);
+    }
+
+    let mut files = Vec::with_capacity(expanded.members.len());
+    let mut skipped = expanded.skipped;
+    let mut members = expanded.members.into_iter();
+    while let Some(member) = members.next() {
+        match store_upload(project_manager, db_pool, project_id, &member, chunking).await {
+            Ok(stored) => files.push(StoredMember { name: member.name, stored }),
+            Err(UploadError::Unreadable(reason)) => skipped.push(Skipped { name: member.name, reason }),
+            Err(UploadError::Failed(e)) => {
+                archive::discard(members);
+                eprintln!("Failed to store {}: {}", member.name, e); // Log the error
+                return Err(HttpResponse::InternalServerError().body("Something went wrong"))
+            }
+        }
+    }
+    Ok(ArchiveUpload { files, skipped, job_ids: Vec::new() })
+}
+
+/// The fields of an upload form, which may arrive in any order.
+#[derive(Default)]
+struct UploadForm {
+    upload_name: Option<String>,
+    chunking: Option<Result<ChunkingStrategy, String>>,
+    embed: bool,
+    payload: Option<SavedPayload>,
+}
+
+/// The payload field, written out to a temporary file of the project.
+struct SavedPayload {
+    /// The file name the client gave the payload, used when the form has no `upload_name`.
+    file_name: Option<String>,
+    temp_path: PathBuf,
+    content_hash: String,
+    size: i64,
+}
+
+fn storage_error(e: std::io::Error) -> HttpResponse {
+    eprintln!("Failed to write upload: {}", e); // Log the error
+    HttpResponse::InternalServerError().body("Something went wrong")
+}
+
+fn multipart_error(e: actix_multipart::MultipartError) -> HttpResponse {
+    HttpResponse::BadRequest().body(format!("Invalid multipart body: {}", e))
+}
+
+/// Reads every field of an upload form, streaming the payload to a temporary file. Returns the response to send
+/// instead when the form is rejected, in which case nothing is left behind.
+async fn read_upload_form(payload: &mut Multipart, project_id: i64, limits: &UploadLimits) -> Result<UploadForm, HttpResponse> {
+    let mut form = UploadForm::default();
+    let result: Result<(), HttpResponse> = async {
+        while let Some(item) = payload.next().await {
+            let mut field = item.map_err(multipart_error)?;
+            let field_name = field.name().to_string();
+            match field_name.as_str() {
+                "upload_name" => form.upload_name = read_string(&mut field).await,
+                // Overrides the project's chunking strategy for this upload
+                "chunking" => {
+                    form.chunking = Some(match read_string(&mut field).await {
+                        Some(json) => ChunkingStrategy::parse(&json),
+                        None => Err(String::from("not valid UTF-8")),
+                    });
+                },
+                // Queues embedding of the files of an archive
+                "embed" => form.embed = read_string(&mut field).await.is_some_and(|value| value.trim() == "true"),
+                "payload" => {
+                    if form.payload.is_some() {
+                        return Err(HttpResponse::BadRequest().body("Only one payload can be uploaded at a time"));
+                    }
+                    // Parts without a content type are plain text, as RFC 7578 has it
+                    let content_type = field.content_type().map_or_else(|| String::from("text/plain"), |mime| mime.essence_str().to_string());
+                    if !limits.allows_type(&content_type) {
+                        return Err(HttpResponse::UnsupportedMediaType().body(format!("Uploads of type {} are not allowed", content_type)));
+                    }
+                    form.payload = Some(save_payload(&mut field, project_id, limits.max_file_bytes).await?);
+                },
+                _ => {},
+            }
+        }
+        Ok(())
+    }.await;
+
+    match result {
+        Ok(()) => Ok(form),
+        Err(response) => {
+            if let Some(saved) = form.payload {
+                let _ = fs::remove_file(&saved.temp_path);
+            }
+            Err(response)
+        }
+    }
+}
+
+/// Streams the payload into a temporary file, hashing it on the way. Gives up with `413` as soon as it grows past
+/// `max_bytes`; the file only becomes a version once it is complete.
+async fn save_payload(field: &mut Field, project_id: i64, max_bytes: u64) -> Result<SavedPayload, HttpResponse> {
+    let file_name = field.content_disposition().get_filename().map(str::to_string);
+    let temp_path = file_store::temp_path(project_id);
+    let mut hasher = Sha256::new();
+    let mut size: u64 = 0;
+    let written: Result<(), HttpResponse> = async {
+        fs::create_dir_all(temp_path.parent().unwrap()).map_err(storage_error)?;
+        let mut file = async_std::fs::File::create(&temp_path).await.map_err(storage_error)?;
+        while let Some(chunk) = field.next().await {
+            let data = chunk.map_err(multipart_error)?;
+            size += data.len() as u64;
+            if size > max_bytes {
+                return Err(HttpResponse::PayloadTooLarge().body(format!("Uploads may be at most {} bytes", max_bytes)));
+            }
+            hasher.update(&data);
+            async_std::io::WriteExt::write_all(&mut file, &data).await.map_err(storage_error)?;
+        }
+        // async-std only writes out what is buffered when asked to
+        async_std::io::WriteExt::flush(&mut file).await.map_err(storage_error)
+    }.await;
+
+    match written {
+        Ok(()) => Ok(SavedPayload { file_name, temp_path, content_hash: format!("{:x}", hasher.finalize()), size: size as i64 }),
+        Err(response) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(response)
+        }
+    }
+}
+
+/// Returns the response to send when `incoming` more bytes would take the project past `PROJECT_MAX_BYTES`.
+async fn project_limit_reached(db_pool: &SqlitePool, project_id: i64, incoming: i64, limits: &UploadLimits) -> Option<HttpResponse> {
+    let max_bytes = limits.max_project_bytes?;
+    match uploads::project_bytes(db_pool, project_id).await {
+        Ok(stored) if (stored + incoming) as u64 > max_bytes => {
+            Some(HttpResponse::PayloadTooLarge().body(format!("Projects may store at most {} bytes, {} are in use", max_bytes, stored)))
+        },
+        Ok(_) => None,
+        Err(e) => {
+            eprintln!("Database error: {}", e); // Log the error
+            Some(HttpResponse::InternalServerError().body("Something went wrong"))
+        }
     }
 }
 
 pub async fn upload(
+    project_manager: web::Data<Arc<Mutex<ProjectManager>>>,
     db_pool: web::Data<SqlitePool>,
+    job_queue: web::Data<JobQueue>,
     id: web::Path<i64>,
     mut payload: Multipart,
-) -> Result<HttpResponse, actix_web::Error> {
-    let mut upload_name = String::new();
-    let mut file_path = String::from("");
-    while let Some(item) = payload.next().await {
-        let mut field = item?;
-        let content_disposition = field.content_disposition(); //.unwrap();
-        let field_name = content_disposition.get_name().unwrap();
-
-        match field_name {
-            "upload_name" => {
-                if let Some(name) = read_string(&mut field).await { // pass `&mut field` instead of `&mut item`
-                    upload_name = name;
-                    println!("{}", upload_name)
-                } else {
-                    // Handle the case when read_string() returns None.
-                    // You could return an error response, or you can just continue.
-                }
-            },
-            "payload" => {
-                file_path = format!("./project_data/{}/{}", id, sanitize_filename::sanitize(&upload_name));
-                
-                let mut f = async_std::fs::File::create(&file_path).await.unwrap();
-        
-                while let Some(chunk) = field.next().await {
-                    let data = chunk.unwrap();
-                    async_std::io::WriteExt::write_all(&mut f, &data).await.unwrap();
-                }
-            },
-            _ => {
+    user_id: Option<web::ReqData<i64>>,
+) -> HttpResponse {
+    if let Some(response) = project_unavailable(&project_manager.lock().unwrap(), *id) {
+        return response;
+    }
+    let limits = UploadLimits::from_env();
+    let form = match read_upload_form(&mut payload, *id, &limits).await {
+        Ok(form) => form,
+        Err(response) => return response,
+    };
+    let saved = match form.payload {
+        Some(saved) => saved,
+        None => return HttpResponse::BadRequest().body("Missing payload"),
+    };
+    let rejected = |response: HttpResponse| {
+        let _ = fs::remove_file(&saved.temp_path);
+        response
+    };
+
+    let name = form.upload_name.clone()
+        .or_else(|| saved.file_name.clone())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty());
+    let name = match name {
+        Some(name) => name,
+        None => return rejected(HttpResponse::BadRequest().body("Missing upload_name")),
+    };
+    let chunking = match form.chunking.transpose() {
+        Ok(chunking) => chunking,
+        Err(e) => return rejected(HttpResponse::BadRequest().body(format!("Invalid chunking: {}", e))),
+    };
 
+    // Archives are expanded into one file per member
+    if let Some(format) = ArchiveFormat::detect(&name) {
+        let mut uploaded = match upload_archive(&project_manager, &db_pool, *id, format, &saved.temp_path, chunking.as_ref(), &limits).await {
+            Ok(uploaded) => uploaded,
+            Err(response) => return response,
+        };
+        if form.embed {
+            let user_id = user_id.map(|user_id| user_id.into_inner());
+            for file in &uploaded.files {
+                match job_queue.enqueue(*id, file.stored.id, false, user_id).await {
+                    Ok(job_id) => uploaded.job_ids.push(job_id),
+                    Err(e) => {
+                        eprintln!("Failed to queue embedding of file {}: {}", file.stored.id, e);
+                        return HttpResponse::InternalServerError().body("Something went wrong")
+                    }
+                }
             }
-        } 
+        }
+        return HttpResponse::Ok().json(uploaded);
     }
 
-    let mut conn = db_pool.acquire().await.unwrap();
-    let result = sqlx::query(
-        r#"
-        INSERT INTO file_entry (name, path, project_id)
-        VALUES (?, ?, ?);
-        "#,     
-    )
-    .bind(upload_name)
-    .bind(file_path)
-    .bind(*id)
-    .execute(&mut conn)
-    .await;
-    Ok(HttpResponse::Ok().into())
+    if let Some(response) = project_limit_reached(&db_pool, *id, saved.size, &limits).await {
+        return rejected(response);
+    }
+    let upload = PendingFile { name, temp_path: saved.temp_path.clone(), content_hash: saved.content_hash.clone(), size: saved.size };
+    match store_upload(&project_manager, &db_pool, *id, &upload, chunking.as_ref()).await {
+        Ok(stored) => HttpResponse::Ok().json(stored),
+        Err(UploadError::Unreadable(e)) => HttpResponse::UnprocessableEntity().body(e),
+        Err(UploadError::Failed(e)) => {
+            eprintln!("Failed to store upload: {}", e); // Log the error
+            HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
 }
 
 pub async fn get_files_by_project_id(
@@ -145,9 +670,26 @@ pub async fn get_files_by_project_id(
     project_id: web::Path<i64>,
 ) -> HttpResponse {
     let mut conn = db_pool.acquire().await.unwrap();
-    let result: Result<Vec<crate::models::file::File>, sqlx::Error> = sqlx::query_as(
+    let result: Result<Vec<crate::models::file::FileListing>, sqlx::Error> = sqlx::query_as(
         r#"
-        SELECT id, name, path, project_id FROM file_entry WHERE project_id = ?
+        SELECT
+            file_entry.id, file_entry.name, file_entry.path, file_entry.project_id,
+            file_entry.content_hash, file_entry.embedded_hash, file_entry.current_version,
+            file_entry.external_id, file_entry.metadata,
+            CASE
+                WHEN file_entry.embedded_hash IS NOT NULL AND file_entry.embedded_hash = file_entry.content_hash THEN 'complete'
+                WHEN SUM(file_chunk.status = 'embedded') > 0 THEN 'partial'
+                WHEN SUM(file_chunk.status = 'failed') > 0 THEN 'failed'
+                ELSE 'pending'
+            END AS embedding_status,
+            COUNT(file_chunk.file_id) AS chunks_total,
+            COALESCE(SUM(file_chunk.status = 'embedded'), 0) AS chunks_embedded,
+            COALESCE(SUM(file_chunk.status = 'failed'), 0) AS chunks_failed
+        FROM file_entry
+        LEFT JOIN file_chunk ON file_chunk.file_id = file_entry.id AND file_chunk.version = file_entry.current_version
+        WHERE file_entry.project_id = ?
+        GROUP BY file_entry.id
+        ORDER BY file_entry.id
         "#,
     )
     .bind(project_id.into_inner())
@@ -168,6 +710,247 @@ pub async fn get_files_by_project_id(
     }
 }
 
+pub async fn delete_file(
+    project_manager: web::Data<Arc<Mutex<ProjectManager>>>,
+    db_pool: web::Data<SqlitePool>,
+    path: web::Path<(i64, i64)>,
+) -> HttpResponse {
+    let (project_id, file_id) = path.into_inner();
+    if let Some(response) = project_unavailable(&project_manager.lock().unwrap(), project_id) {
+        return response;
+    }
+
+    let mut conn = db_pool.acquire().await.unwrap();
+    // Every file, however old, is stored under files/{file_id}/ since adopt_unversioned ran at startup
+    let result: Result<i64, sqlx::Error> = sqlx::query_scalar("SELECT id FROM file_entry WHERE id = ? AND project_id = ?")
+        .bind(file_id)
+        .bind(project_id)
+        .fetch_one(&mut conn)
+        .await;
+
+    match result {
+        Ok(_) => {},
+        Err(sqlx::Error::RowNotFound) => return HttpResponse::NotFound().body("File not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e); // Log the error
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+
+    // A job that is still embedding the file stops at its next progress report instead of
+    // writing rows for it after the delete
+    let result = sqlx::query("UPDATE embedding_job SET status = 'cancelled', finished_at = CURRENT_TIMESTAMP WHERE file_id = ? AND status IN ('queued', 'running')")
+        .bind(file_id)
+        .execute(&mut conn)
+        .await;
+    if let Err(e) = result {
+        eprintln!("Database error: {}", e); // Log the error
+        return HttpResponse::InternalServerError().body("Something went wrong")
+    }
+
+    // The stored file is parked in the project's trash until the rows are gone; recover_trash
+    // finishes or rolls back the delete if the server dies in between.
+    let _trash_guard = file_store::TRASH_LOCK.read().await;
+    let trashed = match file_store::move_to_trash(project_id, file_id) {
+        Ok(trashed) => trashed,
+        Err(e) => {
+            eprintln!("Failed to move file {} to trash: {}", file_id, e);
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    };
+
+    let mut transaction = conn.begin().await.unwrap();
+    let result = async {
+        sqlx::query("DELETE FROM embedding_job WHERE file_id = ?")
+            .bind(file_id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("DELETE FROM file_embedding WHERE file_id = ?")
+            .bind(file_id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("DELETE FROM file_chunk WHERE file_id = ?")
+            .bind(file_id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("DELETE FROM file_version WHERE file_id = ?")
+            .bind(file_id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("DELETE FROM file_entry WHERE id = ?")
+            .bind(file_id)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await
+    }.await;
+
+    if let Err(e) = result {
+        eprintln!("Database error: {}", e); // Log the error
+        if trashed {
+            if let Err(e) = file_store::restore_from_trash(project_id, file_id) {
+                eprintln!("Failed to restore file {}: {}", file_id, e);
+            }
+        }
+        return HttpResponse::InternalServerError().body("Something went wrong")
+    }
+
+    if trashed {
+        file_store::empty_trash(project_id, file_id);
+    }
+    ProjectManager::remove_file(&project_manager, project_id, file_id).await;
+    HttpResponse::Ok().body("File deleted")
+}
+
+pub async fn get_file_versions(
+    db_pool: web::Data<SqlitePool>,
+    path: web::Path<(i64, i64)>,
+    user_id: Option<web::ReqData<i64>>,
+) -> HttpResponse {
+    let (project_id, file_id) = path.into_inner();
+    if let Some(response) = forbidden(&db_pool, project_id, user_id.as_deref().copied()).await {
+        return response;
+    }
+    let mut conn = db_pool.acquire().await.unwrap();
+    let result: Result<Vec<FileVersion>, sqlx::Error> = sqlx::query_as(
+        r#"
+        SELECT file_version.file_id, file_version.version, file_version.path, file_version.content_hash,
+            file_version.size, file_version.chunking, file_version.format, file_version.text_path,
+            file_version.sections, file_version.created_at
+        FROM file_version
+        JOIN file_entry ON file_entry.id = file_version.file_id
+        WHERE file_version.file_id = ? AND file_entry.project_id = ?
+        ORDER BY file_version.version
+        "#,
+    )
+    .bind(file_id)
+    .bind(project_id)
+    .fetch_all(&mut conn)
+    .await;
+
+    match result {
+        Ok(versions) if versions.is_empty() => HttpResponse::NotFound().body("File not found"),
+        Ok(versions) => HttpResponse::Ok().json(versions),
+        Err(e) => {
+            eprintln!("Database error: {}", e); // Log the error
+            HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DiffQuery {
+    from: i64,
+    to: Option<i64>
+}
+
+/// Unified line diff between two stored versions of a file; `to` defaults to the current version.
+pub async fn diff_file_versions(
+    db_pool: web::Data<SqlitePool>,
+    path: web::Path<(i64, i64)>,
+    query: web::Query<DiffQuery>,
+    user_id: Option<web::ReqData<i64>>,
+) -> HttpResponse {
+    let (project_id, file_id) = path.into_inner();
+    if let Some(response) = forbidden(&db_pool, project_id, user_id.as_deref().copied()).await {
+        return response;
+    }
+    let mut conn = db_pool.acquire().await.unwrap();
+    let current: Result<Option<i64>, sqlx::Error> = sqlx::query_scalar("SELECT current_version FROM file_entry WHERE id = ? AND project_id = ?")
+        .bind(file_id)
+        .bind(project_id)
+        .fetch_optional(&mut conn)
+        .await;
+    let to = match current {
+        Ok(Some(current)) => query.to.unwrap_or(current),
+        Ok(None) => return HttpResponse::NotFound().body("File not found"),
+        Err(e) => {
+            eprintln!("Database error: {}", e); // Log the error
+            return HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    };
+
+    let mut texts = Vec::new();
+    for version in [query.from, to] {
+        let path: Result<Option<String>, sqlx::Error> = sqlx::query_scalar("SELECT COALESCE(text_path, path) FROM file_version WHERE file_id = ? AND version = ?")
+            .bind(file_id)
+            .bind(version)
+            .fetch_optional(&mut conn)
+            .await;
+        match path {
+            Ok(Some(path)) => match fs::read(&path) {
+                Ok(bytes) => texts.push(String::from_utf8_lossy(&bytes).to_string()),
+                Err(e) => {
+                    eprintln!("couldn't open {}: {}", path, e);
+                    return HttpResponse::InternalServerError().body("Something went wrong")
+                }
+            },
+            Ok(None) => return HttpResponse::NotFound().body(format!("Version {} not found", version)),
+            Err(e) => {
+                eprintln!("Database error: {}", e); // Log the error
+                return HttpResponse::InternalServerError().body("Something went wrong")
+            }
+        }
+    }
+
+    let diff = TextDiff::from_lines(&texts[0], &texts[1])
+        .unified_diff()
+        .header(&format!("v{}", query.from), &format!("v{}", to))
+        .to_string();
+    HttpResponse::Ok().content_type("text/plain; charset=utf-8").body(diff)
+}
+
+/// Returns the response to send when a project's embeddings are not available in memory yet.
+pub fn project_unavailable(project_manager: &ProjectManager, project_id: i64) -> Option<HttpResponse> {
+    match project_manager.get_load_status(project_id) {
+        Some(LoadStatus::Ready) => None,
+        Some(LoadStatus::Loading) => Some(HttpResponse::ServiceUnavailable().body("Project is still loading")),
+        Some(LoadStatus::Failed(e)) => Some(HttpResponse::ServiceUnavailable().body(format!("Project failed to load: {}", e))),
+        None if !project_manager.is_initialized() => Some(HttpResponse::ServiceUnavailable().body("Projects are still loading")),
+        None => Some(HttpResponse::NotFound().body("Project not found")),
+    }
+}
+
+/// Returns the response to send when the user may not read the project. Inert while authentication is off, as
+/// requests then carry no user.
+pub async fn forbidden(db_pool: &SqlitePool, project_id: i64, user_id: Option<i64>) -> Option<HttpResponse> {
+    match permissions::can_read_project(db_pool, project_id, user_id).await {
+        Ok(true) => None,
+        Ok(false) => Some(HttpResponse::Forbidden().body("No access to this project")),
+        Err(e) => {
+            eprintln!("Database error: {}", e); // Log the error
+            Some(HttpResponse::InternalServerError().body("Something went wrong"))
+        }
+    }
+}
+
+pub async fn readiness(project_manager: web::Data<Arc<Mutex<ProjectManager>>>) -> HttpResponse {
+    let project_manager = project_manager.lock().unwrap();
+    let scheduler = scheduler::global();
+    let body = serde_json::json!({
+        "ready": project_manager.is_ready(),
+        "projects": project_manager.get_load_statuses(),
+        "embedding_requests": { "running": scheduler.running(), "limit": scheduler.limit() },
+    });
+
+    if project_manager.is_ready() {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+pub async fn get_project_status(
+    project_manager: web::Data<Arc<Mutex<ProjectManager>>>,
+    project_id: web::Path<i64>,
+) -> HttpResponse {
+    let project_manager = project_manager.lock().unwrap();
+    match project_manager.get_load_status(*project_id) {
+        Some(status) => HttpResponse::Ok().json(status),
+        None if !project_manager.is_initialized() => HttpResponse::Ok().json(LoadStatus::Loading),
+        None => HttpResponse::NotFound().body("Project not found"),
+    }
+}
+
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/projects")
@@ -177,6 +960,18 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/projects/{id}")
             .route(web::get().to(get_project_by_id))
+            .route(web::put().to(update_project))
+            .route(web::delete().to(delete_project))
+    );
+
+    cfg.service(
+        web::resource("/projects/{id}/status")
+            .route(web::get().to(get_project_status))
+    );
+
+    cfg.service(
+        web::resource("/ready")
+            .route(web::get().to(readiness))
     );
 
     cfg.service(
@@ -188,4 +983,19 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
         web::resource("/projects/{id}/files")
             .route(web::get().to(get_files_by_project_id))
     );
+
+    cfg.service(
+        web::resource("/projects/{id}/files/{file_id}")
+            .route(web::delete().to(delete_file))
+    );
+
+    cfg.service(
+        web::resource("/projects/{id}/files/{file_id}/versions")
+            .route(web::get().to(get_file_versions))
+    );
+
+    cfg.service(
+        web::resource("/projects/{id}/files/{file_id}/diff")
+            .route(web::get().to(diff_file_versions))
+    );
 }
@@ -0,0 +1,22 @@
+use actix_web::{web, HttpResponse};
+use sqlx::{SqlitePool};
+use crate::providers::usage::{self, UsageFilter};
+
+/// Tokens used per day, project and model, with their estimated cost. Filtered by `from` and `to`
+/// (inclusive `YYYY-MM-DD` days), `project_id` and `user_id`.
+pub async fn get_usage(db_pool: web::Data<SqlitePool>, filter: web::Query<UsageFilter>) -> HttpResponse {
+    match usage::report(&db_pool, &filter).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().body("Something went wrong")
+        }
+    }
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/usage")
+            .route(web::get().to(get_usage))
+    );
+}
@@ -0,0 +1,239 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use sqlx::{SqlitePool};
+use sqlx::Acquire;
+use tokio::sync::Notify;
+use crate::handlers::embedding_handler::{run_embeddings_and_store, EmbedError};
+use crate::memory_management::project_manager::ProjectManager;
+use crate::models::embedding_job::EmbeddingJob;
+
+pub const DEFAULT_WORKERS: usize = 2;
+// Workers also look for jobs this often, in case a wakeup got lost
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Completed => "completed",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+const JOB_COLUMNS: &str = "id, project_id, file_id, status, chunks_done, chunks_total, tokens, force, user_id, error, created_at, started_at, finished_at";
+
+/// The running side of a job, handed to `run_embeddings_and_store` so it can record its progress.
+pub struct JobHandle {
+    id: i64,
+    db_pool: SqlitePool,
+}
+
+impl JobHandle {
+    /// Records progress. Returns false once the job has been cancelled, at which point the work should stop.
+    pub async fn report(&self, chunks_done: usize, chunks_total: usize, tokens: usize) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE embedding_job SET chunks_done = ?, chunks_total = ?, tokens = ? WHERE id = ? AND status = 'running'")
+            .bind(chunks_done as i64)
+            .bind(chunks_total as i64)
+            .bind(tokens as i64)
+            .bind(self.id)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct Claimed {
+    id: i64,
+    file_id: i64,
+    force: bool,
+    user_id: Option<i64>,
+}
+
+/// Embedding jobs persisted in `embedding_job` and worked off by a pool of background workers. A job's state lives
+/// only in the database, so jobs interrupted by a restart are picked up again by `resume`.
+#[derive(Clone)]
+pub struct JobQueue {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    db_pool: SqlitePool,
+    project_manager: Arc<Mutex<ProjectManager>>,
+    notify: Notify,
+}
+
+impl JobQueue {
+    pub fn new(db_pool: SqlitePool, project_manager: Arc<Mutex<ProjectManager>>) -> JobQueue {
+        JobQueue { inner: Arc::new(Inner { db_pool, project_manager, notify: Notify::new() }) }
+    }
+
+    /// Queues embedding of a file, with its tokens counted against `user_id`. A file that already has a queued or
+    /// running job gets that job's id back; a forced request turns a queued job into a forced one.
+    pub async fn enqueue(&self, project_id: i64, file_id: i64, force: bool, user_id: Option<i64>) -> Result<i64, sqlx::Error> {
+        let mut conn = self.inner.db_pool.acquire().await?;
+        let mut transaction = conn.begin().await?;
+        let existing: Option<i64> = sqlx::query_scalar("SELECT id FROM embedding_job WHERE file_id = ? AND status IN ('queued', 'running')")
+            .bind(file_id)
+            .fetch_optional(&mut transaction)
+            .await?;
+        let id = match existing {
+            Some(id) => {
+                if force {
+                    sqlx::query("UPDATE embedding_job SET force = 1 WHERE id = ? AND status = 'queued'")
+                        .bind(id)
+                        .execute(&mut transaction)
+                        .await?;
+                }
+                id
+            },
+            None => sqlx::query("INSERT INTO embedding_job (project_id, file_id, force, user_id) VALUES (?, ?, ?, ?)")
+                .bind(project_id)
+                .bind(file_id)
+                .bind(force)
+                .bind(user_id)
+                .execute(&mut transaction)
+                .await?
+                .last_insert_rowid(),
+        };
+        transaction.commit().await?;
+        self.inner.notify.notify_one();
+        Ok(id)
+    }
+
+    pub async fn get(&self, job_id: i64) -> Result<Option<EmbeddingJob>, sqlx::Error> {
+        sqlx::query_as(&format!("SELECT {} FROM embedding_job WHERE id = ?", JOB_COLUMNS))
+            .bind(job_id)
+            .fetch_optional(&self.inner.db_pool)
+            .await
+    }
+
+    pub async fn list(&self, project_id: i64) -> Result<Vec<EmbeddingJob>, sqlx::Error> {
+        sqlx::query_as(&format!("SELECT {} FROM embedding_job WHERE project_id = ? ORDER BY id DESC", JOB_COLUMNS))
+            .bind(project_id)
+            .fetch_all(&self.inner.db_pool)
+            .await
+    }
+
+    /// Cancels a job that hasn't finished. A running job stops after the batch it is waiting for; the chunks
+    /// stored so far are kept and reused by the next attempt. Returns false when the job had already finished.
+    pub async fn cancel(&self, job_id: i64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE embedding_job SET status = 'cancelled', finished_at = CURRENT_TIMESTAMP
+            WHERE id = ? AND status IN ('queued', 'running')
+            "#,
+        )
+        .bind(job_id)
+        .execute(&self.inner.db_pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Puts jobs that were running when the server stopped back in the queue.
+    pub async fn resume(&self) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("UPDATE embedding_job SET status = 'queued', started_at = NULL WHERE status = 'running'")
+            .execute(&self.inner.db_pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    pub fn start(&self, workers: usize) {
+        for _ in 0..workers.max(1) {
+            actix_web::rt::spawn(self.clone().work());
+        }
+    }
+
+    async fn work(self) {
+        loop {
+            match self.run_next().await {
+                Ok(true) => {},
+                Ok(false) => {
+                    let _ = tokio::time::timeout(POLL_INTERVAL, self.inner.notify.notified()).await;
+                },
+                Err(e) => {
+                    eprintln!("Embedding job queue error: {}", e);
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    /// Runs the oldest queued job, if there is one.
+    pub async fn run_next(&self) -> Result<bool, sqlx::Error> {
+        let job = match self.claim().await? {
+            Some(job) => job,
+            None => return Ok(false),
+        };
+        let job_id = job.id;
+
+        let handle = JobHandle { id: job_id, db_pool: self.inner.db_pool.clone() };
+        let result = run_embeddings_and_store(&self.inner.project_manager, &self.inner.db_pool, job.file_id, job.force, job.user_id, Some(&handle)).await;
+        let (status, error) = match result {
+            Ok(_) => (JobStatus::Completed, None),
+            Err(EmbedError::Cancelled) => return Ok(true),
+            Err(e) => {
+                eprintln!("Embedding job {} failed: {}", job_id, e);
+                (JobStatus::Failed, Some(e.to_string()))
+            }
+        };
+
+        // A job cancelled in the meantime stays cancelled
+        sqlx::query(
+            r#"
+            UPDATE embedding_job
+            SET status = ?, error = ?, finished_at = CURRENT_TIMESTAMP,
+                chunks_done = CASE WHEN ? = 'completed' THEN chunks_total ELSE chunks_done END
+            WHERE id = ? AND status = 'running'
+            "#,
+        )
+        .bind(status.as_str())
+        .bind(error)
+        .bind(status.as_str())
+        .bind(job_id)
+        .execute(&self.inner.db_pool)
+        .await?;
+        Ok(true)
+    }
+
+    async fn claim(&self) -> Result<Option<Claimed>, sqlx::Error> {
+        loop {
+            let job: Option<Claimed> = sqlx::query_as("SELECT id, file_id, force, user_id FROM embedding_job WHERE status = 'queued' ORDER BY id LIMIT 1")
+                .fetch_optional(&self.inner.db_pool)
+                .await?;
+            let job = match job {
+                Some(job) => job,
+                None => return Ok(None),
+            };
+            // Another worker may have taken it in between, then look again
+            let result = sqlx::query("UPDATE embedding_job SET status = 'running', started_at = CURRENT_TIMESTAMP WHERE id = ? AND status = 'queued'")
+                .bind(job.id)
+                .execute(&self.inner.db_pool)
+                .await?;
+            if result.rows_affected() > 0 {
+                return Ok(Some(job));
+            }
+        }
+    }
+}
+
+/// Number of job workers, from `EMBEDDING_WORKERS`.
+pub fn workers() -> usize {
+    std::env::var("EMBEDDING_WORKERS").ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_WORKERS)
+}
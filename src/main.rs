@@ -3,6 +3,10 @@ mod models;
 mod handlers;
 mod tests;
 mod memory_management;
+mod chunking;
+mod providers;
+mod jobs;
+mod parsing;
 
 use actix_web::{App, HttpServer, web};
 use sqlx::{SqlitePool, sqlite::SqliteConnectOptions, ConnectOptions};
@@ -53,18 +57,47 @@ async fn main() -> std::io::Result<()> {
             .await
             .expect("Failed to create pool.");
     }
-    let mut project_manager = ProjectManager::new(pool.clone());
-    project_manager.init_projects().await;
-    
+    // `summaries_service fsck [--repair]` checks the database against ./project_data and exits. It runs
+    // before the startup recovery below so a plain check changes nothing and still reports interrupted
+    // deletes; only a repair migrates the database first.
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("fsck") {
+        let repair = args.iter().any(|arg| arg == "--repair");
+        if repair {
+            utils::migrations::run(&pool)
+                .await
+                .expect("Failed to migrate database.");
+        }
+        return memory_management::consistency_checker::run_cli(&pool, repair).await;
+    }
+
+    // Databases created by an older init.sql get the columns and tables added since
+    utils::migrations::run(&pool)
+        .await
+        .expect("Failed to migrate database.");
+    // Finish or roll back file deletes that were interrupted by a crash
+    if let Err(e) = utils::file_store::recover_trash(&pool).await {
+        eprintln!("Failed to recover interrupted deletes: {}", e);
+    }
+    // Files uploaded before versioning get a version like every other file
+    if let Err(e) = utils::file_versions::adopt_unversioned(&pool).await {
+        eprintln!("Failed to adopt unversioned files: {}", e);
+    }
+
     // Wrap the project_manager in an Arc<Mutex<...>>
-    let project_manager = web::Data::new(Arc::new(Mutex::new(project_manager)));
-    
-    HttpServer::new(move || {
+    let project_manager = Arc::new(Mutex::new(ProjectManager::new(pool.clone())));
+    let loader_project_manager = project_manager.clone();
+    let job_queue = jobs::JobQueue::new(pool.clone(), project_manager.clone());
+    let worker_queue = job_queue.clone();
+    let project_manager = web::Data::new(project_manager);
+
+    let server = HttpServer::new(move || {
 
         App::new()
             .data(web::JsonConfig::default().limit(10 * 1024 * 1024)) 
             .app_data(web::Data::new(pool.clone()))
             .app_data(project_manager.clone())
+            .app_data(web::Data::new(job_queue.clone()))
             .service(
                 web::resource("/login").route(web::post().to(login))
             )
@@ -75,8 +108,25 @@ async fn main() -> std::io::Result<()> {
             .configure(handlers::user_handler::init_routes)
             .configure(handlers::project_handler::init_routes)
             .configure(handlers::embedding_handler::init_routes)
+            .configure(handlers::admin_handler::init_routes)
+            .configure(handlers::job_handler::init_routes)
+            .configure(handlers::usage_handler::init_routes)
+            .configure(handlers::document_handler::init_routes)
+            .configure(handlers::download_handler::init_routes)
     })
     .bind("0.0.0.0:8000")?
-    .run()
-    .await
+    .run();
+
+    // Projects are loaded in the background once the server is bound; until a project is ready
+    // its endpoints answer 503 and /ready reports the loading progress.
+    // Embedding jobs start once the projects are loaded, jobs interrupted by the last shutdown first.
+    actix_web::rt::spawn(async move {
+        ProjectManager::load_projects(loader_project_manager).await;
+        if let Err(e) = worker_queue.resume().await {
+            eprintln!("Failed to resume embedding jobs: {}", e);
+        }
+        worker_queue.start(jobs::workers());
+    });
+
+    server.await
 }
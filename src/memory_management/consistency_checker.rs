@@ -0,0 +1,571 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use sqlx::{SqlitePool};
+use sqlx::Acquire;
+use serde::Serialize;
+use crate::models::file::File;
+use crate::memory_management::project_manager::{ProjectManager, LoadStatus};
+use crate::utils::file_store::{self, PROJECT_DATA_DIR};
+use crate::utils::hash::sha256_hex;
+
+/// A single way in which SQLite, the files under `./project_data` and the in-memory stores disagree.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Discrepancy {
+    ProjectDirectoryMissing { project_id: i64 },
+    FileMissingOnDisk { project_id: i64, file_id: i64, path: String },
+    VersionMissingOnDisk { project_id: i64, file_id: i64, version: i64, path: String },
+    UntrackedFileOnDisk { project_id: i64, path: String },
+    InterruptedDelete { project_id: i64, file_id: i64 },
+    InterruptedProjectDelete { project_id: i64 },
+    FileWithoutProject { project_id: i64, file_id: i64 },
+    OrphanEmbeddings { file_id: i64, count: i64 },
+    InvalidEmbedding { project_id: i64, file_id: i64, version: i64, start_byte: i64, end_byte: i64 },
+    EmbeddingOutOfBounds { project_id: i64, file_id: i64, version: i64, start_byte: i64, end_byte: i64, file_size: i64 },
+    IncompleteEmbeddings { project_id: i64, file_id: i64, covered_bytes: i64, file_size: i64 },
+    /// Partly embedded, but with chunk rows to resume from or a job working on it; reported, never repaired.
+    EmbeddingInProgress { project_id: i64, file_id: i64, covered_bytes: i64, file_size: i64 },
+    ProjectNotInMemory { project_id: i64 },
+    UnknownProjectInMemory { project_id: i64 },
+    FileIdsMismatch { project_id: i64, missing: Vec<i64>, unexpected: Vec<i64> },
+    EmbeddingCountMismatch { project_id: i64, file_id: i64, database: usize, memory: usize },
+}
+
+#[derive(Serialize, Debug)]
+pub struct FsckReport {
+    pub found: Vec<Discrepancy>,
+    pub remaining: Option<Vec<Discrepancy>>,
+}
+
+#[derive(sqlx::FromRow)]
+struct EmbeddingRow {
+    file_id: i64,
+    version: i64,
+    start_byte: i64,
+    end_byte: i64,
+    embedding: Vec<u8>,
+}
+
+/// Compares `file_entry`, `file_embedding`, the project directories and (when given) the in-memory
+/// stores, returning every discrepancy found. Nothing is modified.
+pub async fn check(db_pool: &SqlitePool, project_manager: Option<&Arc<Mutex<ProjectManager>>>) -> Result<Vec<Discrepancy>, sqlx::Error> {
+    let mut discrepancies = Vec::new();
+
+    let project_ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM projects")
+        .fetch_all(db_pool)
+        .await?;
+    let project_ids: HashSet<i64> = project_ids.into_iter().collect();
+
+    let files: Vec<File> = sqlx::query_as("SELECT id, name, path, project_id, content_hash, embedded_hash, current_version FROM file_entry")
+        .fetch_all(db_pool)
+        .await?;
+
+    let versions: Vec<(i64, i64, String, Option<String>)> = sqlx::query_as("SELECT file_id, version, path, text_path FROM file_version ORDER BY file_id, version")
+        .fetch_all(db_pool)
+        .await?;
+
+    let embeddings: Vec<EmbeddingRow> = sqlx::query_as("SELECT file_id, version, start_byte, end_byte, embedding FROM file_embedding ORDER BY file_id, start_byte")
+        .fetch_all(db_pool)
+        .await?;
+
+    // Files chunked by a run that recorded its chunks, and whether every chunk made it. Such a chunk list is what
+    // the file is checked against, since chunkers may leave bytes out and unfinished runs are resumed from it.
+    let chunked: Vec<(i64, bool)> = sqlx::query_as(
+        r#"
+        SELECT file_chunk.file_id, MIN(file_chunk.status = 'embedded') FROM file_chunk
+        JOIN file_entry ON file_entry.id = file_chunk.file_id AND file_entry.current_version = file_chunk.version
+        GROUP BY file_chunk.file_id
+        "#,
+    )
+    .fetch_all(db_pool)
+    .await?;
+    let chunked: HashMap<i64, bool> = chunked.into_iter().collect();
+    let active_jobs: Vec<i64> = sqlx::query_scalar("SELECT file_id FROM embedding_job WHERE status IN ('queued', 'running')")
+        .fetch_all(db_pool)
+        .await?;
+    let active_jobs: HashSet<i64> = active_jobs.into_iter().collect();
+
+    // Files and directories on disk
+    let mut tracked_paths: HashSet<String> = HashSet::new();
+    let mut file_sizes: HashMap<i64, i64> = HashMap::new();
+    let mut files_by_id: HashMap<i64, &File> = HashMap::new();
+    for file in &files {
+        tracked_paths.insert(normalize_path(&file.path));
+        files_by_id.insert(file.id, file);
+
+        if !project_ids.contains(&file.project_id) {
+            discrepancies.push(Discrepancy::FileWithoutProject { project_id: file.project_id, file_id: file.id });
+            continue;
+        }
+
+        match std::fs::metadata(&file.path) {
+            Ok(metadata) if metadata.is_file() => {
+                file_sizes.insert(file.id, metadata.len() as i64);
+            },
+            _ => discrepancies.push(Discrepancy::FileMissingOnDisk {
+                project_id: file.project_id,
+                file_id: file.id,
+                path: file.path.clone(),
+            }),
+        }
+    }
+
+    for (file_id, version, path, text_path) in &versions {
+        tracked_paths.insert(normalize_path(path));
+        tracked_paths.extend(text_path.as_deref().map(normalize_path));
+        let file = match files_by_id.get(file_id) {
+            Some(file) => file,
+            None => continue,
+        };
+        // Chunks of a parsed document are ranges of its extracted text
+        if let (Some(text_path), true) = (text_path, *version == file.current_version) {
+            if let Ok(metadata) = std::fs::metadata(text_path) {
+                file_sizes.insert(*file_id, metadata.len() as i64);
+            } else {
+                file_sizes.remove(file_id);
+            }
+        }
+        // The current version is covered by the file_entry path check above
+        if *version != file.current_version && !Path::new(path).is_file() {
+            discrepancies.push(Discrepancy::VersionMissingOnDisk {
+                project_id: file.project_id,
+                file_id: *file_id,
+                version: *version,
+                path: path.clone(),
+            });
+        }
+    }
+
+    let mut sorted_project_ids: Vec<i64> = project_ids.iter().cloned().collect();
+    sorted_project_ids.sort();
+    for project_id in &sorted_project_ids {
+        let project_dir = format!("{}/{}", PROJECT_DATA_DIR, project_id);
+        let entries = match std::fs::read_dir(&project_dir) {
+            Ok(entries) => entries,
+            Err(_) => {
+                discrepancies.push(Discrepancy::ProjectDirectoryMissing { project_id: *project_id });
+                continue;
+            }
+        };
+
+        // Stored files sit either directly in the project directory or under files/{file_id}/
+        let mut stored: Vec<String> = entries.flatten()
+            .filter(|entry| entry.path().is_file())
+            .map(|entry| format!("{}/{}", project_dir, entry.file_name().to_string_lossy()))
+            .collect();
+        if let Ok(file_dirs) = std::fs::read_dir(format!("{}/files", project_dir)) {
+            for file_dir in file_dirs.flatten() {
+                let file_dir_name = file_dir.file_name().to_string_lossy().to_string();
+                for entry in std::fs::read_dir(file_dir.path()).into_iter().flatten().flatten() {
+                    if entry.path().is_file() {
+                        stored.push(format!("{}/files/{}/{}", project_dir, file_dir_name, entry.file_name().to_string_lossy()));
+                    }
+                }
+            }
+        }
+
+        let mut untracked: Vec<String> = stored.into_iter()
+            .filter(|path| !tracked_paths.contains(&normalize_path(path)))
+            .collect();
+        untracked.sort();
+        for path in untracked {
+            discrepancies.push(Discrepancy::UntrackedFileOnDisk { project_id: *project_id, path });
+        }
+    }
+
+    for project_id in file_store::list_project_trash() {
+        discrepancies.push(Discrepancy::InterruptedProjectDelete { project_id });
+    }
+    for (project_id, file_id) in file_store::list_trash() {
+        discrepancies.push(Discrepancy::InterruptedDelete { project_id, file_id });
+    }
+
+    // Embedding rows
+    let mut database_counts: HashMap<i64, usize> = HashMap::new();
+    let mut orphan_counts: HashMap<i64, i64> = HashMap::new();
+    let mut coverage: HashMap<i64, i64> = HashMap::new();
+    let mut gaps: HashSet<i64> = HashSet::new();
+    for row in &embeddings {
+        let file = match files_by_id.get(&row.file_id) {
+            Some(file) => file,
+            None => {
+                *orphan_counts.entry(row.file_id).or_insert(0) += 1;
+                continue;
+            }
+        };
+
+        if serde_json::from_slice::<Vec<f64>>(&row.embedding).is_err() {
+            discrepancies.push(Discrepancy::InvalidEmbedding {
+                project_id: file.project_id,
+                file_id: row.file_id,
+                version: row.version,
+                start_byte: row.start_byte,
+                end_byte: row.end_byte,
+            });
+            continue;
+        }
+
+        // Only the current version is held in memory and checked against the file on disk
+        if row.version != file.current_version {
+            continue;
+        }
+
+        if let Some(file_size) = file_sizes.get(&row.file_id) {
+            if row.start_byte < 0 || row.end_byte > *file_size || row.start_byte >= row.end_byte {
+                discrepancies.push(Discrepancy::EmbeddingOutOfBounds {
+                    project_id: file.project_id,
+                    file_id: row.file_id,
+                    version: row.version,
+                    start_byte: row.start_byte,
+                    end_byte: row.end_byte,
+                    file_size: *file_size,
+                });
+                continue;
+            }
+        }
+
+        *database_counts.entry(row.file_id).or_insert(0) += 1;
+
+        // Rows are ordered by start_byte, so a chunk starting past what is covered so far is a gap.
+        let covered = coverage.entry(row.file_id).or_insert(0);
+        if row.start_byte > *covered {
+            gaps.insert(row.file_id);
+        }
+        *covered = std::cmp::max(*covered, row.end_byte);
+    }
+
+    let mut orphan_ids: Vec<i64> = orphan_counts.keys().cloned().collect();
+    orphan_ids.sort();
+    for file_id in orphan_ids {
+        discrepancies.push(Discrepancy::OrphanEmbeddings { file_id, count: orphan_counts[&file_id] });
+    }
+
+    let mut covered_ids: Vec<i64> = coverage.keys().cloned().collect();
+    covered_ids.sort();
+    for file_id in covered_ids {
+        let covered_bytes = coverage[&file_id];
+        if let Some(file_size) = file_sizes.get(&file_id) {
+            let complete = match chunked.get(&file_id) {
+                Some(all_embedded) => *all_embedded,
+                None => !gaps.contains(&file_id) && covered_bytes >= *file_size,
+            };
+            if complete {
+                continue;
+            }
+            let project_id = files_by_id[&file_id].project_id;
+            if chunked.contains_key(&file_id) || active_jobs.contains(&file_id) {
+                discrepancies.push(Discrepancy::EmbeddingInProgress { project_id, file_id, covered_bytes, file_size: *file_size });
+            } else {
+                discrepancies.push(Discrepancy::IncompleteEmbeddings { project_id, file_id, covered_bytes, file_size: *file_size });
+            }
+        }
+    }
+
+    // In-memory stores
+    if let Some(project_manager) = project_manager {
+        let (snapshot, load_status) = {
+            let project_manager = project_manager.lock().unwrap();
+            (project_manager.snapshot(), project_manager.get_load_statuses())
+        };
+
+        for project_id in &sorted_project_ids {
+            if load_status.get(project_id) == Some(&LoadStatus::Loading) {
+                continue;
+            }
+            let store = match snapshot.get(project_id) {
+                Some(store) => store,
+                None => {
+                    discrepancies.push(Discrepancy::ProjectNotInMemory { project_id: *project_id });
+                    continue;
+                }
+            };
+
+            let expected: HashSet<i64> = files.iter()
+                .filter(|file| file.project_id == *project_id)
+                .map(|file| file.id)
+                .collect();
+            let actual: HashSet<i64> = store.file_ids.iter().cloned().collect();
+            let mut missing: Vec<i64> = expected.difference(&actual).cloned().collect();
+            let mut unexpected: Vec<i64> = actual.difference(&expected).cloned().collect();
+            if !missing.is_empty() || !unexpected.is_empty() || actual.len() != store.file_ids.len() {
+                missing.sort();
+                unexpected.sort();
+                discrepancies.push(Discrepancy::FileIdsMismatch { project_id: *project_id, missing, unexpected });
+            }
+
+            let mut compared: Vec<i64> = expected.iter()
+                .chain(store.embedding_counts.keys())
+                .cloned()
+                .collect::<HashSet<i64>>()
+                .into_iter()
+                .collect();
+            compared.sort();
+            for file_id in compared {
+                let database = if expected.contains(&file_id) { *database_counts.get(&file_id).unwrap_or(&0) } else { 0 };
+                let memory = *store.embedding_counts.get(&file_id).unwrap_or(&0);
+                if database != memory {
+                    discrepancies.push(Discrepancy::EmbeddingCountMismatch { project_id: *project_id, file_id, database, memory });
+                }
+            }
+        }
+
+        let mut memory_ids: Vec<i64> = snapshot.keys().cloned().collect();
+        memory_ids.sort();
+        for project_id in memory_ids {
+            if !project_ids.contains(&project_id) {
+                discrepancies.push(Discrepancy::UnknownProjectInMemory { project_id });
+            }
+        }
+    }
+
+    Ok(discrepancies)
+}
+
+/// Fixes the given discrepancies. The database and disk are repaired first; every project they
+/// touched is then reloaded into memory so the stores end up matching the database again.
+pub async fn repair(db_pool: &SqlitePool, project_manager: Option<&Arc<Mutex<ProjectManager>>>, discrepancies: &[Discrepancy]) -> Result<(), sqlx::Error> {
+    let mut conn = db_pool.acquire().await?;
+    let mut transaction = conn.begin().await?;
+    let mut affected_projects: HashSet<i64> = HashSet::new();
+    let mut removed_projects: HashSet<i64> = HashSet::new();
+    let mut stray_files: Vec<String> = Vec::new();
+    let mut moved_files: Vec<(std::path::PathBuf, String)> = Vec::new();
+
+    let result = async {
+        for discrepancy in discrepancies {
+            match discrepancy {
+                Discrepancy::ProjectDirectoryMissing { project_id } => {
+                    if let Err(e) = std::fs::create_dir_all(format!("{}/{}", PROJECT_DATA_DIR, project_id)) {
+                        eprintln!("Failed to create project directory: {}", e);
+                    }
+                },
+                Discrepancy::FileMissingOnDisk { project_id, file_id, .. } => {
+                    delete_file_rows(&mut transaction, *file_id).await?;
+                    affected_projects.insert(*project_id);
+                },
+                Discrepancy::FileWithoutProject { file_id, .. } => {
+                    let path: Option<String> = sqlx::query_scalar("SELECT path FROM file_entry WHERE id = ?")
+                        .bind(file_id)
+                        .fetch_optional(&mut transaction)
+                        .await?;
+                    delete_file_rows(&mut transaction, *file_id).await?;
+                    stray_files.extend(path);
+                },
+                Discrepancy::UntrackedFileOnDisk { project_id, path } => {
+                    // Adopted as version 1 of a new file, moved into the versioned layout
+                    let bytes = match std::fs::read(path) {
+                        Ok(bytes) => bytes,
+                        Err(e) => {
+                            eprintln!("Failed to read {}: {}", path, e);
+                            continue;
+                        }
+                    };
+                    let content_hash = sha256_hex(&bytes);
+                    let name = Path::new(path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+                    let taken: Option<i64> = sqlx::query_scalar("SELECT id FROM file_entry WHERE project_id = ? AND name = ?")
+                        .bind(project_id)
+                        .bind(&name)
+                        .fetch_optional(&mut transaction)
+                        .await?;
+                    if let Some(file_id) = taken {
+                        // Names are unique within a project; left on disk for the project's owner to sort out
+                        eprintln!("Not adopting {}: file {} has the same name", path, file_id);
+                        continue;
+                    }
+                    let result = sqlx::query("INSERT INTO file_entry (name, path, project_id, content_hash, current_version) VALUES (?, '', ?, ?, 1)")
+                        .bind(&name)
+                        .bind(project_id)
+                        .bind(&content_hash)
+                        .execute(&mut transaction)
+                        .await?;
+                    let file_id = result.last_insert_rowid();
+
+                    let stored_path = file_store::version_path(*project_id, file_id, 1, &name);
+                    if let Err(e) = std::fs::create_dir_all(file_store::file_dir(*project_id, file_id))
+                        .and_then(|_| std::fs::rename(path, &stored_path)) {
+                        eprintln!("Failed to move {}: {}", path, e);
+                        return Err(sqlx::Error::Io(e));
+                    }
+                    moved_files.push((stored_path.clone(), path.clone()));
+                    let stored_path = stored_path.to_string_lossy().to_string();
+
+                    sqlx::query("INSERT INTO file_version (file_id, version, path, content_hash, size) VALUES (?, 1, ?, ?, ?)")
+                        .bind(file_id)
+                        .bind(&stored_path)
+                        .bind(&content_hash)
+                        .bind(bytes.len() as i64)
+                        .execute(&mut transaction)
+                        .await?;
+                    sqlx::query("UPDATE file_entry SET path = ? WHERE id = ?")
+                        .bind(&stored_path)
+                        .bind(file_id)
+                        .execute(&mut transaction)
+                        .await?;
+                    affected_projects.insert(*project_id);
+                },
+                Discrepancy::VersionMissingOnDisk { project_id, file_id, version, .. } => {
+                    sqlx::query("DELETE FROM file_embedding WHERE file_id = ? AND version = ?")
+                        .bind(file_id)
+                        .bind(version)
+                        .execute(&mut transaction)
+                        .await?;
+                    sqlx::query("DELETE FROM file_chunk WHERE file_id = ? AND version = ?")
+                        .bind(file_id)
+                        .bind(version)
+                        .execute(&mut transaction)
+                        .await?;
+                    sqlx::query("DELETE FROM file_version WHERE file_id = ? AND version = ?")
+                        .bind(file_id)
+                        .bind(version)
+                        .execute(&mut transaction)
+                        .await?;
+                    affected_projects.insert(*project_id);
+                },
+                Discrepancy::InterruptedDelete { project_id, file_id } => {
+                    file_store::recover_trashed_file(db_pool, *project_id, *file_id).await?;
+                    affected_projects.insert(*project_id);
+                },
+                Discrepancy::InterruptedProjectDelete { project_id } => {
+                    file_store::recover_trashed_project(db_pool, *project_id).await?;
+                    affected_projects.insert(*project_id);
+                },
+                Discrepancy::OrphanEmbeddings { file_id, .. } => {
+                    sqlx::query("DELETE FROM file_embedding WHERE file_id = ?")
+                        .bind(file_id)
+                        .execute(&mut transaction)
+                        .await?;
+                },
+                Discrepancy::InvalidEmbedding { project_id, file_id, version, start_byte, end_byte }
+                | Discrepancy::EmbeddingOutOfBounds { project_id, file_id, version, start_byte, end_byte, .. } => {
+                    sqlx::query("DELETE FROM file_embedding WHERE file_id = ? AND version = ? AND start_byte = ? AND end_byte = ?")
+                        .bind(file_id)
+                        .bind(version)
+                        .bind(start_byte)
+                        .bind(end_byte)
+                        .execute(&mut transaction)
+                        .await?;
+                    affected_projects.insert(*project_id);
+                },
+                Discrepancy::IncompleteEmbeddings { project_id, file_id, .. } => {
+                    // Drop the partial set so the file can be embedded again from scratch.
+                    sqlx::query("DELETE FROM file_embedding WHERE file_id = ? AND version = (SELECT current_version FROM file_entry WHERE id = ?)")
+                        .bind(file_id)
+                        .bind(file_id)
+                        .execute(&mut transaction)
+                        .await?;
+                    affected_projects.insert(*project_id);
+                },
+                Discrepancy::EmbeddingInProgress { .. } => {},
+                Discrepancy::ProjectNotInMemory { project_id }
+                | Discrepancy::FileIdsMismatch { project_id, .. }
+                | Discrepancy::EmbeddingCountMismatch { project_id, .. } => {
+                    affected_projects.insert(*project_id);
+                },
+                Discrepancy::UnknownProjectInMemory { project_id } => {
+                    removed_projects.insert(*project_id);
+                },
+            }
+        }
+        Ok::<(), sqlx::Error>(())
+    }.await;
+    let result = match result {
+        Ok(()) => transaction.commit().await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        // Put adopted files back where they were found so the next run sees the same state
+        for (stored_path, original_path) in moved_files {
+            let _ = std::fs::rename(stored_path, original_path);
+        }
+        return Err(e);
+    }
+
+    for path in stray_files {
+        if let Err(e) = std::fs::remove_file(&path) {
+            eprintln!("Failed to remove {}: {}", path, e);
+        }
+    }
+
+    if let Some(project_manager) = project_manager {
+        for project_id in removed_projects {
+            project_manager.lock().unwrap().remove_project(project_id);
+        }
+        let mut affected_projects: Vec<i64> = affected_projects.into_iter().collect();
+        affected_projects.sort();
+        for project_id in affected_projects {
+            if let Err(e) = ProjectManager::reload_project(project_manager.clone(), db_pool.clone(), project_id).await {
+                eprintln!("Failed to reload project {}: {}", project_id, e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the checker, optionally repairs what it found, and reports both the discrepancies found and,
+/// after a repair, the ones that remain.
+pub async fn run(db_pool: &SqlitePool, project_manager: Option<&Arc<Mutex<ProjectManager>>>, repair_found: bool) -> Result<FsckReport, sqlx::Error> {
+    // A repair waits for deletes in progress, whose trash would otherwise look interrupted
+    let _trash_guard = match repair_found {
+        true => Some(file_store::TRASH_LOCK.write().await),
+        false => None,
+    };
+    let found = check(db_pool, project_manager).await?;
+    if !repair_found || found.is_empty() {
+        return Ok(FsckReport { found, remaining: None });
+    }
+
+    repair(db_pool, project_manager, &found).await?;
+    let remaining = check(db_pool, project_manager).await?;
+    Ok(FsckReport { found, remaining: Some(remaining) })
+}
+
+/// Entry point for `summaries_service fsck [--repair]`. The in-memory stores belong to the running
+/// server, so only the database and the files on disk are checked here.
+pub async fn run_cli(db_pool: &SqlitePool, repair_found: bool) -> std::io::Result<()> {
+    match run(db_pool, None, repair_found).await {
+        Ok(report) => {
+            for discrepancy in &report.found {
+                println!("{}", serde_json::to_string(discrepancy).unwrap());
+            }
+            println!("{} discrepancies found", report.found.len());
+            if let Some(remaining) = &report.remaining {
+                println!("{} discrepancies remaining after repair", remaining.len());
+            }
+            Ok(())
+        },
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            Err(std::io::Error::other(e.to_string()))
+        }
+    }
+}
+
+async fn delete_file_rows(transaction: &mut sqlx::Transaction<'_, sqlx::Sqlite>, file_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM embedding_job WHERE file_id = ?")
+        .bind(file_id)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query("DELETE FROM file_chunk WHERE file_id = ?")
+        .bind(file_id)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query("DELETE FROM file_embedding WHERE file_id = ?")
+        .bind(file_id)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query("DELETE FROM file_version WHERE file_id = ?")
+        .bind(file_id)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query("DELETE FROM file_entry WHERE id = ?")
+        .bind(file_id)
+        .execute(&mut *transaction)
+        .await?;
+    Ok(())
+}
+
+fn normalize_path(path: &str) -> String {
+    path.trim_start_matches("./").to_string()
+}
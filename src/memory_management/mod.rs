@@ -1,2 +1,3 @@
 pub mod project_manager;
-pub mod project_store;
\ No newline at end of file
+pub mod project_store;
+pub mod consistency_checker;
\ No newline at end of file
@@ -2,26 +2,45 @@ use std::collections::HashMap;
 use crate::memory_management::project_store::ProjectStore;
 use sqlx::{SqlitePool};
 use sqlx::Acquire;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use actix_web::{web, Error, HttpResponse};
-use crate::memory_management::project_store::Embedding;
+use crate::memory_management::project_store::{self, Embedding, IndexType};
+use futures::future::join_all;
+use std::sync::{Arc, Mutex};
+use crate::utils::file_versions::StoreStatus;
 
 pub struct ProjectManager {
     projects: HashMap<i64, ProjectStore>,
+    load_status: HashMap<i64, LoadStatus>,
+    initialized: bool,
     dbPool: SqlitePool
 }
 
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", content = "error", rename_all = "lowercase")]
+pub enum LoadStatus {
+    Loading,
+    Ready,
+    Failed(String)
+}
+
+pub struct StoreSnapshot {
+    pub file_ids: Vec<i64>,
+    pub embedding_counts: HashMap<i64, usize>
+}
+
 #[derive(Deserialize, Debug, sqlx::FromRow)]
 struct ProjectQueryResult {
     id: i64,
-    auto_load: bool,
     name: String,
-    file_ids: String
+    index_type: String
 }
 
 #[derive(Deserialize, Debug, sqlx::FromRow)]
 struct EmbeddingResultQuery {
     file_id: i64,
+    version: i64,
     start_byte: i64,
     end_byte: i64,
     embedding: Vec<u8>
@@ -31,127 +50,207 @@ impl ProjectManager {
     pub fn new(dbPool: SqlitePool) -> ProjectManager {
         ProjectManager {
             projects: HashMap::new(),
+            load_status: HashMap::new(),
+            initialized: false,
             dbPool: dbPool
         }
 
 
     }
 
-    pub async fn init_projects(&mut self) {
-        let mut conn = self.dbPool.acquire().await.unwrap();
+    /// Loads every project from the database into memory. Projects are loaded concurrently and the
+    /// manager lock is only taken to record progress, so requests can be served while loading runs.
+    pub async fn load_projects(project_manager: Arc<Mutex<ProjectManager>>) {
+        let db_pool = project_manager.lock().unwrap().dbPool.clone();
         let result: Result<Vec<ProjectQueryResult>, sqlx::Error> = sqlx::query_as(
             r#"
-            SELECT projects.id, projects.auto_load, projects.name, GROUP_CONCAT(file_entry.id) as file_ids
-            FROM projects
-            LEFT JOIN file_entry ON projects.id = file_entry.project_id
-            GROUP BY projects.id;            
-            "#,     
+            SELECT id, name, index_type FROM projects
+            "#,
         )
-        .fetch_all(&mut conn)
+        .fetch_all(&db_pool)
         .await;
-        
-        for project in result.unwrap() {
-            println!("Loading Project id to memory: {}", project.name);
-            let mut conn = self.dbPool.acquire().await.unwrap();
-            let mut embeddings = Vec::<Embedding>::new();
-
-            let result: Result<Vec<EmbeddingResultQuery>, sqlx::Error> = sqlx::query_as(
-                r#"
-                SELECT 
-                    projects.id, 
-                    projects.name, 
-                    file_entry.id as file_id,
-                    file_embedding.start_byte,
-                    file_embedding.end_byte,
-                    file_embedding.embedding
-                FROM projects
-                JOIN file_entry ON projects.id = file_entry.project_id
-                JOIN file_embedding ON file_entry.id = file_embedding.file_id
-                WHERE projects.id = ?
-                "#,     
-            )
-            .bind(project.id)
-            .fetch_all(&mut conn)
-            .await;     
-
-            let file_ids: Vec<i64> = project.file_ids.split(",").map(|x| x.parse::<i64>().unwrap()).collect();
-            for embedding in result.unwrap() {
-                let embedding_str = String::from_utf8(embedding.embedding).unwrap();
-                let data: Vec<f64> = serde_json::from_str(&embedding_str).unwrap();
-
-                let insert_embedding = Embedding {
-                    file_id: embedding.file_id,
-                    start_byte: embedding.start_byte,
-                    end_byte: embedding.end_byte,
-                    embedding: data
-                };
-
-                embeddings.push(insert_embedding);
+
+        let projects = match result {
+            Ok(projects) => projects,
+            Err(e) => {
+                eprintln!("Database error while listing projects: {}", e);
+                Vec::new()
             }
+        };
 
-            let project_store = ProjectStore::new(project.name.clone(), project.id, file_ids, true, embeddings);
-            self.add_project(project.id, project_store);
-            
+        {
+            let mut project_manager = project_manager.lock().unwrap();
+            for project in &projects {
+                project_manager.load_status.insert(project.id, LoadStatus::Loading);
+            }
+            project_manager.initialized = true;
         }
+
+        let futures = projects.into_iter().map(|project| {
+            let project_manager = project_manager.clone();
+            let db_pool = db_pool.clone();
+            async move {
+                println!("Loading Project id to memory: {}", project.name);
+                let result = load_project_store(&db_pool, project.id, project.name.clone(), &project.index_type).await;
+                let mut project_manager = project_manager.lock().unwrap();
+                match result {
+                    Ok(project_store) => {
+                        project_manager.add_project(project.id, project_store);
+                        project_manager.load_status.insert(project.id, LoadStatus::Ready);
+                    },
+                    Err(e) => {
+                        eprintln!("Failed to load project {}: {}", project.id, e);
+                        project_manager.load_status.insert(project.id, LoadStatus::Failed(e));
+                    }
+                }
+            }
+        });
+        join_all(futures).await;
     }
-    
-    pub fn get_most_similiar_embedding(&mut self, project_id: i64, embedding: Embedding) -> Embedding {
-        let project_store = self.get_project(project_id).unwrap();
-        let knn = project_store.get_knn(&embedding, 1);
-        project_store.embeddings[knn].clone()
+
+    /// Reloads a single project from the database, replacing whatever is currently held in memory.
+    pub async fn reload_project(project_manager: Arc<Mutex<ProjectManager>>, db_pool: SqlitePool, project_id: i64) -> Result<(), String> {
+        let (name, index_type): (String, String) = sqlx::query_as(
+            r#"
+            SELECT name, index_type FROM projects WHERE id = ?
+            "#,
+        )
+        .bind(project_id)
+        .fetch_one(&db_pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        project_manager.lock().unwrap().load_status.insert(project_id, LoadStatus::Loading);
+        let result = load_project_store(&db_pool, project_id, name, &index_type).await;
+        let mut project_manager = project_manager.lock().unwrap();
+        match result {
+            Ok(project_store) => {
+                project_manager.add_project(project_id, project_store);
+                project_manager.load_status.insert(project_id, LoadStatus::Ready);
+                Ok(())
+            },
+            Err(e) => {
+                project_manager.load_status.insert(project_id, LoadStatus::Failed(e.clone()));
+                Err(e)
+            }
+        }
+    }
+
+    /// True once the project list has been read and no project is still loading.
+    pub fn is_ready(&self) -> bool {
+        self.initialized && !self.load_status.values().any(|status| *status == LoadStatus::Loading)
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
+    pub fn get_load_status(&self, project_id: i64) -> Option<LoadStatus> {
+        self.load_status.get(&project_id).cloned()
+    }
+
+    pub fn get_load_statuses(&self) -> HashMap<i64, LoadStatus> {
+        self.load_status.clone()
+    }
+
+    pub fn get_most_similiar_embedding(&mut self, project_id: i64, embedding: &Embedding) -> Option<Embedding> {
+        let project_store = self.get_project(project_id)?;
+        let knn = project_store.get_knn(embedding, 1)?;
+        Some(project_store.embeddings[knn].clone())
     }
 
 
     pub fn add_blank_project(&mut self, id: i64, name: String) {
-        let project_store = ProjectStore::new(name.clone(), 
+        let project_store = ProjectStore::new(name.clone(),
             id, Vec::new(), true, Vec::new());
         self.add_project(id, project_store);
+        self.load_status.insert(id, LoadStatus::Ready);
     }
 
     pub fn add_file(&mut self, id: i64, file_id: i64) {
-        let project = self.get_project(id).unwrap();
-        project.file_ids.push(id);
+        if let Some(project) = self.get_project(id) {
+            project.file_ids.push(file_id);
+        }
     }
 
-    pub async fn update_embeddings(&mut self, project_id: i64, file_id: i64) {
-        let mut conn = self.dbPool.acquire().await.unwrap();
-        let result: Result<Vec<EmbeddingResultQuery>, sqlx::Error> = sqlx::query_as(
-            r#"
-            SELECT 
-                file_id, start_byte, end_byte, embedding
-            FROM file_embedding
-                WHERE file_id = ?
-            "#,     
-        )
+    pub fn update_project(&mut self, id: i64, name: Option<String>, index_type: Option<IndexType>) {
+        if let Some(project) = self.get_project(id) {
+            if let Some(name) = name {
+                project.name = name;
+            }
+            if let Some(index_type) = index_type {
+                project.set_index_type(index_type);
+            }
+        }
+    }
 
-        .bind(file_id)
-        .fetch_all(&mut conn)
-        .await;
+    /// Replaces the embeddings of one file in the project's store. The index is built on the blocking pool
+    /// without holding the manager lock and swapped in afterwards; when the store changed meanwhile, the
+    /// replacement is applied again on top of the newer embeddings.
+    pub async fn replace_file_embeddings(project_manager: &Arc<Mutex<ProjectManager>>, project_id: i64, file_id: i64, embeddings: Vec<Embedding>) {
+        loop {
+            let (generation, index_type, mut all) = match project_manager.lock().unwrap().get_project(project_id) {
+                Some(project) => project.copy_embeddings(),
+                None => return,
+            };
+            all.retain(|embedding| embedding.file_id != file_id);
+            all.extend(embeddings.iter().cloned());
 
-        for embedding in result.unwrap() {
-            let embedding_str = String::from_utf8(embedding.embedding).unwrap();
-            let data: Vec<f64> = serde_json::from_str(&embedding_str).unwrap();
+            let built = tokio::task::spawn_blocking(move || {
+                let vp_tree = project_store::build_index(index_type, &all);
+                (all, vp_tree)
+            })
+            .await;
+            let (all, vp_tree) = match built {
+                Ok(built) => built,
+                Err(e) => {
+                    eprintln!("Failed to rebuild the index of project {}: {}", project_id, e);
+                    return;
+                }
+            };
 
-            let insert_embedding = Embedding {
-                file_id: embedding.file_id,
-                start_byte: embedding.start_byte,
-                end_byte: embedding.end_byte,
-                embedding: data
+            // A project deleted meanwhile has nothing left to update
+            let done = match project_manager.lock().unwrap().get_project(project_id) {
+                Some(project) => project.swap_index(generation, all, vp_tree),
+                None => true,
             };
+            if done {
+                return;
+            }
+        }
+    }
 
-            let mut project = self.get_project(project_id).unwrap();
-            project.embeddings.push(insert_embedding);
+    /// Makes the project's store follow a version just stored for a file.
+    pub async fn track_version(project_manager: &Arc<Mutex<ProjectManager>>, project_id: i64, file_id: i64, status: StoreStatus) {
+        match status {
+            StoreStatus::Created => project_manager.lock().unwrap().add_file(project_id, file_id),
+            // Search follows the current version, which has no embeddings until it is embedded
+            StoreStatus::Updated => ProjectManager::replace_file_embeddings(project_manager, project_id, file_id, Vec::new()).await,
+            StoreStatus::Unchanged => {},
         }
     }
 
-    pub fn add_embedding(&mut self, id: i64, embedding: Vec<f64>, file_id: i64, start_byte: i64, end_byte: i64) {
-        let project = self.get_project(id).unwrap();
-        project.embeddings.push(Embedding {
-            embedding: embedding.clone(),
-            start_byte: start_byte,
-            end_byte: end_byte,
-            file_id: file_id
-        })
+    pub async fn remove_file(project_manager: &Arc<Mutex<ProjectManager>>, project_id: i64, file_id: i64) {
+        if let Some(project) = project_manager.lock().unwrap().get_project(project_id) {
+            project.file_ids.retain(|id| *id != file_id);
+        }
+        ProjectManager::replace_file_embeddings(project_manager, project_id, file_id, Vec::new()).await;
+    }
+
+    pub fn remove_project(&mut self, id: i64) {
+        self.projects.remove(&id);
+        self.load_status.remove(&id);
+    }
+
+    /// Summarises what each in-memory store holds so it can be compared against the database.
+    pub fn snapshot(&self) -> HashMap<i64, StoreSnapshot> {
+        self.projects.iter().map(|(id, project)| {
+            let mut embedding_counts = HashMap::new();
+            for embedding in &project.embeddings {
+                *embedding_counts.entry(embedding.file_id).or_insert(0) += 1;
+            }
+            (*id, StoreSnapshot { file_ids: project.file_ids.clone(), embedding_counts })
+        }).collect()
     }
 
     fn add_project(&mut self, id: i64, project_store: ProjectStore) {
@@ -161,4 +260,136 @@ impl ProjectManager {
     fn get_project(&mut self, id: i64) -> Option<&mut ProjectStore> {
         self.projects.get_mut(&id)
     }
-}
\ No newline at end of file
+}
+
+fn parse_embedding(embedding: EmbeddingResultQuery) -> Result<Embedding, String> {
+    let data: Vec<f64> = serde_json::from_slice(&embedding.embedding)
+        .map_err(|e| format!("invalid embedding for file {}: {}", embedding.file_id, e))?;
+
+    Ok(Embedding {
+        file_id: embedding.file_id,
+        start_byte: embedding.start_byte,
+        end_byte: embedding.end_byte,
+        version: embedding.version,
+        embedding: data
+    })
+}
+
+async fn load_project_store(db_pool: &SqlitePool, project_id: i64, name: String, index_type: &str) -> Result<ProjectStore, String> {
+    let index_type = IndexType::parse(index_type)
+        .ok_or_else(|| format!("unknown index type {}", index_type))?;
+
+    let file_ids: Vec<i64> = sqlx::query_scalar(
+        r#"
+        SELECT id FROM file_entry WHERE project_id = ?
+        "#,
+    )
+    .bind(project_id)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let rows: Vec<EmbeddingResultQuery> = sqlx::query_as(
+        r#"
+        SELECT
+            file_entry.id as file_id,
+            file_embedding.version,
+            file_embedding.start_byte,
+            file_embedding.end_byte,
+            file_embedding.embedding
+        FROM file_entry
+        JOIN file_embedding ON file_entry.id = file_embedding.file_id
+            AND file_embedding.version = file_entry.current_version
+        WHERE file_entry.project_id = ?
+        "#,
+    )
+    .bind(project_id)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    // Building the index is CPU bound and takes a while for large projects; it runs on the blocking pool so
+    // projects load in parallel without holding up the server's workers
+    tokio::task::spawn_blocking(move || {
+        let embeddings = rows.into_iter()
+            .map(parse_embedding)
+            .collect::<Result<Vec<Embedding>, String>>()?;
+
+        let mut project_store = ProjectStore::new(name, project_id, file_ids, true, embeddings);
+        if index_type != IndexType::VpTree {
+            project_store.set_index_type(index_type);
+        }
+        Ok(project_store)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Finds the closest chunk within one stored version of a file. Historical versions are not kept in
+/// memory, so their embeddings are read from the database and scanned linearly.
+pub async fn search_file_version(db_pool: &SqlitePool, project_id: i64, file_id: i64, version: i64, embedding: &Embedding) -> Result<Option<Embedding>, String> {
+    let rows: Vec<EmbeddingResultQuery> = sqlx::query_as(
+        r#"
+        SELECT
+            file_embedding.file_id,
+            file_embedding.version,
+            file_embedding.start_byte,
+            file_embedding.end_byte,
+            file_embedding.embedding
+        FROM file_embedding
+        JOIN file_entry ON file_entry.id = file_embedding.file_id
+        WHERE file_entry.project_id = ? AND file_embedding.file_id = ? AND file_embedding.version = ?
+        "#,
+    )
+    .bind(project_id)
+    .bind(file_id)
+    .bind(version)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    closest(rows, embedding)
+}
+
+/// Finds the closest chunk among the current versions of the files whose metadata has every key of `filter`
+/// with the given value. Like historical versions these are scanned from the database.
+pub async fn search_filtered(db_pool: &SqlitePool, project_id: i64, filter: &Map<String, Value>, embedding: &Embedding) -> Result<Option<Embedding>, String> {
+    let mut query = String::from(
+        r#"
+        SELECT
+            file_embedding.file_id,
+            file_embedding.version,
+            file_embedding.start_byte,
+            file_embedding.end_byte,
+            file_embedding.embedding
+        FROM file_embedding
+        JOIN file_entry ON file_entry.id = file_embedding.file_id
+            AND file_embedding.version = file_entry.current_version
+        WHERE file_entry.project_id = ?
+        "#,
+    );
+    for _ in filter {
+        query.push_str(" AND json_extract(file_entry.metadata, ?) = json_extract(?, '$')");
+    }
+    let mut rows = sqlx::query_as::<_, EmbeddingResultQuery>(&query).bind(project_id);
+    for (key, value) in filter {
+        rows = rows.bind(format!("$.\"{}\"", key)).bind(value.to_string());
+    }
+    let rows = rows.fetch_all(db_pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    closest(rows, embedding)
+}
+
+fn closest(rows: Vec<EmbeddingResultQuery>, embedding: &Embedding) -> Result<Option<Embedding>, String> {
+    let candidates = rows.into_iter()
+        .map(parse_embedding)
+        .collect::<Result<Vec<Embedding>, String>>()?;
+
+    use vpsearch::MetricSpace;
+    Ok(candidates.into_iter()
+        .map(|candidate| (embedding.distance(&candidate, &()), candidate))
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(_, candidate)| candidate))
+}
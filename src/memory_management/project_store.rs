@@ -14,6 +14,25 @@ pub struct Embedding {
     pub start_byte: i64,
     pub end_byte: i64,
     pub file_id: i64,
+    pub version: i64,
+}
+
+/// How a project's embeddings are searched: a vantage point tree, or an exact linear scan.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexType {
+    VpTree,
+    Flat,
+}
+
+impl IndexType {
+    pub fn parse(value: &str) -> Option<IndexType> {
+        match value {
+            "vptree" => Some(IndexType::VpTree),
+            "flat" => Some(IndexType::Flat),
+            _ => None,
+        }
+    }
 }
 
 pub struct ProjectStore {
@@ -22,20 +41,40 @@ pub struct ProjectStore {
     pub project_id: i64,
     pub file_ids: Vec<i64>,
     pub embeddings: Vec<Embedding>,
+    index_type: IndexType,
     vp_tree: Option<vpsearch::Tree<Embedding>>,
+    // Bumped whenever the embeddings or the index change, so an index built from a copy of the embeddings
+    // is only swapped in if nothing changed meanwhile
+    generation: u64,
+}
+
+/// Cosine similarity of two vectors: 1 for the same direction, 0 when either is all zeros.
+pub fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let n = min(a.len(), b.len());
+    let dot_product = unsafe { ddot(n as i32, a, 1, b, 1) };
+    let a_magnitude = unsafe { dnrm2(n as i32, a, 1) };
+    let b_magnitude = unsafe { dnrm2(n as i32, b, 1) };
+    if a_magnitude == 0.0 || b_magnitude == 0.0 {
+        return 0.0;
+    }
+    dot_product / (a_magnitude * b_magnitude)
+}
+
+/// The index `get_knn` searches for `index_type`: a vantage point tree, or nothing for a linear scan.
+pub fn build_index(index_type: IndexType, embeddings: &[Embedding]) -> Option<vpsearch::Tree<Embedding>> {
+    match index_type {
+        IndexType::VpTree => Some(vpsearch::Tree::new(embeddings)),
+        IndexType::Flat => None,
+    }
 }
 
 impl vpsearch::MetricSpace for Embedding {
     type UserData = ();
     type Distance = f64;
+    /// The angle between the embeddings, so the nearest one is the most similar. Unlike one minus the cosine
+    /// similarity it obeys the triangle inequality, which the vantage point tree's pruning relies on.
     fn distance(&self, other: &Self, _: &Self::UserData) -> f64 {
-        let a = &self.embedding;
-        let b = &other.embedding;
-        let n = min(a.len(), b.len());
-        let dot_product = unsafe { ddot(n as i32, &a, 1, &b, 1) };
-        let a_magnitude = unsafe { dnrm2(n as i32, &a, 1) };
-        let b_magnitude = unsafe { dnrm2(n as i32, &b, 1) };
-        dot_product / (a_magnitude * b_magnitude)
+        cosine_similarity(&self.embedding, &other.embedding).clamp(-1.0, 1.0).acos()
     }
 }
 
@@ -47,16 +86,61 @@ impl ProjectStore {
             file_ids: file_ids,
             in_memory: in_memory,
             embeddings: embeddings,
-            vp_tree: None
+            index_type: IndexType::VpTree,
+            vp_tree: None,
+            generation: 0
         };
 
-        store.vp_tree = Some(vpsearch::Tree::new(&store.embeddings));
+        store.rebuild_index();
         store
     }
 
-    pub fn get_knn(&self, embedding: &Embedding, k: usize) -> usize{
-        let (index, _) = self.vp_tree.as_ref().unwrap().find_nearest(&embedding);
-        return index;
+    pub fn set_index_type(&mut self, index_type: IndexType) {
+        self.index_type = index_type;
+        self.rebuild_index();
+    }
+
+    pub fn rebuild_index(&mut self) {
+        self.vp_tree = build_index(self.index_type, &self.embeddings);
+        self.generation += 1;
+    }
+
+    /// A copy of the embeddings to build a new index from, with what `swap_index` needs to check that the
+    /// store hasn't changed in the meantime.
+    pub fn copy_embeddings(&self) -> (u64, IndexType, Vec<Embedding>) {
+        (self.generation, self.index_type, self.embeddings.clone())
+    }
+
+    /// Replaces the embeddings and their index with ones built from `copy_embeddings` of `generation`.
+    /// Returns false, changing nothing, when the store has changed since.
+    pub fn swap_index(&mut self, generation: u64, embeddings: Vec<Embedding>, vp_tree: Option<vpsearch::Tree<Embedding>>) -> bool {
+        if generation != self.generation {
+            return false;
+        }
+        self.embeddings = embeddings;
+        self.vp_tree = vp_tree;
+        self.generation += 1;
+        true
+    }
+
+    pub fn get_knn(&self, embedding: &Embedding, k: usize) -> Option<usize> {
+        if self.embeddings.is_empty() {
+            return None;
+        }
+        match self.index_type {
+            IndexType::VpTree => {
+                let (index, _) = self.vp_tree.as_ref().unwrap().find_nearest(&embedding);
+                Some(index)
+            },
+            IndexType::Flat => {
+                use vpsearch::MetricSpace;
+                self.embeddings.iter()
+                    .enumerate()
+                    .map(|(index, candidate)| (index, embedding.distance(candidate, &())))
+                    .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+                    .map(|(index, _)| index)
+            }
+        }
     }
 }
 
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::chunking::strategy::ChunkingStrategy;
+
+/// A text document sent as JSON. It is named `name`, or its `external_id` when it has no name.
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct NewDocument {
+    pub name: Option<String>,
+    pub external_id: Option<String>,
+    pub text: String,
+    pub metadata: Option<Map<String, Value>>,
+    pub chunking: Option<ChunkingStrategy>,
+    #[serde(default)]
+    pub embed: bool
+}
+
+/// Replaces the text of a document; its metadata is kept unless given.
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct DocumentUpdate {
+    pub text: String,
+    pub metadata: Option<Map<String, Value>>,
+    pub chunking: Option<ChunkingStrategy>,
+    #[serde(default)]
+    pub embed: bool
+}
@@ -4,5 +4,8 @@ use serde::{Serialize};
 pub struct EmbeddingEntry {
     pub start_byte: i64,
     pub end_byte: i64,
+    pub symbol: Option<String>,
+    pub start_line: Option<i64>,
+    pub end_line: Option<i64>,
     pub embedding: Vec<f64>,
 }
\ No newline at end of file
@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug)]
+pub struct EmbeddingJob {
+    pub id: i64,
+    pub project_id: i64,
+    pub file_id: i64,
+    pub status: String,
+    pub chunks_done: i64,
+    pub chunks_total: i64,
+    pub tokens: i64,
+    pub force: bool,
+    pub user_id: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: String,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>
+}
\ No newline at end of file
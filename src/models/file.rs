@@ -1,9 +1,34 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::types::Json;
 
 #[derive(sqlx::FromRow, Serialize, Deserialize, Debug)]
 pub struct File {
     pub id: i64,
     pub name: String,
     pub path: String,
-    pub project_id: i64
-}
\ No newline at end of file
+    pub project_id: i64,
+    pub content_hash: Option<String>,
+    pub embedded_hash: Option<String>,
+    pub current_version: i64
+}
+
+/// A file as listed in its project, with how far the embedding of its current version got:
+/// `pending`, `partial`, `complete` or `failed`. Imported rows and JSON documents also have the caller's
+/// `external_id` and their `metadata`.
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug)]
+pub struct FileListing {
+    pub id: i64,
+    pub name: String,
+    pub path: String,
+    pub project_id: i64,
+    pub content_hash: Option<String>,
+    pub embedded_hash: Option<String>,
+    pub current_version: i64,
+    pub external_id: Option<String>,
+    pub metadata: Option<Json<Map<String, Value>>>,
+    pub embedding_status: String,
+    pub chunks_total: i64,
+    pub chunks_embedded: i64,
+    pub chunks_failed: i64
+}
@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use crate::chunking::strategy::ChunkingStrategy;
+use crate::parsing::Section;
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug)]
+pub struct FileVersion {
+    pub file_id: i64,
+    pub version: i64,
+    pub path: String,
+    pub content_hash: String,
+    pub size: i64,
+    pub chunking: Option<Json<ChunkingStrategy>>,
+    /// Set once the version has been parsed; `text_path` and `sections` only for formats with extracted text.
+    pub format: Option<String>,
+    pub text_path: Option<String>,
+    pub sections: Option<Json<Vec<Section>>>,
+    pub created_at: String
+}
\ No newline at end of file
@@ -7,4 +7,8 @@ pub mod claims;
 pub mod token_response;
 pub mod reset_password_credentials;
 pub mod file;
-pub mod embedding_entry;
\ No newline at end of file
+pub mod embedding_entry;
+pub mod project_update;
+pub mod file_version;
+pub mod embedding_job;
+pub mod document;
\ No newline at end of file
@@ -1,8 +1,19 @@
 use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use crate::chunking::strategy::ChunkingStrategy;
 
-#[derive(sqlx::FromRow, Deserialize, Serialize)]
+#[derive(sqlx::FromRow, Deserialize, Serialize, Default)]
 pub struct Project {
     pub id: Option<i64>,
     pub name: String,
-    pub description: String
+    pub description: String,
+    pub auto_load: Option<bool>,
+    pub index_type: Option<String>,
+    pub max_file_versions: Option<i64>,
+    pub embedding_provider: Option<String>,
+    pub embedding_base_url: Option<String>,
+    pub embedding_model: Option<String>,
+    pub embedding_dimensions: Option<i64>,
+    pub chunking: Option<Json<ChunkingStrategy>>,
+    pub monthly_token_quota: Option<i64>
 }
\ No newline at end of file
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use crate::chunking::strategy::ChunkingStrategy;
+
+/// Settings that can be cleared are `Option<Option<T>>`: a missing field leaves the setting alone and an
+/// explicit `null` clears it.
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct ProjectUpdate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub auto_load: Option<bool>,
+    pub index_type: Option<String>,
+    pub max_file_versions: Option<i64>,
+    pub embedding_provider: Option<String>,
+    #[serde(default, with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub embedding_base_url: Option<Option<String>>,
+    #[serde(default, with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub embedding_model: Option<Option<String>>,
+    #[serde(default, with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub embedding_dimensions: Option<Option<i64>>,
+    #[serde(default, with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub chunking: Option<Option<ChunkingStrategy>>,
+    #[serde(default, with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub monthly_token_quota: Option<Option<i64>>
+}
+
+mod nullable {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    // Only called for fields that are present, so `null` becomes `Some(None)`
+    pub fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<T>>, D::Error> {
+        Option::<T>::deserialize(deserializer).map(Some)
+    }
+
+    pub fn serialize<T: Serialize, S: Serializer>(value: &Option<Option<T>>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.as_ref().and_then(Option::as_ref).serialize(serializer)
+    }
+}
@@ -0,0 +1,74 @@
+use std::io::{Cursor, Read};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use super::TextBuilder;
+
+/// `word/document.xml` is read up to this size, so a small archive can't inflate into an unbounded one.
+const MAX_DOCUMENT_XML_BYTES: u64 = 64 * 1024 * 1024;
+
+pub(super) fn is_docx(bytes: &[u8]) -> bool {
+    zip::ZipArchive::new(Cursor::new(bytes))
+        .map(|mut archive| archive.by_name("word/document.xml").is_ok())
+        .unwrap_or(false)
+}
+
+/// One paragraph per block. Paragraphs styled as a title or heading start a section named after them.
+pub(super) fn extract(bytes: &[u8]) -> Result<TextBuilder, String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| format!("not a readable DOCX: {}", e))?;
+    let mut xml = Vec::new();
+    archive.by_name("word/document.xml")
+        .map_err(|e| format!("not a readable DOCX: {}", e))?
+        .take(MAX_DOCUMENT_XML_BYTES + 1)
+        .read_to_end(&mut xml)
+        .map_err(|e| format!("not a readable DOCX: {}", e))?;
+    if xml.len() as u64 > MAX_DOCUMENT_XML_BYTES {
+        return Err(String::from("DOCX text is too large"));
+    }
+
+    let mut reader = Reader::from_reader(xml.as_slice());
+    let mut builder = TextBuilder::default();
+    let mut buf = Vec::new();
+    let mut paragraph = String::new();
+    let mut is_heading = false;
+    let mut in_text = false;
+    loop {
+        let event = reader.read_event_into(&mut buf).map_err(|e| format!("not a readable DOCX: {}", e))?;
+        match event {
+            Event::Start(element) | Event::Empty(element) => match element.name().as_ref() {
+                b"w:p" => {
+                    paragraph.clear();
+                    is_heading = false;
+                },
+                b"w:pStyle" => {
+                    let style = element.try_get_attribute("w:val").ok().flatten()
+                        .map(|value| String::from_utf8_lossy(&value.value).to_lowercase())
+                        .unwrap_or_default();
+                    is_heading = style == "title" || style.starts_with("heading");
+                },
+                b"w:t" => in_text = true,
+                b"w:tab" => paragraph.push('\t'),
+                b"w:br" | b"w:cr" => paragraph.push('\n'),
+                _ => {},
+            },
+            Event::Text(text) if in_text => {
+                let text = text.unescape().map_err(|e| format!("not a readable DOCX: {}", e))?;
+                paragraph.push_str(&text);
+            },
+            Event::End(element) => match element.name().as_ref() {
+                b"w:t" => in_text = false,
+                b"w:p" => {
+                    if is_heading && !paragraph.trim().is_empty() {
+                        builder.start_section(None, Some(paragraph.trim().to_string()));
+                    }
+                    builder.push(paragraph.trim_end());
+                    builder.break_block();
+                },
+                _ => {},
+            },
+            Event::Eof => break,
+            _ => {},
+        }
+        buf.clear();
+    }
+    Ok(builder)
+}
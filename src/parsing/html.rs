@@ -0,0 +1,58 @@
+use scraper::{ElementRef, Html, Node};
+use super::TextBuilder;
+
+/// Elements whose content is never shown as text.
+const SKIPPED: &[&str] = &["head", "script", "style", "noscript", "template", "svg"];
+
+/// Elements that sit on lines of their own.
+const BLOCKS: &[&str] = &[
+    "p", "div", "section", "article", "main", "header", "footer", "nav", "aside", "blockquote", "pre",
+    "ul", "ol", "li", "dl", "dt", "dd", "table", "tr", "form", "fieldset", "figure", "figcaption", "hr",
+];
+
+/// Text as a browser would lay it out, without markup. Every `h1` to `h6` starts a section named after it.
+pub(super) fn extract(source: &str) -> TextBuilder {
+    let document = Html::parse_document(source);
+    let mut builder = TextBuilder::default();
+    walk(document.root_element(), &mut builder, false);
+    builder
+}
+
+fn walk(element: ElementRef, builder: &mut TextBuilder, preformatted: bool) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) if preformatted => builder.push(text),
+            Node::Text(text) => builder.push_collapsed(text),
+            Node::Element(_) => {
+                let child = match ElementRef::wrap(child) {
+                    Some(child) => child,
+                    None => continue,
+                };
+                let name = child.value().name();
+                if SKIPPED.contains(&name) {
+                    continue;
+                }
+                if matches!(name, "h1" | "h2" | "h3" | "h4" | "h5" | "h6") {
+                    let heading = child.text().collect::<Vec<_>>().join(" ");
+                    let heading = heading.split_whitespace().collect::<Vec<_>>().join(" ");
+                    builder.start_section(None, Some(heading.clone()));
+                    builder.push(&heading);
+                    builder.break_block();
+                } else if name == "br" {
+                    builder.break_line();
+                } else if BLOCKS.contains(&name) {
+                    builder.break_line();
+                    walk(child, builder, preformatted || name == "pre");
+                    if name == "p" || name == "pre" {
+                        builder.break_block();
+                    } else {
+                        builder.break_line();
+                    }
+                } else {
+                    walk(child, builder, preformatted);
+                }
+            },
+            _ => {},
+        }
+    }
+}
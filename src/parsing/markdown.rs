@@ -0,0 +1,48 @@
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag};
+use super::TextBuilder;
+
+/// Text without the inline markup. Each heading starts a section named after it and keeps its ATX marker, and
+/// code blocks are kept verbatim between fences, so the Markdown chunking strategy can still tell headings from
+/// `#` lines of code in the extracted text.
+pub(super) fn extract(source: &str) -> TextBuilder {
+    let mut builder = TextBuilder::default();
+    let mut heading: Option<String> = None;
+    for event in Parser::new(source) {
+        match event {
+            Event::Start(Tag::Heading(..)) => heading = Some(String::new()),
+            Event::End(Tag::Heading(level, ..)) => {
+                let text = heading.take().unwrap_or_default();
+                builder.start_section(None, Some(text.clone()));
+                builder.push(&format!("{} {}", "#".repeat(level as usize), text));
+                builder.break_block();
+            },
+            Event::Start(Tag::CodeBlock(kind)) => {
+                builder.break_line();
+                match kind {
+                    CodeBlockKind::Fenced(info) => builder.push(&format!("```{}\n", info)),
+                    CodeBlockKind::Indented => builder.push("```\n"),
+                }
+            },
+            Event::End(Tag::CodeBlock(_)) => {
+                builder.break_line();
+                builder.push("```");
+                builder.break_block();
+            },
+            Event::Text(text) | Event::Code(text) => match heading.as_mut() {
+                Some(heading) => heading.push_str(&text),
+                None => builder.push(&text),
+            },
+            Event::SoftBreak | Event::HardBreak => match heading.as_mut() {
+                Some(heading) => heading.push(' '),
+                None => builder.break_line(),
+            },
+            Event::Start(Tag::Item) => builder.break_line(),
+            Event::End(Tag::Paragraph) | Event::End(Tag::List(_))
+                | Event::End(Tag::BlockQuote) | Event::End(Tag::Table(_)) | Event::Rule => builder.break_block(),
+            Event::End(Tag::Item) | Event::End(Tag::TableRow) | Event::End(Tag::TableHead) => builder.break_line(),
+            Event::End(Tag::TableCell) => builder.push("\t"),
+            _ => {},
+        }
+    }
+    builder
+}
@@ -0,0 +1,167 @@
+//! Extracts the text of uploaded documents, so a PDF or DOCX is embedded as its text rather than its bytes.
+//!
+//! The extracted text is stored next to the original and everything downstream (chunks, byte ranges,
+//! lines) refers to it. Each document also gets a list of sections mapping ranges of the extracted text
+//! back to the page or heading of the original they came from. Plain text and source code are embedded
+//! as stored and have no extracted text.
+
+pub mod pdf;
+pub mod docx;
+pub mod html;
+pub mod markdown;
+pub mod rows;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentFormat {
+    Pdf,
+    Docx,
+    Html,
+    Markdown,
+    Text,
+    Binary,
+}
+
+impl DocumentFormat {
+    /// Goes by the extension first and by the content for names without a known one.
+    pub fn detect(name: &str, bytes: &[u8]) -> DocumentFormat {
+        let extension = name.rsplit_once('.').map(|(_, extension)| extension.to_lowercase()).unwrap_or_default();
+        match extension.as_str() {
+            "pdf" => DocumentFormat::Pdf,
+            "docx" => DocumentFormat::Docx,
+            "html" | "htm" | "xhtml" => DocumentFormat::Html,
+            "md" | "markdown" => DocumentFormat::Markdown,
+            _ if bytes.starts_with(b"%PDF-") => DocumentFormat::Pdf,
+            _ if bytes.starts_with(b"PK\x03\x04") && docx::is_docx(bytes) => DocumentFormat::Docx,
+            _ => match std::str::from_utf8(bytes) {
+                Ok(text) if looks_like_html(text) => DocumentFormat::Html,
+                Ok(_) => DocumentFormat::Text,
+                Err(_) => DocumentFormat::Binary,
+            },
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentFormat::Pdf => "pdf",
+            DocumentFormat::Docx => "docx",
+            DocumentFormat::Html => "html",
+            DocumentFormat::Markdown => "markdown",
+            DocumentFormat::Text => "text",
+            DocumentFormat::Binary => "binary",
+        }
+    }
+}
+
+fn looks_like_html(text: &str) -> bool {
+    let start = text.trim_start().get(..15).unwrap_or("").to_lowercase();
+    start.starts_with("<!doctype html") || start.starts_with("<html")
+}
+
+/// A range of the extracted text and where it came from: a page for PDFs, the heading it falls under for
+/// the other formats. Text before the first heading has no heading.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Section {
+    pub start: usize,
+    pub end: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heading: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ParsedDocument {
+    pub text: String,
+    pub sections: Vec<Section>,
+}
+
+/// Extracts the text of a document. Returns `None` for plain text and binary files, which are embedded as
+/// they are.
+pub fn parse(format: DocumentFormat, bytes: &[u8]) -> Result<Option<ParsedDocument>, String> {
+    let builder = match format {
+        DocumentFormat::Pdf => pdf::extract(bytes)?,
+        DocumentFormat::Docx => docx::extract(bytes)?,
+        DocumentFormat::Html => html::extract(&String::from_utf8_lossy(bytes)),
+        DocumentFormat::Markdown => markdown::extract(&String::from_utf8_lossy(bytes)),
+        DocumentFormat::Text | DocumentFormat::Binary => return Ok(None),
+    };
+    let (text, sections) = builder.finish();
+    Ok(Some(ParsedDocument { text, sections }))
+}
+
+/// Collects the extracted text and its sections as a parser walks a document.
+#[derive(Default)]
+struct TextBuilder {
+    text: String,
+    sections: Vec<Section>,
+}
+
+impl TextBuilder {
+    /// Text pushed from here on belongs to a new section, which starts on a block of its own.
+    fn start_section(&mut self, page: Option<u32>, heading: Option<String>) {
+        self.break_block();
+        self.cover_start();
+        self.end_section();
+        self.sections.push(Section { start: self.text.len(), end: self.text.len(), page, heading });
+    }
+
+    /// Text before the first section gets one without a page or heading.
+    fn cover_start(&mut self) {
+        if self.sections.is_empty() && !self.text.is_empty() {
+            self.sections.push(Section { start: 0, end: 0, page: None, heading: None });
+        }
+    }
+
+    fn end_section(&mut self) {
+        if let Some(section) = self.sections.last_mut() {
+            section.end = self.text.len();
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+
+    /// Pushes text with its runs of whitespace collapsed into single spaces, as a browser renders it.
+    fn push_collapsed(&mut self, text: &str) {
+        for c in text.chars() {
+            if !c.is_whitespace() {
+                self.text.push(c);
+            } else if !self.text.is_empty() && !self.text.ends_with(char::is_whitespace) {
+                self.text.push(' ');
+            }
+        }
+    }
+
+    /// Ends the current line, unless the text is at the start of one already.
+    fn break_line(&mut self) {
+        while self.text.ends_with(' ') || self.text.ends_with('\t') {
+            self.text.pop();
+        }
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+    }
+
+    /// Leaves an empty line between blocks such as paragraphs and pages.
+    fn break_block(&mut self) {
+        self.break_line();
+        if !self.text.is_empty() && !self.text.ends_with("\n\n") {
+            self.text.push('\n');
+        }
+    }
+
+    fn finish(mut self) -> (String, Vec<Section>) {
+        self.break_line();
+        while self.text.ends_with("\n\n") {
+            self.text.pop();
+        }
+        self.cover_start();
+        self.end_section();
+        let sections = self.sections.into_iter().filter(|section| section.start < section.end).collect();
+        (self.text, sections)
+    }
+}
@@ -0,0 +1,25 @@
+use lopdf::Document;
+use super::TextBuilder;
+
+/// One section per page. Pages whose text can't be read (scans, unusual font encodings) are left empty.
+pub(super) fn extract(bytes: &[u8]) -> Result<TextBuilder, String> {
+    let document = Document::load_mem(bytes).map_err(|e| format!("not a readable PDF: {}", e))?;
+    if document.is_encrypted() {
+        return Err(String::from("encrypted PDFs are not supported"));
+    }
+
+    let mut builder = TextBuilder::default();
+    for page in document.get_pages().into_keys() {
+        builder.start_section(Some(page), None);
+        match document.extract_text(&[page]) {
+            Ok(text) => {
+                for line in text.lines() {
+                    builder.push(line.trim_end());
+                    builder.break_line();
+                }
+            },
+            Err(e) => eprintln!("Failed to extract the text of page {}: {}", page, e),
+        }
+    }
+    Ok(builder)
+}
@@ -0,0 +1,113 @@
+//! Reads tabular imports, CSV with a header line or JSON Lines, into rows of named values.
+
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RowFormat {
+    Csv,
+    Jsonl,
+}
+
+impl RowFormat {
+    /// Accepts `csv`, `jsonl` and `ndjson`.
+    pub fn parse(value: &str) -> Option<RowFormat> {
+        match value.trim().to_lowercase().as_str() {
+            "csv" => Some(RowFormat::Csv),
+            "jsonl" | "ndjson" => Some(RowFormat::Jsonl),
+            _ => None,
+        }
+    }
+
+    /// JSON Lines start with an object; anything else is taken for CSV.
+    pub fn detect(bytes: &[u8]) -> RowFormat {
+        match bytes.iter().find(|byte| !byte.is_ascii_whitespace()) {
+            Some(b'{') => RowFormat::Jsonl,
+            _ => RowFormat::Csv,
+        }
+    }
+}
+
+/// A row and the 1-based line it starts on. CSV values are always strings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub line: usize,
+    pub values: Map<String, Value>,
+}
+
+impl Row {
+    /// The value of a column as text; missing columns and nulls have none.
+    pub fn get_text(&self, column: &str) -> Option<String> {
+        match self.values.get(column)? {
+            Value::Null => None,
+            Value::String(value) => Some(value.clone()),
+            value => Some(value.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Rows {
+    pub columns: Vec<String>,
+    pub rows: Vec<Row>,
+    /// Lines that couldn't be read, with the reason.
+    pub invalid: Vec<(usize, String)>,
+}
+
+/// Reads every row. Malformed lines are reported in `invalid` and the rest is still read; only a CSV whose header
+/// can't be read fails as a whole. The columns of JSON Lines are the keys found in any of its rows.
+pub fn read(format: RowFormat, bytes: &[u8]) -> Result<Rows, String> {
+    match format {
+        RowFormat::Csv => read_csv(bytes),
+        RowFormat::Jsonl => Ok(read_jsonl(bytes)),
+    }
+}
+
+fn read_csv(bytes: &[u8]) -> Result<Rows, String> {
+    let mut reader = csv::ReaderBuilder::new().from_reader(bytes);
+    let columns: Vec<String> = reader.headers()
+        .map_err(|e| format!("unreadable header: {}", e))?
+        .iter()
+        .map(|column| column.trim().to_string())
+        .collect();
+
+    let mut rows = Rows { columns, ..Default::default() };
+    for record in reader.records() {
+        match record {
+            Ok(record) => {
+                let line = record.position().map_or(0, |position| position.line() as usize);
+                let values = rows.columns.iter()
+                    .zip(record.iter())
+                    .map(|(column, value)| (column.clone(), Value::String(value.to_string())))
+                    .collect();
+                rows.rows.push(Row { line, values });
+            },
+            Err(e) => {
+                let line = e.position().map_or(0, |position| position.line() as usize);
+                rows.invalid.push((line, e.to_string()));
+            },
+        }
+    }
+    Ok(rows)
+}
+
+fn read_jsonl(bytes: &[u8]) -> Rows {
+    let mut rows = Rows::default();
+    for (i, line) in String::from_utf8_lossy(bytes).lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Value>(line) {
+            Ok(Value::Object(values)) => {
+                for column in values.keys() {
+                    if !rows.columns.contains(column) {
+                        rows.columns.push(column.clone());
+                    }
+                }
+                rows.rows.push(Row { line: i + 1, values });
+            },
+            Ok(_) => rows.invalid.push((i + 1, String::from("not a JSON object"))),
+            Err(e) => rows.invalid.push((i + 1, e.to_string())),
+        }
+    }
+    rows
+}
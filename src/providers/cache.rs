@@ -0,0 +1,192 @@
+use async_trait::async_trait;
+use serde::Serialize;
+use sqlx::{Acquire, SqlitePool};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::utils::hash::sha256_hex;
+use super::{EmbeddingProvider, Metered, ProviderError};
+
+pub const DEFAULT_MAX_ENTRIES: i64 = 100_000;
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+static EVICTIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Most vectors kept in `embedding_cache`, from `EMBEDDING_CACHE_MAX_ENTRIES`. 0 turns the cache off.
+pub fn max_entries() -> i64 {
+    std::env::var("EMBEDDING_CACHE_MAX_ENTRIES").ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_MAX_ENTRIES)
+}
+
+/// Answers inputs that were embedded before with the same model from `embedding_cache`, keyed by the
+/// SHA-256 of the text, and only sends the rest to the provider. The cache is shared by every project, so
+/// boilerplate repeated across files and projects is paid for once. When it grows past `max_entries`, the
+/// least recently used vectors are evicted. A cache that can't be read or written is skipped, never fatal.
+pub struct CachedProvider {
+    inner: Box<dyn EmbeddingProvider>,
+    db_pool: SqlitePool,
+    model: String,
+    max_entries: i64,
+}
+
+impl CachedProvider {
+    /// `model` has to tell apart everything that changes the vectors: endpoint, model and dimensions.
+    pub fn new(inner: Box<dyn EmbeddingProvider>, db_pool: SqlitePool, model: String, max_entries: i64) -> CachedProvider {
+        CachedProvider { inner, db_pool, model, max_entries }
+    }
+
+    async fn lookup(&self, hashes: &[String]) -> Result<HashMap<String, Vec<f64>>, sqlx::Error> {
+        let mut conn = self.db_pool.acquire().await?;
+        let mut transaction = conn.begin().await?;
+        let mut found = HashMap::new();
+        for hash in hashes {
+            if found.contains_key(hash) {
+                continue;
+            }
+            let blob: Option<Vec<u8>> = sqlx::query_scalar("SELECT embedding FROM embedding_cache WHERE model = ? AND text_hash = ?")
+                .bind(&self.model)
+                .bind(hash)
+                .fetch_optional(&mut transaction)
+                .await?;
+            let embedding: Option<Vec<f64>> = blob.and_then(|blob| serde_json::from_slice(&blob).ok());
+            if let Some(embedding) = embedding {
+                sqlx::query("UPDATE embedding_cache SET hits = hits + 1, last_used_at = CURRENT_TIMESTAMP WHERE model = ? AND text_hash = ?")
+                    .bind(&self.model)
+                    .bind(hash)
+                    .execute(&mut transaction)
+                    .await?;
+                found.insert(hash.clone(), embedding);
+            }
+        }
+        transaction.commit().await?;
+        Ok(found)
+    }
+
+    async fn store(&self, entries: &[(&str, &Vec<f64>)]) -> Result<(), sqlx::Error> {
+        let mut conn = self.db_pool.acquire().await?;
+        let mut transaction = conn.begin().await?;
+        for (hash, embedding) in entries {
+            let data = serde_json::to_vec(embedding)
+                .map_err(|_| sqlx::Error::Protocol("Failed to serialize embedding data".into()))?;
+            sqlx::query("INSERT OR REPLACE INTO embedding_cache (model, text_hash, embedding) VALUES (?, ?, ?)")
+                .bind(&self.model)
+                .bind(hash)
+                .bind(&data)
+                .execute(&mut transaction)
+                .await?;
+        }
+        let evicted = sqlx::query(
+            r#"
+            DELETE FROM embedding_cache WHERE rowid IN (
+                SELECT rowid FROM embedding_cache ORDER BY last_used_at, rowid
+                LIMIT MAX((SELECT COUNT(*) FROM embedding_cache) - ?, 0)
+            )
+            "#,
+        )
+        .bind(self.max_entries)
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+        transaction.commit().await?;
+        EVICTIONS.fetch_add(evicted, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for CachedProvider {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.inner.dimensions()
+    }
+
+    fn max_batch_inputs(&self) -> usize {
+        self.inner.max_batch_inputs()
+    }
+
+    fn max_batch_tokens(&self) -> usize {
+        self.inner.max_batch_tokens()
+    }
+
+    async fn embed_batch(&self, inputs: &[String]) -> Result<Vec<Vec<f64>>, ProviderError> {
+        self.embed_batch_metered(inputs).await.map(|metered| metered.embeddings)
+    }
+
+    /// Only the inputs sent to the provider count towards the tokens.
+    async fn embed_batch_metered(&self, inputs: &[String]) -> Result<Metered, ProviderError> {
+        let hashes: Vec<String> = inputs.iter().map(|input| sha256_hex(input.as_bytes())).collect();
+        let mut found = self.lookup(&hashes).await.unwrap_or_else(|e| {
+            eprintln!("Embedding cache lookup failed: {}", e);
+            HashMap::new()
+        });
+
+        let missing: Vec<usize> = (0..inputs.len()).filter(|i| !found.contains_key(&hashes[*i])).collect();
+        HITS.fetch_add((inputs.len() - missing.len()) as u64, Ordering::Relaxed);
+        MISSES.fetch_add(missing.len() as u64, Ordering::Relaxed);
+
+        let mut tokens = 0;
+        if !missing.is_empty() {
+            let missing_inputs: Vec<String> = missing.iter().map(|i| inputs[*i].clone()).collect();
+            let metered = self.inner.embed_batch_metered(&missing_inputs).await?;
+            tokens = metered.tokens;
+            let embeddings = metered.embeddings;
+            let entries: Vec<(&str, &Vec<f64>)> = missing.iter().map(|i| hashes[*i].as_str()).zip(embeddings.iter()).collect();
+            if let Err(e) = self.store(&entries).await {
+                eprintln!("Embedding cache store failed: {}", e);
+            }
+            for (i, embedding) in missing.into_iter().zip(embeddings) {
+                found.insert(hashes[i].clone(), embedding);
+            }
+        }
+
+        Ok(Metered { embeddings: hashes.iter().map(|hash| found[hash].clone()).collect(), tokens })
+    }
+}
+
+/// Cache size and hit rate. `hits`, `misses` and `evictions` count since the server started;
+/// `stored_hits` sums the hits recorded with the cached vectors.
+#[derive(Serialize, Debug)]
+pub struct CacheStats {
+    pub entries: i64,
+    pub size_bytes: i64,
+    pub max_entries: i64,
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_rate: f64,
+    pub evictions: u64,
+    pub stored_hits: i64,
+}
+
+pub async fn stats(db_pool: &SqlitePool) -> Result<CacheStats, sqlx::Error> {
+    let (entries, size_bytes, stored_hits): (i64, i64, i64) = sqlx::query_as(
+        "SELECT COUNT(*), COALESCE(SUM(LENGTH(embedding)), 0), COALESCE(SUM(hits), 0) FROM embedding_cache",
+    )
+    .fetch_one(db_pool)
+    .await?;
+    let hits = HITS.load(Ordering::Relaxed);
+    let misses = MISSES.load(Ordering::Relaxed);
+    Ok(CacheStats {
+        entries,
+        size_bytes,
+        max_entries: max_entries(),
+        hits,
+        misses,
+        hit_rate: if hits + misses == 0 { 0.0 } else { hits as f64 / (hits + misses) as f64 },
+        evictions: EVICTIONS.load(Ordering::Relaxed),
+        stored_hits,
+    })
+}
+
+/// Empties the cache, returning how many vectors were dropped.
+pub async fn clear(db_pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM embedding_cache").execute(db_pool).await?;
+    Ok(result.rows_affected())
+}
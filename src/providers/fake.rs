@@ -0,0 +1,72 @@
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use super::{EmbeddingProvider, ProviderError, FAKE};
+
+pub const DEFAULT_DIMENSIONS: usize = 16;
+pub const MAX_BATCH_INPUTS: usize = 2048;
+pub const MAX_BATCH_TOKENS: usize = 300_000;
+
+/// Offline provider for tests and local development. The vector is derived from a hash of the
+/// input, so the same text always maps to the same unit vector and no network is touched.
+pub struct FakeProvider {
+    dimensions: usize,
+}
+
+impl FakeProvider {
+    pub fn new(dimensions: usize) -> FakeProvider {
+        FakeProvider { dimensions }
+    }
+
+    pub fn vector(&self, input: &str) -> Vec<f64> {
+        let digest = Sha256::digest(input.as_bytes());
+        let mut state = u64::from_le_bytes(digest[..8].try_into().unwrap());
+        let mut vector: Vec<f64> = (0..self.dimensions).map(|_| {
+            // splitmix64
+            state = state.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            z ^= z >> 31;
+            (z as f64 / u64::MAX as f64) * 2.0 - 1.0
+        }).collect();
+
+        let norm = vector.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        vector
+    }
+}
+
+impl Default for FakeProvider {
+    fn default() -> Self {
+        FakeProvider::new(DEFAULT_DIMENSIONS)
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for FakeProvider {
+    fn name(&self) -> &'static str {
+        FAKE
+    }
+
+    fn model(&self) -> &str {
+        FAKE
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        Some(self.dimensions)
+    }
+
+    fn max_batch_inputs(&self) -> usize {
+        MAX_BATCH_INPUTS
+    }
+
+    fn max_batch_tokens(&self) -> usize {
+        MAX_BATCH_TOKENS
+    }
+
+    async fn embed_batch(&self, inputs: &[String]) -> Result<Vec<Vec<f64>>, ProviderError> {
+        Ok(inputs.iter().map(|input| self.vector(input)).collect())
+    }
+}
@@ -0,0 +1,281 @@
+pub mod openai;
+pub mod ollama;
+pub mod fake;
+pub mod resilience;
+pub mod scheduler;
+pub mod cache;
+pub mod usage;
+
+use async_trait::async_trait;
+use sqlx::SqlitePool;
+use std::fmt;
+use std::ops::Range;
+use std::time::Duration;
+use serde::Deserialize;
+
+pub const OPENAI: &str = "openai";
+pub const OPENAI_COMPATIBLE: &str = "openai_compatible";
+pub const OLLAMA: &str = "ollama";
+pub const FAKE: &str = "fake";
+
+/// Turns text into vectors. Every project is bound to one provider, so the corpus and the
+/// queries run against it are always embedded by the same backend.
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn model(&self) -> &str;
+
+    /// The requested output size, or `None` when the model's native size is used.
+    fn dimensions(&self) -> Option<usize>;
+
+    /// Most inputs a single request may carry.
+    fn max_batch_inputs(&self) -> usize;
+
+    /// Most tokens, summed over all inputs, a single request may carry.
+    fn max_batch_tokens(&self) -> usize;
+
+    /// Embeds every input in one request. The result is in input order.
+    async fn embed_batch(&self, inputs: &[String]) -> Result<Vec<Vec<f64>>, ProviderError>;
+
+    /// Like `embed_batch`, along with the tokens the request was billed for. Providers that report their
+    /// usage override this; for the others the tokens are estimated.
+    async fn embed_batch_metered(&self, inputs: &[String]) -> Result<Metered, ProviderError> {
+        let embeddings = self.embed_batch(inputs).await?;
+        Ok(Metered { embeddings, tokens: inputs.iter().map(|input| estimate_tokens(input)).sum() })
+    }
+}
+
+/// Embeddings returned by `embed_batch_metered`.
+#[derive(Debug)]
+pub struct Metered {
+    pub embeddings: Vec<Vec<f64>>,
+    pub tokens: usize,
+}
+
+#[derive(Debug)]
+pub enum ProviderError {
+    /// The project's provider settings can't be used.
+    Config(String),
+    /// The request never got a response (connection refused, timeout, ...).
+    Request(reqwest::Error),
+    /// 429 from the provider.
+    RateLimited { retry_after: Option<Duration>, message: String },
+    /// 5xx from the provider.
+    Unavailable { status: u16, retry_after: Option<Duration>, message: String },
+    /// Any other non-success status; the request itself is at fault and retrying won't help.
+    Rejected { status: u16, message: String },
+    InvalidResponse(String),
+    /// The circuit breaker is open after repeated failures; no request was sent.
+    CircuitOpen { retry_after: Duration },
+}
+
+impl ProviderError {
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ProviderError::Request(e) => !e.is_builder(),
+            ProviderError::RateLimited { .. } | ProviderError::Unavailable { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// How long the provider asked us to wait, if it said.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ProviderError::RateLimited { retry_after, .. } | ProviderError::Unavailable { retry_after, .. } => *retry_after,
+            ProviderError::CircuitOpen { retry_after } => Some(*retry_after),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderError::Config(message) => write!(f, "provider misconfigured: {}", message),
+            ProviderError::Request(e) => write!(f, "request failed: {}", e),
+            ProviderError::RateLimited { message, .. } => write!(f, "rate limited: {}", message),
+            ProviderError::Unavailable { status, message, .. } => write!(f, "provider unavailable ({}): {}", status, message),
+            ProviderError::Rejected { status, message } => write!(f, "provider rejected the request ({}): {}", status, message),
+            ProviderError::InvalidResponse(message) => write!(f, "invalid provider response: {}", message),
+            ProviderError::CircuitOpen { retry_after } => write!(f, "provider circuit open, retry in {}s", retry_after.as_secs()),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: ErrorDetail,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ErrorDetail {
+    Object { message: String },
+    Text(String),
+}
+
+/// Passes successful responses through and turns the rest into a `ProviderError`. The message is taken
+/// from an OpenAI- or Ollama-style `{"error": ...}` body when there is one.
+pub async fn check_response(res: reqwest::Response) -> Result<reqwest::Response, ProviderError> {
+    let status = res.status();
+    if status.is_success() {
+        return Ok(res);
+    }
+    let retry_after = res.headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+    let body = res.text().await.unwrap_or_default();
+    let message = match serde_json::from_str::<ErrorBody>(&body) {
+        Ok(ErrorBody { error: ErrorDetail::Object { message } }) => message,
+        Ok(ErrorBody { error: ErrorDetail::Text(message) }) => message,
+        Err(_) => body,
+    };
+
+    let status = status.as_u16();
+    Err(match status {
+        429 => ProviderError::RateLimited { retry_after, message },
+        500..=599 => ProviderError::Unavailable { status, retry_after, message },
+        _ => ProviderError::Rejected { status, message },
+    })
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        return (seconds >= 0.0 && seconds.is_finite()).then(|| Duration::from_secs_f64(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.signed_duration_since(chrono::Utc::now());
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+impl From<reqwest::Error> for ProviderError {
+    fn from(e: reqwest::Error) -> Self {
+        ProviderError::Request(e)
+    }
+}
+
+/// The embedding columns of a project row. A missing model means the provider's default.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ProviderConfig {
+    pub embedding_provider: String,
+    pub embedding_base_url: Option<String>,
+    pub embedding_model: Option<String>,
+    pub embedding_dimensions: Option<i64>,
+}
+
+pub fn is_known_provider(name: &str) -> bool {
+    matches!(name, OPENAI | OPENAI_COMPATIBLE | OLLAMA | FAKE)
+}
+
+pub fn build_provider(config: &ProviderConfig) -> Result<resilience::ResilientProvider, ProviderError> {
+    let model = config.embedding_model.clone();
+    let dimensions = match config.embedding_dimensions {
+        Some(dimensions) if dimensions < 1 => return Err(ProviderError::Config(format!("invalid embedding_dimensions {}", dimensions))),
+        dimensions => dimensions.map(|dimensions| dimensions as usize),
+    };
+    let inner: Box<dyn EmbeddingProvider> = match config.embedding_provider.as_str() {
+        OPENAI => Box::new(openai::OpenAiProvider::openai(model, dimensions)?),
+        OPENAI_COMPATIBLE => {
+            let base_url = config.embedding_base_url.clone()
+                .ok_or_else(|| ProviderError::Config(String::from("openai_compatible needs an embedding_base_url")))?;
+            Box::new(openai::OpenAiProvider::compatible(base_url, model, dimensions))
+        },
+        OLLAMA => Box::new(ollama::OllamaProvider::new(config.embedding_base_url.clone(), model, dimensions)),
+        FAKE => Box::new(fake::FakeProvider::new(dimensions.unwrap_or(fake::DEFAULT_DIMENSIONS))),
+        other => return Err(ProviderError::Config(format!("unknown embedding provider {}", other))),
+    };
+
+    // Projects that talk to the same endpoint share its rate limits and its circuit breaker
+    let endpoint = format!("{} {}", config.embedding_provider, config.embedding_base_url.as_deref().unwrap_or(""));
+    let resilience = resilience::ResilienceConfig::from_env();
+    let shared = resilience::shared(&endpoint, &resilience);
+    Ok(resilience::ResilientProvider::new(inner, shared, resilience))
+}
+
+pub async fn provider_for_project(db_pool: &SqlitePool, project_id: i64) -> Result<Box<dyn EmbeddingProvider>, ProviderError> {
+    let config: ProviderConfig = sqlx::query_as(
+        r#"
+        SELECT embedding_provider, embedding_base_url, embedding_model, embedding_dimensions FROM projects WHERE id = ?
+        "#,
+    )
+    .bind(project_id)
+    .fetch_one(db_pool)
+    .await
+    .map_err(|e| ProviderError::Config(format!("project {}: {}", project_id, e)))?;
+
+    // Requests wait for the project's turn in the scheduler; cache hits never reach it
+    let provider: Box<dyn EmbeddingProvider> = Box::new(build_provider(&config)?.scheduled(scheduler::global().clone(), project_id));
+    let max_entries = cache::max_entries();
+    if max_entries <= 0 {
+        return Ok(provider);
+    }
+    let model = format!(
+        "{} {} {} {}",
+        provider.name(),
+        config.embedding_base_url.as_deref().unwrap_or(""),
+        provider.model(),
+        provider.dimensions().map_or(String::from("native"), |dimensions| dimensions.to_string()),
+    );
+    Ok(Box::new(cache::CachedProvider::new(provider, db_pool.clone(), model, max_entries)))
+}
+
+/// Rejects a vector whose length doesn't match the configured output size, so a server that ignores
+/// the `dimensions` parameter can't put differently sized vectors into a project.
+pub fn check_dimensions(embedding: Vec<f64>, dimensions: Option<usize>) -> Result<Vec<f64>, ProviderError> {
+    match dimensions {
+        Some(dimensions) if embedding.len() != dimensions => Err(ProviderError::InvalidResponse(
+            format!("expected {} dimensions, got {}", dimensions, embedding.len())
+        )),
+        _ => Ok(embedding),
+    }
+}
+
+/// Puts the embeddings a provider returned back into input order. `indexed` pairs each vector with
+/// the position of its input; every position must be present exactly once.
+pub fn collect_batch(count: usize, indexed: impl IntoIterator<Item = (usize, Vec<f64>)>, dimensions: Option<usize>) -> Result<Vec<Vec<f64>>, ProviderError> {
+    let mut embeddings: Vec<Option<Vec<f64>>> = vec![None; count];
+    for (index, embedding) in indexed {
+        match embeddings.get_mut(index) {
+            Some(slot @ None) => *slot = Some(check_dimensions(embedding, dimensions)?),
+            Some(Some(_)) => return Err(ProviderError::InvalidResponse(format!("duplicate embedding for input {}", index))),
+            None => return Err(ProviderError::InvalidResponse(format!("embedding for unknown input {}", index))),
+        }
+    }
+    embeddings.into_iter()
+        .enumerate()
+        .map(|(index, embedding)| embedding.ok_or_else(|| ProviderError::InvalidResponse(format!("no embedding for input {}", index))))
+        .collect()
+}
+
+/// Token count used to size batches and budget rate limits. Exact for OpenAI models, an estimate for
+/// providers with their own tokenizer.
+pub fn estimate_tokens(text: &str) -> usize {
+    crate::chunking::tokens::count_tokens(text)
+}
+
+/// Splits consecutive inputs into batches that stay within both request limits. An input that is
+/// over the token limit on its own still gets a batch of its own; the provider decides what to do with it.
+pub fn pack_batches(token_counts: &[usize], max_inputs: usize, max_tokens: usize) -> Vec<Range<usize>> {
+    let mut batches = Vec::new();
+    let mut start = 0;
+    let mut tokens = 0;
+    for (i, count) in token_counts.iter().enumerate() {
+        let full = i - start >= max_inputs.max(1) || tokens + count > max_tokens;
+        if i > start && full {
+            batches.push(start..i);
+            start = i;
+            tokens = 0;
+        }
+        tokens += count;
+    }
+    if start < token_counts.len() {
+        batches.push(start..token_counts.len());
+    }
+    batches
+}
@@ -0,0 +1,85 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use super::{check_response, collect_batch, estimate_tokens, EmbeddingProvider, Metered, ProviderError, OLLAMA};
+
+pub const OLLAMA_BASE_URL: &str = "http://localhost:11434";
+pub const DEFAULT_MODEL: &str = "nomic-embed-text";
+// Ollama has no documented request limits; these keep a single request from tying up a local model for long
+pub const MAX_BATCH_INPUTS: usize = 64;
+pub const MAX_BATCH_TOKENS: usize = 32_768;
+
+#[derive(Serialize, Debug)]
+struct Request<'a> {
+    model: &'a str,
+    input: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Response {
+    embeddings: Vec<Vec<f64>>,
+    prompt_eval_count: Option<usize>,
+}
+
+/// Local model server speaking Ollama's `POST /api/embed`.
+pub struct OllamaProvider {
+    client: reqwest::Client,
+    base_url: String,
+    model: String,
+    dimensions: Option<usize>,
+}
+
+impl OllamaProvider {
+    pub fn new(base_url: Option<String>, model: Option<String>, dimensions: Option<usize>) -> OllamaProvider {
+        let base_url = base_url.unwrap_or_else(|| String::from(OLLAMA_BASE_URL));
+        OllamaProvider {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model: model.unwrap_or_else(|| String::from(DEFAULT_MODEL)),
+            dimensions,
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OllamaProvider {
+    fn name(&self) -> &'static str {
+        OLLAMA
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.dimensions
+    }
+
+    fn max_batch_inputs(&self) -> usize {
+        MAX_BATCH_INPUTS
+    }
+
+    fn max_batch_tokens(&self) -> usize {
+        MAX_BATCH_TOKENS
+    }
+
+    async fn embed_batch(&self, inputs: &[String]) -> Result<Vec<Vec<f64>>, ProviderError> {
+        self.embed_batch_metered(inputs).await.map(|metered| metered.embeddings)
+    }
+
+    async fn embed_batch_metered(&self, inputs: &[String]) -> Result<Metered, ProviderError> {
+        let res = self.client.post(format!("{}/api/embed", self.base_url))
+            .json(&Request { model: &self.model, input: inputs, dimensions: self.dimensions })
+            .send()
+            .await?;
+        let res = check_response(res).await?;
+        let response: Response = res.json().await
+            .map_err(|e| ProviderError::InvalidResponse(e.to_string()))?;
+        // Embeddings come back in input order
+        let embeddings = collect_batch(inputs.len(), response.embeddings.into_iter().enumerate(), self.dimensions)?;
+        let tokens = response.prompt_eval_count
+            .unwrap_or_else(|| inputs.iter().map(|input| estimate_tokens(input)).sum());
+        Ok(Metered { embeddings, tokens })
+    }
+}
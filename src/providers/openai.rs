@@ -0,0 +1,130 @@
+use async_trait::async_trait;
+use reqwest::header::AUTHORIZATION;
+use serde::{Deserialize, Serialize};
+use super::{check_response, collect_batch, estimate_tokens, EmbeddingProvider, Metered, ProviderError, OPENAI, OPENAI_COMPATIBLE};
+
+pub const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+pub const DEFAULT_MODEL: &str = "text-embedding-ada-002";
+// Request limits of the OpenAI embeddings endpoint
+pub const MAX_BATCH_INPUTS: usize = 2048;
+pub const MAX_BATCH_TOKENS: usize = 300_000;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Embedding {
+    pub embedding: Vec<f64>,
+    pub index: i32,
+    pub object: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Usage {
+    pub prompt_tokens: i32,
+    pub total_tokens: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Response {
+    pub data: Vec<Embedding>,
+    pub model: String,
+    pub object: String,
+    pub usage: Option<Usage>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Request<'a> {
+    input: &'a [String],
+    model: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+}
+
+/// Talks to `POST {base_url}/embeddings`, either on api.openai.com or on any server that
+/// implements the same API (vLLM, LocalAI, LM Studio, ...).
+pub struct OpenAiProvider {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    dimensions: Option<usize>,
+    compatible: bool,
+}
+
+impl OpenAiProvider {
+    /// The default OpenAI model with the key from the environment, for the tests that call the real API.
+    #[cfg(test)]
+    pub fn from_env() -> Result<OpenAiProvider, ProviderError> {
+        OpenAiProvider::openai(None, None)
+    }
+
+    /// `dimensions` is only honoured by the text-embedding-3 models; ada-002 rejects it.
+    pub fn openai(model: Option<String>, dimensions: Option<usize>) -> Result<OpenAiProvider, ProviderError> {
+        let api_key = std::env::var("OPENAI_API_TOKEN")
+            .map_err(|_| ProviderError::Config(String::from("OPENAI_API_TOKEN must be set")))?;
+        Ok(OpenAiProvider {
+            client: reqwest::Client::new(),
+            base_url: String::from(OPENAI_BASE_URL),
+            api_key: Some(api_key),
+            model: model.unwrap_or_else(|| String::from(DEFAULT_MODEL)),
+            dimensions,
+            compatible: false,
+        })
+    }
+
+    /// Local servers usually don't check keys, so `EMBEDDING_API_KEY` is only sent when set.
+    pub fn compatible(base_url: String, model: Option<String>, dimensions: Option<usize>) -> OpenAiProvider {
+        OpenAiProvider {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: std::env::var("EMBEDDING_API_KEY").ok(),
+            model: model.unwrap_or_else(|| String::from(DEFAULT_MODEL)),
+            dimensions,
+            compatible: true,
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAiProvider {
+    fn name(&self) -> &'static str {
+        if self.compatible { OPENAI_COMPATIBLE } else { OPENAI }
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.dimensions
+    }
+
+    fn max_batch_inputs(&self) -> usize {
+        MAX_BATCH_INPUTS
+    }
+
+    fn max_batch_tokens(&self) -> usize {
+        MAX_BATCH_TOKENS
+    }
+
+    async fn embed_batch(&self, inputs: &[String]) -> Result<Vec<Vec<f64>>, ProviderError> {
+        self.embed_batch_metered(inputs).await.map(|metered| metered.embeddings)
+    }
+
+    async fn embed_batch_metered(&self, inputs: &[String]) -> Result<Metered, ProviderError> {
+        let mut request = self.client.post(format!("{}/embeddings", self.base_url))
+            .json(&Request { input: inputs, model: &self.model, dimensions: self.dimensions });
+        if let Some(api_key) = &self.api_key {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", api_key));
+        }
+        let res = check_response(request.send().await?).await?;
+        let response: Response = res.json().await
+            .map_err(|e| ProviderError::InvalidResponse(e.to_string()))?;
+        // The API doesn't promise to return the data in input order, `index` says which input each vector is for
+        let indexed = response.data.into_iter().map(|item| (item.index as usize, item.embedding));
+        let embeddings = collect_batch(inputs.len(), indexed, self.dimensions)?;
+        let tokens = match response.usage {
+            Some(usage) => usage.prompt_tokens.max(0) as usize,
+            None => inputs.iter().map(|input| estimate_tokens(input)).sum(),
+        };
+        Ok(Metered { embeddings, tokens })
+    }
+}
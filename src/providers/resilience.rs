@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use rand::Rng;
+use super::scheduler::EmbeddingScheduler;
+use super::{estimate_tokens, EmbeddingProvider, Metered, ProviderError};
+
+/// Retry, rate limit and circuit breaker settings, read from the `EMBEDDING_*` environment variables
+/// listed in the README.
+#[derive(Debug, Clone)]
+pub struct ResilienceConfig {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+    pub failure_threshold: u32,
+    pub cooldown: Duration,
+}
+
+impl Default for ResilienceConfig {
+    fn default() -> Self {
+        ResilienceConfig {
+            max_retries: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(60),
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+fn env_var<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().and_then(|value| value.parse().ok())
+}
+
+impl ResilienceConfig {
+    pub fn from_env() -> ResilienceConfig {
+        let default = ResilienceConfig::default();
+        ResilienceConfig {
+            max_retries: env_var("EMBEDDING_MAX_RETRIES").unwrap_or(default.max_retries),
+            base_delay: env_var("EMBEDDING_RETRY_BASE_MS").map(Duration::from_millis).unwrap_or(default.base_delay),
+            max_delay: env_var("EMBEDDING_RETRY_MAX_MS").map(Duration::from_millis).unwrap_or(default.max_delay),
+            requests_per_minute: env_var("EMBEDDING_REQUESTS_PER_MINUTE"),
+            tokens_per_minute: env_var("EMBEDDING_TOKENS_PER_MINUTE"),
+            failure_threshold: env_var("EMBEDDING_CIRCUIT_FAILURES").unwrap_or(default.failure_threshold),
+            cooldown: env_var("EMBEDDING_CIRCUIT_COOLDOWN_SECS").map(Duration::from_secs).unwrap_or(default.cooldown),
+        }
+    }
+}
+
+/// Delay before retry number `attempt` (starting at 0): exponential, capped at `max`, with jitter so that
+/// many clients failing together don't retry in lockstep. Never shorter than what the provider asked for.
+pub fn backoff(attempt: u32, base: Duration, max: Duration, retry_after: Option<Duration>) -> Duration {
+    let exponential = base.saturating_mul(1u32 << attempt.min(16)).min(max);
+    let jittered = exponential.mul_f64(rand::thread_rng().gen_range(0.5..=1.0));
+    retry_after.map_or(jittered, |retry_after| retry_after.max(jittered))
+}
+
+/// Token buckets for requests and tokens per minute. Both start full and refill continuously.
+pub struct RateLimiter {
+    requests_per_minute: Option<f64>,
+    tokens_per_minute: Option<f64>,
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    requests: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: Option<u32>, tokens_per_minute: Option<u32>) -> RateLimiter {
+        let requests_per_minute = requests_per_minute.map(|limit| limit.max(1) as f64);
+        let tokens_per_minute = tokens_per_minute.map(|limit| limit.max(1) as f64);
+        RateLimiter {
+            requests_per_minute,
+            tokens_per_minute,
+            buckets: Mutex::new(Buckets {
+                requests: requests_per_minute.unwrap_or(0.0),
+                tokens: tokens_per_minute.unwrap_or(0.0),
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// How long until a request of `tokens` fits. Takes it from the buckets when it fits now.
+    fn try_acquire(&self, tokens: usize) -> Option<Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+        let elapsed_minutes = now.duration_since(buckets.updated).as_secs_f64() / 60.0;
+        buckets.updated = now;
+
+        let mut wait_minutes: f64 = 0.0;
+        if let Some(limit) = self.requests_per_minute {
+            buckets.requests = (buckets.requests + elapsed_minutes * limit).min(limit);
+            wait_minutes = wait_minutes.max((1.0 - buckets.requests) / limit);
+        }
+        // A request bigger than the whole budget only has to wait for a full bucket
+        let needed = self.tokens_per_minute.map_or(0.0, |limit| (tokens as f64).min(limit));
+        if let Some(limit) = self.tokens_per_minute {
+            buckets.tokens = (buckets.tokens + elapsed_minutes * limit).min(limit);
+            wait_minutes = wait_minutes.max((needed - buckets.tokens) / limit);
+        }
+
+        if wait_minutes > 0.0 {
+            return Some(Duration::from_secs_f64(wait_minutes * 60.0));
+        }
+        if self.requests_per_minute.is_some() {
+            buckets.requests -= 1.0;
+        }
+        buckets.tokens -= needed;
+        None
+    }
+
+    pub async fn acquire(&self, tokens: usize) {
+        while let Some(wait) = self.try_acquire(tokens) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+enum CircuitState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    HalfOpen { since: Instant },
+}
+
+/// Stops sending requests to a provider after `threshold` consecutive failures. Once `cooldown` has passed a
+/// single trial request is let through; its outcome closes the circuit again or reopens it.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<CircuitState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            threshold: threshold.max(1),
+            cooldown,
+            state: Mutex::new(CircuitState::Closed { failures: 0 }),
+        }
+    }
+
+    pub fn check(&self) -> Result<(), ProviderError> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        match *state {
+            CircuitState::Closed { .. } => Ok(()),
+            CircuitState::Open { until } if now < until => Err(ProviderError::CircuitOpen { retry_after: until - now }),
+            // A trial that never reported back (its request was dropped) doesn't keep the circuit stuck
+            CircuitState::HalfOpen { since } if now < since + self.cooldown => Err(ProviderError::CircuitOpen { retry_after: since + self.cooldown - now }),
+            _ => {
+                *state = CircuitState::HalfOpen { since: now };
+                Ok(())
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        *self.state.lock().unwrap() = CircuitState::Closed { failures: 0 };
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            CircuitState::Closed { failures } => failures + 1,
+            _ => self.threshold,
+        };
+        *state = if failures >= self.threshold {
+            CircuitState::Open { until: Instant::now() + self.cooldown }
+        } else {
+            CircuitState::Closed { failures }
+        };
+    }
+}
+
+/// Limiter and breaker for one provider endpoint, shared by every project that uses it.
+pub struct Shared {
+    pub limiter: RateLimiter,
+    pub breaker: CircuitBreaker,
+}
+
+impl Shared {
+    pub fn new(config: &ResilienceConfig) -> Shared {
+        Shared {
+            limiter: RateLimiter::new(config.requests_per_minute, config.tokens_per_minute),
+            breaker: CircuitBreaker::new(config.failure_threshold, config.cooldown),
+        }
+    }
+}
+
+static SHARED: OnceLock<Mutex<HashMap<String, Arc<Shared>>>> = OnceLock::new();
+
+pub fn shared(endpoint: &str, config: &ResilienceConfig) -> Arc<Shared> {
+    SHARED.get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap()
+        .entry(endpoint.to_string())
+        .or_insert_with(|| Arc::new(Shared::new(config)))
+        .clone()
+}
+
+/// Wraps a provider with the shared limiter and circuit breaker, and retries failures that may go away.
+pub struct ResilientProvider {
+    inner: Box<dyn EmbeddingProvider>,
+    shared: Arc<Shared>,
+    config: ResilienceConfig,
+    // The scheduler and the project whose turn each attempt waits for
+    scheduler: Option<(EmbeddingScheduler, i64)>,
+}
+
+impl ResilientProvider {
+    pub fn new(inner: Box<dyn EmbeddingProvider>, shared: Arc<Shared>, config: ResilienceConfig) -> ResilientProvider {
+        ResilientProvider { inner, shared, config, scheduler: None }
+    }
+
+    /// Takes a slot in `scheduler` for every attempt, on behalf of `project_id`. The slot is given back
+    /// before a backoff, so a request that is waiting to be retried doesn't hold it.
+    pub fn scheduled(self, scheduler: EmbeddingScheduler, project_id: i64) -> ResilientProvider {
+        ResilientProvider { scheduler: Some((scheduler, project_id)), ..self }
+    }
+
+    async fn attempt(&self, inputs: &[String]) -> Result<Metered, ProviderError> {
+        let _permit = match &self.scheduler {
+            Some((scheduler, project_id)) => Some(scheduler.acquire(*project_id).await),
+            None => None,
+        };
+        self.inner.embed_batch_metered(inputs).await
+    }
+}
+
+/// Failures that say the provider itself is unwell. A 429 is the provider working as intended and is
+/// left to the backoff; a rejected request says nothing about the provider's health.
+fn is_outage(e: &ProviderError) -> bool {
+    matches!(e, ProviderError::Request(_) | ProviderError::Unavailable { .. })
+}
+
+#[async_trait]
+impl EmbeddingProvider for ResilientProvider {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.inner.dimensions()
+    }
+
+    fn max_batch_inputs(&self) -> usize {
+        self.inner.max_batch_inputs()
+    }
+
+    fn max_batch_tokens(&self) -> usize {
+        self.inner.max_batch_tokens()
+    }
+
+    async fn embed_batch(&self, inputs: &[String]) -> Result<Vec<Vec<f64>>, ProviderError> {
+        self.embed_batch_metered(inputs).await.map(|metered| metered.embeddings)
+    }
+
+    async fn embed_batch_metered(&self, inputs: &[String]) -> Result<Metered, ProviderError> {
+        let tokens: usize = inputs.iter().map(|input| estimate_tokens(input)).sum();
+        let mut attempt = 0;
+        loop {
+            self.shared.breaker.check()?;
+            self.shared.limiter.acquire(tokens).await;
+
+            let result = self.attempt(inputs).await;
+            match &result {
+                Err(e) if is_outage(e) => self.shared.breaker.record_failure(),
+                _ => self.shared.breaker.record_success(),
+            }
+
+            match result {
+                Err(e) if e.is_retryable() && attempt < self.config.max_retries => {
+                    // Waiting longer than max_delay would just hold the caller hostage
+                    if e.retry_after().is_some_and(|retry_after| retry_after > self.config.max_delay) {
+                        return Err(e);
+                    }
+                    let delay = backoff(attempt, self.config.base_delay, self.config.max_delay, e.retry_after());
+                    eprintln!("{} error, retrying in {:?}: {}", self.name(), delay, e);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                },
+                result => return result,
+            }
+        }
+    }
+}
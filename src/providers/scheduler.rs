@@ -0,0 +1,112 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::oneshot;
+
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Caps how many embedding requests are in flight across the whole server. When every slot is taken,
+/// waiters are queued per project and freed slots are handed to projects in turn, so a project with a
+/// large backlog gets one slot for every slot each other waiting project gets.
+#[derive(Clone)]
+pub struct EmbeddingScheduler {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    limit: usize,
+    state: Mutex<State>,
+}
+
+struct State {
+    running: usize,
+    waiting: HashMap<i64, VecDeque<oneshot::Sender<Permit>>>,
+    // Projects with waiters, in the order they get the next free slot
+    turns: VecDeque<i64>,
+}
+
+/// A slot in the scheduler, given back when dropped.
+pub struct Permit {
+    inner: Option<Arc<Inner>>,
+}
+
+impl EmbeddingScheduler {
+    pub fn new(limit: usize) -> EmbeddingScheduler {
+        EmbeddingScheduler {
+            inner: Arc::new(Inner {
+                limit: limit.max(1),
+                state: Mutex::new(State { running: 0, waiting: HashMap::new(), turns: VecDeque::new() }),
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.inner.limit
+    }
+
+    pub fn running(&self) -> usize {
+        self.inner.state.lock().unwrap().running
+    }
+
+    pub async fn acquire(&self, project_id: i64) -> Permit {
+        let receiver = {
+            let mut state = self.inner.state.lock().unwrap();
+            if state.running < self.inner.limit && state.turns.is_empty() {
+                state.running += 1;
+                return Permit { inner: Some(self.inner.clone()) };
+            }
+            let (sender, receiver) = oneshot::channel();
+            let queue = state.waiting.entry(project_id).or_default();
+            queue.push_back(sender);
+            if queue.len() == 1 {
+                state.turns.push_back(project_id);
+            }
+            receiver
+        };
+        // The sender is only dropped together with the scheduler, which outlives every permit
+        receiver.await.expect("embedding scheduler dropped")
+    }
+}
+
+impl Inner {
+    fn release(self: &Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+        while let Some(project_id) = state.turns.pop_front() {
+            let queue = state.waiting.get_mut(&project_id).unwrap();
+            let sender = queue.pop_front().unwrap();
+            if queue.is_empty() {
+                state.waiting.remove(&project_id);
+            } else {
+                state.turns.push_back(project_id);
+            }
+            // The slot moves straight to the waiter. A waiter that gave up gets skipped; its permit is
+            // disarmed so dropping it doesn't release the slot a second time.
+            match sender.send(Permit { inner: Some(self.clone()) }) {
+                Ok(()) => return,
+                Err(mut permit) => {
+                    permit.inner.take();
+                }
+            }
+        }
+        state.running -= 1;
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            inner.release();
+        }
+    }
+}
+
+static SCHEDULER: OnceLock<EmbeddingScheduler> = OnceLock::new();
+
+/// The scheduler shared by all embedding work, sized by `EMBEDDING_CONCURRENCY`.
+pub fn global() -> &'static EmbeddingScheduler {
+    SCHEDULER.get_or_init(|| {
+        let limit = std::env::var("EMBEDDING_CONCURRENCY").ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_CONCURRENCY);
+        EmbeddingScheduler::new(limit)
+    })
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UsageKind {
+    Ingestion,
+    Search,
+}
+
+impl UsageKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UsageKind::Ingestion => "ingestion",
+            UsageKind::Search => "search",
+        }
+    }
+}
+
+/// Records the tokens one embedding call was billed for. Calls answered entirely from the cache cost nothing
+/// and aren't recorded.
+pub async fn record(db_pool: &SqlitePool, project_id: i64, user_id: Option<i64>, provider: &str, model: &str, kind: UsageKind, tokens: usize) -> Result<(), sqlx::Error> {
+    if tokens == 0 {
+        return Ok(());
+    }
+    sqlx::query("INSERT INTO token_usage (project_id, user_id, provider, model, kind, tokens) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(project_id)
+        .bind(user_id)
+        .bind(provider)
+        .bind(model)
+        .bind(kind.as_str())
+        .bind(tokens as i64)
+        .execute(db_pool)
+        .await?;
+    Ok(())
+}
+
+/// A monthly token quota that has been used up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotaExceeded {
+    pub scope: &'static str,
+    pub id: i64,
+    pub limit: i64,
+    pub used: i64,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Monthly token quota of {} for {} {} exceeded ({} tokens used this month)", self.limit, self.scope, self.id, self.used)
+    }
+}
+
+/// Monthly quota of every user, from `USER_MONTHLY_TOKEN_QUOTA`. Unlimited when unset.
+pub fn user_quota() -> Option<i64> {
+    std::env::var("USER_MONTHLY_TOKEN_QUOTA").ok().and_then(|value| value.parse().ok())
+}
+
+/// Tokens used since the start of the current calendar month (UTC) by the project, or by the user when given.
+async fn used_this_month(db_pool: &SqlitePool, column: &str, id: i64) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(&format!(
+        "SELECT COALESCE(SUM(tokens), 0) FROM token_usage WHERE {} = ? AND created_at >= strftime('%Y-%m-01', 'now')",
+        column,
+    ))
+    .bind(id)
+    .fetch_one(db_pool)
+    .await
+}
+
+/// Returns the quota that rules out more embedding for this project and user, if any. The project's
+/// `monthly_token_quota` is checked first, then the user's.
+pub async fn check_quota(db_pool: &SqlitePool, project_id: i64, user_id: Option<i64>) -> Result<Option<QuotaExceeded>, sqlx::Error> {
+    let project_quota: Option<i64> = sqlx::query_scalar("SELECT monthly_token_quota FROM projects WHERE id = ?")
+        .bind(project_id)
+        .fetch_optional(db_pool)
+        .await?
+        .flatten();
+    if let Some(limit) = project_quota {
+        let used = used_this_month(db_pool, "project_id", project_id).await?;
+        if used >= limit {
+            return Ok(Some(QuotaExceeded { scope: "project", id: project_id, limit, used }));
+        }
+    }
+    if let (Some(user_id), Some(limit)) = (user_id, user_quota()) {
+        let used = used_this_month(db_pool, "user_id", user_id).await?;
+        if used >= limit {
+            return Ok(Some(QuotaExceeded { scope: "user", id: user_id, limit, used }));
+        }
+    }
+    Ok(None)
+}
+
+/// List price in USD per million input tokens, for the models we know the price of.
+pub fn price_per_million_tokens(provider: &str, model: &str) -> Option<f64> {
+    match (provider, model) {
+        (super::OLLAMA, _) | (super::FAKE, _) => Some(0.0),
+        (_, "text-embedding-3-small") => Some(0.02),
+        (_, "text-embedding-3-large") => Some(0.13),
+        (_, "text-embedding-ada-002") => Some(0.10),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct UsageFilter {
+    /// First day included, `YYYY-MM-DD`.
+    pub from: Option<String>,
+    /// Last day included, `YYYY-MM-DD`.
+    pub to: Option<String>,
+    pub project_id: Option<i64>,
+    pub user_id: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct UsageRow {
+    pub day: String,
+    pub project_id: i64,
+    pub provider: String,
+    pub model: String,
+    pub requests: i64,
+    pub tokens: i64,
+    /// `None` when the model's price isn't known.
+    pub estimated_cost_usd: Option<f64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct UsageReport {
+    pub rows: Vec<UsageRow>,
+    pub tokens: i64,
+    /// Cost of the rows whose price is known.
+    pub estimated_cost_usd: f64,
+}
+
+/// Token usage per day, project and model.
+pub async fn report(db_pool: &SqlitePool, filter: &UsageFilter) -> Result<UsageReport, sqlx::Error> {
+    let rows: Vec<(String, i64, String, String, i64, i64)> = sqlx::query_as(
+        r#"
+        SELECT date(created_at) AS day, project_id, provider, model, COUNT(*), SUM(tokens)
+        FROM token_usage
+        WHERE (? IS NULL OR date(created_at) >= ?)
+            AND (? IS NULL OR date(created_at) <= ?)
+            AND (? IS NULL OR project_id = ?)
+            AND (? IS NULL OR user_id = ?)
+        GROUP BY day, project_id, provider, model
+        ORDER BY day, project_id, provider, model
+        "#,
+    )
+    .bind(&filter.from)
+    .bind(&filter.from)
+    .bind(&filter.to)
+    .bind(&filter.to)
+    .bind(filter.project_id)
+    .bind(filter.project_id)
+    .bind(filter.user_id)
+    .bind(filter.user_id)
+    .fetch_all(db_pool)
+    .await?;
+
+    let rows: Vec<UsageRow> = rows.into_iter()
+        .map(|(day, project_id, provider, model, requests, tokens)| {
+            let estimated_cost_usd = price_per_million_tokens(&provider, &model).map(|price| tokens as f64 * price / 1_000_000.0);
+            UsageRow { day, project_id, provider, model, requests, tokens, estimated_cost_usd }
+        })
+        .collect();
+    Ok(UsageReport {
+        tokens: rows.iter().map(|row| row.tokens).sum(),
+        estimated_cost_usd: rows.iter().filter_map(|row| row.estimated_cost_usd).sum(),
+        rows,
+    })
+}
@@ -0,0 +1,122 @@
+#[cfg(test)]
+mod tests {
+    use crate::utils::archive::{self, ArchiveError, ArchiveFormat, ArchiveLimits, Skipped};
+    use crate::utils::file_store;
+    use crate::utils::hash::sha256_hex;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    // A high id keeps the test's files apart from real project data
+    const PROJECT_ID: i64 = 990_045;
+
+    const LIMITS: ArchiveLimits = ArchiveLimits { max_files: 100, max_bytes: 1024 * 1024 * 1024, max_ratio: 100 };
+
+    fn write_archive(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_member_paths() {
+        assert_eq!(archive::member_path("docs/./guide/intro.md").as_deref(), Some("docs/guide/intro.md"));
+        assert_eq!(archive::member_path("docs\\notes.txt").as_deref(), Some("docs/notes.txt"));
+        assert_eq!(archive::member_path("../etc/passwd"), None);
+        assert_eq!(archive::member_path("docs/../../etc/passwd"), None);
+        assert_eq!(archive::member_path("/etc/passwd"), None);
+        assert_eq!(archive::member_path("C:/Windows/win.ini"), None);
+        assert_eq!(ArchiveFormat::detect("kb.TAR.GZ"), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::detect("notes.txt"), None);
+    }
+
+    #[test]
+    fn test_expand_zip_keeps_safe_files() {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+        zip.add_directory("docs/", options).unwrap();
+        zip.start_file("docs/guide.md", options).unwrap();
+        zip.write_all(b"# Guide").unwrap();
+        zip.start_file("../escape.txt", options).unwrap();
+        zip.write_all(b"outside").unwrap();
+        zip.start_file("__MACOSX/docs/._guide.md", options).unwrap();
+        zip.write_all(b"resource fork").unwrap();
+        zip.add_symlink("passwd", "/etc/passwd", options).unwrap();
+        let path = write_archive("archive_test.zip", &zip.finish().unwrap().into_inner());
+
+        let expanded = archive::expand(ArchiveFormat::Zip, &path, PROJECT_ID, &LIMITS).unwrap();
+        assert_eq!(expanded.members.len(), 1);
+        assert_eq!(expanded.members[0].name, "docs/guide.md");
+        assert_eq!(expanded.members[0].size, 7);
+        assert_eq!(expanded.members[0].content_hash, sha256_hex(b"# Guide"));
+        assert_eq!(std::fs::read_to_string(&expanded.members[0].temp_path).unwrap(), "# Guide");
+        assert_eq!(expanded.skipped, vec![
+            Skipped { name: String::from("../escape.txt"), reason: String::from("unsafe path") },
+            Skipped { name: String::from("passwd"), reason: String::from("not a regular file") },
+        ]);
+
+        archive::discard(expanded.members);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_dir_all(file_store::project_dir(PROJECT_ID)).unwrap();
+    }
+
+    #[test]
+    fn test_expand_tar_gz() {
+        let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default()));
+        for (name, contents) in [("kb/faq.txt", "Questions"), ("kb/answers/one.txt", "Answer")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, name, contents.as_bytes()).unwrap();
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_cksum();
+        tar.append_link(&mut header, "kb/link", "/etc/passwd").unwrap();
+        let path = write_archive("archive_test.tar.gz", &tar.into_inner().unwrap().finish().unwrap());
+
+        let expanded = archive::expand(ArchiveFormat::TarGz, &path, PROJECT_ID + 1, &LIMITS).unwrap();
+        let names: Vec<&str> = expanded.members.iter().map(|member| member.name.as_str()).collect();
+        assert_eq!(names, vec!["kb/faq.txt", "kb/answers/one.txt"]);
+        assert_eq!(expanded.skipped.len(), 1);
+
+        archive::discard(expanded.members);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_dir_all(file_store::project_dir(PROJECT_ID + 1)).unwrap();
+    }
+
+    #[test]
+    fn test_expand_rejects_zip_bombs() {
+        // 64 MiB of zeros deflate to about 64 KiB
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.start_file("zeros.txt", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(&vec![0; 64 * 1024 * 1024]).unwrap();
+        zip.start_file("more.txt", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(b"more").unwrap();
+        let path = write_archive("archive_test_bomb.zip", &zip.finish().unwrap().into_inner());
+
+        match archive::expand(ArchiveFormat::Zip, &path, PROJECT_ID + 2, &LIMITS) {
+            Err(ArchiveError::TooLarge(_)) => {},
+            other => panic!("expected the archive to be too large, got {:?}", other),
+        }
+        let few_files = ArchiveLimits { max_files: 1, ..LIMITS };
+        let small = write_archive("archive_test_small.zip", &{
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+            for name in ["a.txt", "b.txt"] {
+                zip.start_file(name, zip::write::FileOptions::default()).unwrap();
+                zip.write_all(name.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap().into_inner()
+        });
+        assert!(matches!(archive::expand(ArchiveFormat::Zip, &small, PROJECT_ID + 2, &few_files), Err(ArchiveError::TooLarge(_))));
+
+        // Nothing is left behind
+        let leftover = std::fs::read_dir(file_store::project_dir(PROJECT_ID + 2).join(".tmp")).unwrap().count();
+        assert_eq!(leftover, 0);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&small).unwrap();
+        std::fs::remove_dir_all(file_store::project_dir(PROJECT_ID + 2)).unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+#[cfg(test)]
+mod tests {
+    use crate::chunking::content_defined::{chunk, MIN_CHUNK_SIZE, MAX_CHUNK_SIZE};
+
+    fn sample_text(len: usize) -> Vec<u8> {
+        let words = ["vector", "embedding", "project", "store", "search", "tree", "file", "chunk"];
+        let mut text = String::new();
+        let mut i: usize = 7;
+        while text.len() < len {
+            i = i.wrapping_mul(31).wrapping_add(17) % 1009;
+            text.push_str(words[i % words.len()]);
+            text.push(' ');
+        }
+        text.into_bytes()
+    }
+
+    #[test]
+    fn test_chunks_cover_input() {
+        let data = sample_text(20_000);
+        let chunks = chunk(&data);
+
+        assert_eq!(chunks.first().unwrap().0, 0);
+        assert_eq!(chunks.last().unwrap().1, data.len());
+        for window in chunks.windows(2) {
+            assert_eq!(window[0].1, window[1].0);
+        }
+        for (start, end) in &chunks[..chunks.len() - 1] {
+            assert!(end - start >= MIN_CHUNK_SIZE);
+            assert!(end - start <= MAX_CHUNK_SIZE + 3);
+        }
+    }
+
+    #[test]
+    fn test_edit_only_changes_nearby_chunks() {
+        let original = sample_text(20_000);
+        let mut edited = b"A new opening sentence. ".to_vec();
+        edited.extend_from_slice(&original);
+
+        let original_chunks: Vec<&[u8]> = chunk(&original).into_iter().map(|(start, end)| &original[start..end]).collect();
+        let edited_chunks: Vec<&[u8]> = chunk(&edited).into_iter().map(|(start, end)| &edited[start..end]).collect();
+
+        let shared = edited_chunks.iter().filter(|chunk| original_chunks.contains(chunk)).count();
+        assert!(shared >= original_chunks.len() - 2);
+    }
+
+    #[test]
+    fn test_boundaries_respect_utf8() {
+        let data = "héllo wörld ünïcode ".repeat(500).into_bytes();
+        for (start, end) in chunk(&data) {
+            assert!(std::str::from_utf8(&data[start..end]).is_ok());
+        }
+    }
+
+    mod token_aware {
+        use crate::chunking::token_aware::{chunk_with_budget, words, TokenChunk};
+        use crate::chunking::tokens::{Cl100k, TokenCounter};
+
+        fn check(text: &str, max_tokens: usize) -> Vec<TokenChunk> {
+            let chunks = chunk_with_budget(text, &Cl100k, max_tokens / 4, max_tokens / 2, max_tokens);
+            let mut position = 0;
+            for chunk in &chunks {
+                assert_eq!(chunk.start, position);
+                assert!(text.is_char_boundary(chunk.end));
+                assert!(chunk.tokens <= max_tokens);
+                assert_eq!(chunk.tokens, Cl100k.count(&text[chunk.start..chunk.end]));
+                position = chunk.end;
+            }
+            assert_eq!(position, text.len());
+            chunks
+        }
+
+        #[test]
+        fn test_token_budget_and_char_boundaries() {
+            let text = "Vectors, embeddings und Größen. 日本語のテキストも含まれます。 Emoji 🙂🚀 too!\n\n".repeat(200);
+            let chunks = check(&text, 64);
+            assert!(chunks.len() > 1);
+        }
+
+        #[test]
+        fn test_long_word_is_split() {
+            let text = format!("start {} end", "🙂".repeat(400));
+            let chunks = check(&text, 32);
+            assert!(chunks.len() > 3);
+        }
+
+        #[test]
+        fn test_words_cover_text() {
+            assert_eq!(words("  foo bar  "), vec![0..5, 5..11]);
+            assert_eq!(words("   "), vec![0..3]);
+            assert!(words("").is_empty());
+        }
+    }
+
+    mod strategy {
+        use crate::chunking::strategy::ChunkingStrategy;
+        use crate::chunking::token_aware::TokenChunk;
+        use crate::chunking::tokens::{Cl100k, TokenCounter};
+
+        fn sample() -> String {
+            let mut text = String::new();
+            for section in 0..6 {
+                text.push_str(&format!("# Section {}\n\n", section));
+                text.push_str("```\n# a comment, not a heading\n```\n");
+                for paragraph in 0..4 {
+                    text.push_str(&format!("Paragraph {} of section {}. It has a few sentences! Does it? ", paragraph, section));
+                    text.push_str("Größen und 日本語のテキスト。 Emoji 🙂 too.\n\n");
+                }
+            }
+            text
+        }
+
+        /// Chunks must fit the budget, sit on char boundaries and leave no gaps.
+        fn check(strategy: &ChunkingStrategy, text: &str, max_tokens: usize) -> Vec<TokenChunk> {
+            let chunks = strategy.chunk(text, &Cl100k);
+            let mut covered = 0;
+            for chunk in &chunks {
+                assert!(chunk.start <= covered && chunk.end > covered);
+                assert!(text.is_char_boundary(chunk.start) && text.is_char_boundary(chunk.end));
+                assert!(chunk.tokens <= max_tokens);
+                assert_eq!(chunk.tokens, Cl100k.count(&text[chunk.start..chunk.end]));
+                covered = chunk.end;
+            }
+            assert_eq!(covered, text.len());
+            chunks
+        }
+
+        #[test]
+        fn test_strategies_cover_text_within_budget() {
+            let text = sample();
+            for json in [
+                r#"{"strategy": "tokens", "max_tokens": 48}"#,
+                r#"{"strategy": "sentence", "max_tokens": 48}"#,
+                r#"{"strategy": "paragraph", "max_tokens": 48}"#,
+                r#"{"strategy": "recursive", "max_tokens": 48}"#,
+                r#"{"strategy": "markdown", "max_tokens": 48}"#,
+            ] {
+                let strategy = ChunkingStrategy::parse(json).unwrap();
+                let chunks = check(&strategy, &text, 48);
+                assert!(chunks.windows(2).all(|pair| pair[0].end == pair[1].start), "{}", json);
+            }
+        }
+
+        #[test]
+        fn test_sentences_and_paragraphs_stay_whole() {
+            let text = sample();
+            for chunk in check(&ChunkingStrategy::Sentence { max_tokens: 48 }, &text, 48) {
+                let ends = text[..chunk.end].trim_end();
+                assert!(chunk.end == text.len() || ends.ends_with(['.', '!', '?', '。']) || text[..chunk.end].ends_with('\n'));
+            }
+            for chunk in check(&ChunkingStrategy::Paragraph { max_tokens: 256 }, &text, 256) {
+                assert!(text[..chunk.end].ends_with("\n\n"));
+            }
+        }
+
+        #[test]
+        fn test_markdown_chunks_never_span_a_heading() {
+            let text = sample();
+            let chunks = check(&ChunkingStrategy::Markdown { max_tokens: 512 }, &text, 512);
+            assert_eq!(chunks.len(), 6);
+            for chunk in chunks {
+                assert!(text[chunk.start..].starts_with("# Section"));
+                assert_eq!(text[chunk.start..chunk.end].matches("\n# Section").count(), 0);
+            }
+        }
+
+        #[test]
+        fn test_sliding_windows_overlap() {
+            let text = sample();
+            let strategy = ChunkingStrategy::SlidingWindow { window_tokens: 64, overlap_tokens: 16 };
+            let chunks = check(&strategy, &text, 64);
+            assert!(chunks.windows(2).all(|pair| pair[1].start < pair[0].end));
+        }
+
+        #[test]
+        fn test_invalid_strategies_are_rejected() {
+            assert!(ChunkingStrategy::parse(r#"{"strategy": "sliding_window", "window_tokens": 32, "overlap_tokens": 32}"#).is_err());
+            assert!(ChunkingStrategy::parse(r#"{"strategy": "sentence", "max_tokens": 0}"#).is_err());
+            assert!(ChunkingStrategy::parse(r#"{"strategy": "recursive", "separators": [""]}"#).is_err());
+            assert!(ChunkingStrategy::parse(r#"{"strategy": "words"}"#).is_err());
+            assert_eq!(ChunkingStrategy::parse(r#"{"strategy": "tokens"}"#).unwrap(), ChunkingStrategy::default());
+        }
+    }
+
+    mod code {
+        use crate::chunking::code::{chunk, CodeSpan, Language};
+        use crate::chunking::token_aware::TokenChunk;
+        use crate::chunking::tokens::Cl100k;
+
+        const RUST: &str = "use std::fmt;\n\n/// A point\n#[derive(Debug)]\npub struct Point { x: i32, y: i32 }\n\nimpl Point {\n    pub fn new() -> Point {\n        Point { x: 0, y: 0 }\n    }\n\n    pub fn norm(&self) -> f64 {\n        ((self.x * self.x + self.y * self.y) as f64).sqrt()\n    }\n}\n\nimpl fmt::Display for Point {\n    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {\n        write!(f, \"({}, {})\", self.x, self.y)\n    }\n}\n";
+
+        fn check(text: &str, language: Language, max_tokens: usize) -> Vec<(TokenChunk, CodeSpan)> {
+            let chunks = chunk(text, language, &Cl100k, max_tokens).unwrap();
+            let mut position = 0;
+            for (chunk, span) in &chunks {
+                assert_eq!(chunk.start, position);
+                assert!(chunk.tokens <= max_tokens);
+                assert!(span.start_line <= span.end_line);
+                position = chunk.end;
+            }
+            assert_eq!(position, text.len());
+            chunks
+        }
+
+        fn symbols(chunks: &[(TokenChunk, CodeSpan)]) -> Vec<Option<&str>> {
+            chunks.iter().map(|(_, span)| span.symbol.as_deref()).collect()
+        }
+
+        #[test]
+        fn test_one_chunk_per_definition() {
+            let chunks = check(RUST, Language::Rust, 512);
+            assert_eq!(symbols(&chunks), vec![None, Some("Point"), Some("Point"), Some("<Point as fmt::Display>")]);
+            // The doc comment and attribute stay with the struct
+            assert!(RUST[chunks[1].0.start..].starts_with("/// A point"));
+            assert_eq!((chunks[1].1.start_line, chunks[1].1.end_line), (3, 5));
+            assert_eq!((chunks[2].1.start_line, chunks[2].1.end_line), (7, 15));
+        }
+
+        #[test]
+        fn test_large_definitions_split_by_member() {
+            let chunks = check(RUST, Language::Rust, 40);
+            let symbols = symbols(&chunks);
+            assert!(symbols.contains(&Some("Point::new")));
+            assert!(symbols.contains(&Some("Point::norm")));
+            assert!(symbols.contains(&Some("<Point as fmt::Display>::fmt")));
+        }
+
+        #[test]
+        fn test_languages() {
+            let python = "import os\n\n@cached\ndef load(path):\n    return open(path).read()\n\nclass Store:\n    def get(self, key):\n        return key\n";
+            assert_eq!(symbols(&check(python, Language::Python, 512)), vec![None, Some("load"), Some("Store")]);
+
+            let typescript = "import { x } from './x';\n\nexport function add(a: number, b: number): number {\n  return a + b;\n}\n\nconst double = (a: number) => a * 2;\n\nexport class Counter {\n  count = 0;\n}\n";
+            assert_eq!(symbols(&check(typescript, Language::TypeScript, 512)), vec![None, Some("add"), Some("double"), Some("Counter")]);
+
+            let go = "package main\n\ntype Store struct{ items []string }\n\n// Len counts the items\nfunc (s *Store) Len() int {\n\treturn len(s.items)\n}\n";
+            assert_eq!(symbols(&check(go, Language::Go, 512)), vec![None, Some("Store"), Some("Store.Len")]);
+
+            let java = "package store;\n\npublic class Store {\n    public int size() {\n        return 0;\n    }\n}\n";
+            assert_eq!(symbols(&check(java, Language::Java, 512)), vec![None, Some("Store")]);
+
+            assert_eq!(Language::from_path("src/lib.rs"), Some(Language::Rust));
+            assert_eq!(Language::from_path("App.tsx"), Some(Language::Tsx));
+            assert_eq!(Language::from_path("README.md"), None);
+        }
+    }
+
+    #[test]
+    fn test_locate_counts_lines_and_characters() {
+        use crate::chunking::{locate, Location};
+
+        let text = "fn main() {\n    println!(\"héllo\");\n}\n".as_bytes();
+        let start = 16;
+        let end = text.iter().position(|byte| *byte == b';').unwrap() + 1;
+        assert_eq!(locate(text, start, end), Location { start_line: 2, start_column: 5, end_line: 2, end_column: 22 });
+        // A range ending inside `é` still points at it
+        assert_eq!(locate(text, 0, 29), Location { start_line: 1, start_column: 1, end_line: 2, end_column: 16 });
+        assert_eq!(locate(text, 0, text.len() + 10).end_line, 3);
+    }
+}
@@ -0,0 +1,104 @@
+#[cfg(test)]
+mod tests {
+    use sqlx::SqlitePool;
+    use crate::memory_management::consistency_checker::{check, repair, Discrepancy};
+    use crate::utils::file_store;
+    use tokio::fs::read_to_string;
+
+    async fn setup_db() -> SqlitePool {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+
+        // Read SQL commands from file
+        let sql_commands = read_to_string("init.sql").await.expect("Could not read SQL file");
+
+        // Execute SQL commands
+        sqlx::query(&sql_commands).execute(&pool).await.expect("Could not execute SQL commands");
+
+        pool
+    }
+
+    #[actix_rt::test]
+    async fn test_check_and_repair_database() {
+        let pool = setup_db().await;
+
+        sqlx::query("INSERT INTO projects (name, description) VALUES ('fsck_project', 'test')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        sqlx::query("INSERT INTO file_entry (name, path, project_id) VALUES ('gone.txt', './project_data/fsck_missing/gone.txt', 1)")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert file.");
+        sqlx::query("INSERT INTO file_embedding (file_id, start_byte, end_byte, embedding) VALUES (1, 0, 3, X'5B315D'), (7, 0, 3, X'5B315D')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert embeddings.");
+
+        let found = check(&pool, None).await.expect("Check failed.");
+
+        assert!(found.contains(&Discrepancy::FileMissingOnDisk {
+            project_id: 1,
+            file_id: 1,
+            path: String::from("./project_data/fsck_missing/gone.txt"),
+        }));
+        assert!(found.contains(&Discrepancy::OrphanEmbeddings { file_id: 7, count: 1 }));
+
+        // Leave the project directory alone so the test does not write to ./project_data
+        let database_only: Vec<Discrepancy> = found.into_iter()
+            .filter(|discrepancy| !matches!(discrepancy, Discrepancy::ProjectDirectoryMissing { .. }))
+            .collect();
+        repair(&pool, None, &database_only).await.expect("Repair failed.");
+
+        let files: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM file_entry").fetch_one(&pool).await.unwrap();
+        let embeddings: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM file_embedding").fetch_one(&pool).await.unwrap();
+        assert_eq!(files, 0);
+        assert_eq!(embeddings, 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_repair_keeps_resumable_embeddings() {
+        let pool = setup_db().await;
+        // A high id keeps the test's files apart from real project data
+        let project_id: i64 = 990_041;
+        let dir = file_store::project_dir(project_id);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        std::fs::write(path("resumed.txt"), "hello world").unwrap();
+        std::fs::write(path("partial.txt"), "hello world").unwrap();
+
+        sqlx::query("INSERT INTO projects (id, name, description) VALUES (?, 'fsck_project', 'test')")
+            .bind(project_id)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        sqlx::query("INSERT INTO file_entry (id, name, path, project_id) VALUES (1, 'resumed.txt', ?, ?), (2, 'partial.txt', ?, ?)")
+            .bind(path("resumed.txt"))
+            .bind(project_id)
+            .bind(path("partial.txt"))
+            .bind(project_id)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert files.");
+        sqlx::query("INSERT INTO file_embedding (file_id, start_byte, end_byte, embedding) VALUES (1, 0, 5, X'5B315D'), (2, 0, 5, X'5B315D')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert embeddings.");
+        // The second chunk of the first file failed and is left for a retry
+        sqlx::query("INSERT INTO file_chunk (file_id, version, start_byte, end_byte, chunk_hash, status) VALUES (1, 1, 0, 5, 'a', 'embedded'), (1, 1, 5, 11, 'b', 'failed')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert chunks.");
+
+        let found = check(&pool, None).await.expect("Check failed.");
+        assert!(found.contains(&Discrepancy::EmbeddingInProgress { project_id, file_id: 1, covered_bytes: 5, file_size: 11 }));
+        assert!(found.contains(&Discrepancy::IncompleteEmbeddings { project_id, file_id: 2, covered_bytes: 5, file_size: 11 }));
+
+        repair(&pool, None, &found).await.expect("Repair failed.");
+        let chunks: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM file_chunk WHERE file_id = 1").fetch_one(&pool).await.unwrap();
+        let embeddings: Vec<i64> = sqlx::query_scalar("SELECT file_id FROM file_embedding").fetch_all(&pool).await.unwrap();
+        assert_eq!(chunks, 2);
+        assert_eq!(embeddings, vec![1]);
+
+        std::fs::remove_dir_all(file_store::project_dir(project_id)).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+#[cfg(test)]
+mod tests {
+    use actix_web::web;
+    use actix_web::http::StatusCode;
+    use sqlx::SqlitePool;
+    use serde_json::{json, Map, Value};
+    use crate::handlers::document_handler::{create_document, replace_document};
+    use crate::handlers::project_handler::delete_file;
+    use crate::jobs::JobQueue;
+    use crate::models::document::{DocumentUpdate, NewDocument};
+    use crate::memory_management::project_manager::{ProjectManager, search_filtered};
+    use crate::memory_management::project_store::Embedding;
+    use crate::parsing::rows::{self, RowFormat};
+    use crate::utils::documents::{self, RowSelection, SkippedRow};
+    use crate::utils::file_store;
+    use crate::utils::file_versions::StoreStatus;
+    use std::sync::{Arc, Mutex};
+    use tokio::fs::read_to_string;
+
+    async fn setup_db() -> SqlitePool {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+
+        // Read SQL commands from file
+        let sql_commands = read_to_string("init.sql").await.expect("Could not read SQL file");
+
+        // Execute SQL commands
+        sqlx::query(&sql_commands).execute(&pool).await.expect("Could not execute SQL commands");
+
+        pool
+    }
+
+    async fn setup_project(pool: &SqlitePool, project_id: i64) -> Arc<Mutex<ProjectManager>> {
+        sqlx::query("INSERT INTO projects (id, name, description, embedding_provider) VALUES (?, 'rows', 'test', 'fake')")
+            .bind(project_id)
+            .execute(pool)
+            .await
+            .expect("Failed to insert project.");
+        let project_manager = Arc::new(Mutex::new(ProjectManager::new(pool.clone())));
+        project_manager.lock().unwrap().add_blank_project(project_id, String::from("rows"));
+        project_manager
+    }
+
+    fn selection(text_columns: &[&str], metadata_columns: &[&str]) -> RowSelection {
+        RowSelection {
+            id_column: String::from("id"),
+            text_columns: text_columns.iter().map(|column| column.to_string()).collect(),
+            metadata_columns: metadata_columns.iter().map(|column| column.to_string()).collect(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_reimported_rows_are_upserted() {
+        let pool = setup_db().await;
+        // A high id keeps the test's files apart from real project data
+        let project_id: i64 = 990_046;
+        let project_manager = setup_project(&pool, project_id).await;
+
+        let csv = "id,question,answer,category\n\
+            faq-1,How do I pay?,By card.,billing\n\
+            faq-2,How do I log in?,With your email.,account\n";
+        let rows = rows::read(RowFormat::detect(csv.as_bytes()), csv.as_bytes()).unwrap();
+        let selection = selection(&[], &["category"]).resolve(&rows.columns).unwrap();
+        assert_eq!(selection.text_columns, vec!["question", "answer"]);
+        let import = documents::import_rows(&project_manager, &pool, project_id, rows, &selection).await.unwrap();
+        assert_eq!(import.rows.len(), 2);
+        assert!(import.rows.iter().all(|row| row.stored.status == StoreStatus::Created));
+
+        // One answer changed, the other row is the same
+        let csv = csv.replace("By card.", "By card or transfer.");
+        let rows = rows::read(RowFormat::Csv, csv.as_bytes()).unwrap();
+        let import = documents::import_rows(&project_manager, &pool, project_id, rows, &selection).await.unwrap();
+        let statuses: Vec<(&str, &StoreStatus)> = import.rows.iter().map(|row| (row.external_id.as_str(), &row.stored.status)).collect();
+        assert_eq!(statuses, vec![("faq-1", &StoreStatus::Updated), ("faq-2", &StoreStatus::Unchanged)]);
+
+        let files: Vec<(String, i64, String)> = sqlx::query_as("SELECT external_id, current_version, metadata FROM file_entry WHERE project_id = ? ORDER BY id")
+            .bind(project_id)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].1, 2);
+        assert_eq!(serde_json::from_str::<Value>(&files[0].2).unwrap(), json!({"category": "billing"}));
+        let path: String = sqlx::query_scalar("SELECT path FROM file_version WHERE file_id = ? AND version = 2")
+            .bind(import.rows[0].stored.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "How do I pay?\n\nBy card or transfer.");
+
+        std::fs::remove_dir_all(file_store::project_dir(project_id)).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_unusable_rows_are_skipped() {
+        let pool = setup_db().await;
+        let project_id: i64 = 990_047;
+        let project_manager = setup_project(&pool, project_id).await;
+
+        let jsonl = "{\"id\": 7, \"title\": \"Refunds\", \"body\": \"Within 30 days.\", \"tier\": 2}\n\
+            {\"id\": 8, \"title\": \"Broken\"\n\
+            {\"title\": \"No id\"}\n\
+            {\"id\": 9, \"title\": \"\", \"body\": null}\n";
+        let rows = rows::read(RowFormat::detect(jsonl.as_bytes()), jsonl.as_bytes()).unwrap();
+        assert_eq!(selection(&["summary"], &[]).resolve(&rows.columns).unwrap_err(), "Unknown column summary");
+        let selection = selection(&["title", "body"], &["tier"]).resolve(&rows.columns).unwrap();
+        let import = documents::import_rows(&project_manager, &pool, project_id, rows, &selection).await.unwrap();
+        assert_eq!(import.rows.len(), 1);
+        assert_eq!(import.rows[0].external_id, "7");
+        let lines: Vec<usize> = import.skipped.iter().map(|skipped| skipped.line).collect();
+        assert_eq!(lines, vec![2, 3, 4]);
+        assert_eq!(import.skipped[2], SkippedRow { line: 4, reason: String::from("no text") });
+
+        std::fs::remove_dir_all(file_store::project_dir(project_id)).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_search_filtered_by_metadata() {
+        let pool = setup_db().await;
+        sqlx::query("INSERT INTO projects (name, description) VALUES ('test_project', 'test_description')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        sqlx::query(r#"INSERT INTO file_entry (name, path, project_id, metadata) VALUES
+            ('a', 'a', 1, '{"category": "billing", "tier": 1}'),
+            ('b', 'b', 1, '{"category": "account", "tier": 2}'),
+            ('c', 'c', 1, NULL)"#)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert files.");
+        sqlx::query("INSERT INTO file_embedding (file_id, start_byte, end_byte, embedding) VALUES (1, 0, 1, CAST('[1.0, 0.0]' AS BLOB)), (2, 0, 1, CAST('[0.0, 1.0]' AS BLOB)), (3, 0, 1, CAST('[0.0, 1.0]' AS BLOB))")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert embeddings.");
+
+        let query = Embedding { embedding: vec![0.0, 1.0], start_byte: -1, end_byte: -1, file_id: -1, version: -1 };
+        let filter = |value: Value| -> Map<String, Value> { value.as_object().unwrap().clone() };
+
+        let closest = search_filtered(&pool, 1, &filter(json!({"category": "billing"})), &query).await.unwrap();
+        assert_eq!(closest.map(|embedding| embedding.file_id), Some(1));
+        let closest = search_filtered(&pool, 1, &filter(json!({"category": "account", "tier": 2})), &query).await.unwrap();
+        assert_eq!(closest.map(|embedding| embedding.file_id), Some(2));
+        let closest = search_filtered(&pool, 1, &filter(json!({"tier": "2"})), &query).await.unwrap();
+        assert!(closest.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_document_api() {
+        let pool = setup_db().await;
+        let project_id: i64 = 990_048;
+        let project_manager = setup_project(&pool, project_id).await;
+        let job_queue = web::Data::new(JobQueue::new(pool.clone(), project_manager.clone()));
+        let project_manager = web::Data::new(project_manager);
+        let db_pool = web::Data::new(pool.clone());
+
+        let new_document = || NewDocument {
+            external_id: Some(String::from("snippet-1")),
+            text: String::from("Reset the router."),
+            metadata: Some(json!({"topic": "network"}).as_object().unwrap().clone()),
+            ..Default::default()
+        };
+        let result = create_document(project_manager.clone(), db_pool.clone(), job_queue.clone(), web::Path::from(project_id), web::Json(new_document()), None).await;
+        assert_eq!(result.status(), StatusCode::OK);
+        let result = create_document(project_manager.clone(), db_pool.clone(), job_queue.clone(), web::Path::from(project_id), web::Json(new_document()), None).await;
+        assert_eq!(result.status(), StatusCode::CONFLICT);
+
+        let (file_id, name): (i64, String) = sqlx::query_as("SELECT id, name FROM file_entry WHERE project_id = ?")
+            .bind(project_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(name, "snippet-1");
+
+        let update = DocumentUpdate { text: String::from("Reset the router, then wait."), embed: true, ..Default::default() };
+        let result = replace_document(project_manager.clone(), db_pool.clone(), job_queue.clone(), web::Path::from((project_id, file_id)), web::Json(update), None).await;
+        assert_eq!(result.status(), StatusCode::OK);
+        let (version, metadata): (i64, String) = sqlx::query_as("SELECT current_version, metadata FROM file_entry WHERE id = ?")
+            .bind(file_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(version, 2);
+        assert_eq!(serde_json::from_str::<Value>(&metadata).unwrap(), json!({"topic": "network"}));
+        let jobs: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM embedding_job WHERE file_id = ?").bind(file_id).fetch_one(&pool).await.unwrap();
+        assert_eq!(jobs, 1);
+
+        let result = delete_file(project_manager.clone(), db_pool.clone(), web::Path::from((project_id, file_id))).await;
+        assert_eq!(result.status(), StatusCode::OK);
+        let update = DocumentUpdate { text: String::from("Gone"), ..Default::default() };
+        let result = replace_document(project_manager, db_pool, job_queue, web::Path::from((project_id, file_id)), web::Json(update), None).await;
+        assert_eq!(result.status(), StatusCode::NOT_FOUND);
+
+        let _ = std::fs::remove_dir_all(file_store::project_dir(project_id));
+    }
+}
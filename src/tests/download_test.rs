@@ -0,0 +1,130 @@
+#[cfg(test)]
+mod tests {
+    use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test::TestRequest;
+    use sqlx::SqlitePool;
+    use crate::handlers::download_handler::{download_chunk, download_file, ChunkQuery, DownloadQuery};
+    use crate::handlers::project_handler::get_file_versions;
+    use crate::memory_management::project_manager::ProjectManager;
+    use crate::utils::documents::{self, TextDocument};
+    use crate::utils::file_store;
+    use crate::utils::permissions;
+    use std::sync::{Arc, Mutex};
+    use tokio::fs::read_to_string;
+
+    async fn setup_db() -> SqlitePool {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+
+        // Read SQL commands from file
+        let sql_commands = read_to_string("init.sql").await.expect("Could not read SQL file");
+
+        // Execute SQL commands
+        sqlx::query(&sql_commands).execute(&pool).await.expect("Could not execute SQL commands");
+
+        pool
+    }
+
+    async fn body(response: actix_web::HttpResponse) -> String {
+        String::from_utf8(actix_web::body::to_bytes(response.into_body()).await.unwrap().to_vec()).unwrap()
+    }
+
+    async fn as_user(req: &HttpRequest, user_id: i64) -> web::ReqData<i64> {
+        req.extensions_mut().insert(user_id);
+        web::ReqData::<i64>::extract(req).await.unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_download_file_and_chunk() {
+        let pool = setup_db().await;
+        // A high id keeps the test's files apart from real project data
+        let project_id: i64 = 990_050;
+        sqlx::query("INSERT INTO projects (id, name, description, embedding_provider) VALUES (?, 'downloads', 'test', 'fake')")
+            .bind(project_id)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        let project_manager = Arc::new(Mutex::new(ProjectManager::new(pool.clone())));
+        project_manager.lock().unwrap().add_blank_project(project_id, String::from("downloads"));
+
+        let document = TextDocument { name: String::from("docs/guide.md"), external_id: None, text: String::from("# Guide\nReset the router."), metadata: None };
+        let stored = documents::upsert(&project_manager, &pool, project_id, &document, None).await.unwrap();
+        sqlx::query("INSERT INTO file_chunk (file_id, version, start_byte, end_byte, chunk_hash) VALUES (?, 1, 8, 25, 'hash')")
+            .bind(stored.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let db_pool = web::Data::new(pool.clone());
+        let path = || web::Path::from((project_id, stored.id));
+
+        let req = TestRequest::default().to_http_request();
+        let result = download_file(req, db_pool.clone(), path(), web::Query(DownloadQuery { version: None }), None).await;
+        assert_eq!(result.status(), StatusCode::OK);
+        assert_eq!(result.headers().get(header::CONTENT_TYPE).unwrap(), "text/markdown");
+        assert_eq!(result.headers().get(header::CONTENT_DISPOSITION).unwrap(), "attachment; filename=\"guide.md\"");
+        assert!(result.headers().contains_key(header::ETAG));
+        assert_eq!(body(result).await, "# Guide\nReset the router.");
+
+        let req = TestRequest::default().insert_header((header::RANGE, "bytes=2-6")).to_http_request();
+        let result = download_file(req, db_pool.clone(), path(), web::Query(DownloadQuery { version: None }), None).await;
+        assert_eq!(result.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body(result).await, "Guide");
+        let req = TestRequest::default().to_http_request();
+        let result = download_file(req, db_pool.clone(), path(), web::Query(DownloadQuery { version: Some(2) }), None).await;
+        assert_eq!(result.status(), StatusCode::NOT_FOUND);
+
+        let chunk = |start_byte, end_byte| web::Query(ChunkQuery { version: None, start_byte, end_byte });
+        let req = TestRequest::default().to_http_request();
+        let result = download_chunk(req, db_pool.clone(), path(), chunk(8, 25), None).await;
+        assert_eq!(result.status(), StatusCode::OK);
+        assert_eq!(result.headers().get(header::CONTENT_DISPOSITION).unwrap(), "attachment; filename=\"guide.md-8-25.txt\"");
+        let etag = result.headers().get(header::ETAG).unwrap().clone();
+        assert_eq!(body(result).await, "Reset the router.");
+
+        let req = TestRequest::default().insert_header((header::IF_NONE_MATCH, etag)).to_http_request();
+        let result = download_chunk(req, db_pool.clone(), path(), chunk(8, 25), None).await;
+        assert_eq!(result.status(), StatusCode::NOT_MODIFIED);
+        let req = TestRequest::default().insert_header((header::RANGE, "bytes=-7")).to_http_request();
+        let result = download_chunk(req, db_pool.clone(), path(), chunk(8, 25), None).await;
+        assert_eq!(result.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(result.headers().get(header::CONTENT_RANGE).unwrap(), "bytes 10-16/17");
+        assert_eq!(body(result).await, "router.");
+        let req = TestRequest::default().insert_header((header::RANGE, "bytes=40-50")).to_http_request();
+        let result = download_chunk(req, db_pool.clone(), path(), chunk(8, 25), None).await;
+        assert_eq!(result.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        let req = TestRequest::default().to_http_request();
+        let result = download_chunk(req, db_pool.clone(), path(), chunk(0, 8), None).await;
+        assert_eq!(result.status(), StatusCode::NOT_FOUND);
+
+        // Users need a permission on the project
+        sqlx::query("INSERT INTO users (id, username, hashed_password) VALUES (1, 'owner', 'x'), (2, 'other', 'x')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO user_project (user_id, project_id, permission_type) VALUES (1, ?, 'read')")
+            .bind(project_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(permissions::can_read_project(&pool, project_id, None).await.unwrap());
+        assert!(permissions::can_read_project(&pool, project_id, Some(1)).await.unwrap());
+        let req = TestRequest::default().to_http_request();
+        let user = as_user(&req, 2).await;
+        let result = download_file(req, db_pool.clone(), path(), web::Query(DownloadQuery { version: None }), Some(user)).await;
+        assert_eq!(result.status(), StatusCode::FORBIDDEN);
+        let req = TestRequest::default().to_http_request();
+        let user = as_user(&req, 2).await;
+        let result = get_file_versions(db_pool.clone(), path(), Some(user)).await;
+        assert_eq!(result.status(), StatusCode::FORBIDDEN);
+        let req = TestRequest::default().to_http_request();
+        let user = as_user(&req, 1).await;
+        let result = download_chunk(req, db_pool.clone(), path(), chunk(8, 25), Some(user)).await;
+        assert_eq!(result.status(), StatusCode::OK);
+        let req = TestRequest::default().to_http_request();
+        let user = as_user(&req, 1).await;
+        let result = get_file_versions(db_pool, path(), Some(user)).await;
+        assert_eq!(result.status(), StatusCode::OK);
+
+        std::fs::remove_dir_all(file_store::project_dir(project_id)).unwrap();
+    }
+}
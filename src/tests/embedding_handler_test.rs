@@ -0,0 +1,250 @@
+#[cfg(test)]
+mod tests {
+    use actix_web::web;
+    use actix_web::http::StatusCode;
+    use sqlx::SqlitePool;
+    use crate::handlers::embedding_handler::{embed_file, get_similiar_text, run_embeddings_and_store, EmbedOptions};
+    use crate::memory_management::project_manager::ProjectManager;
+    use crate::chunking::strategy::ChunkingStrategy;
+    use crate::chunking::tokens::Cl100k;
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+    use tokio::fs::read_to_string;
+
+    async fn setup_db() -> SqlitePool {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+
+        // Read SQL commands from file
+        let sql_commands = read_to_string("init.sql").await.expect("Could not read SQL file");
+
+        // Execute SQL commands
+        sqlx::query(&sql_commands).execute(&pool).await.expect("Could not execute SQL commands");
+
+        pool
+    }
+
+    #[actix_rt::test]
+    async fn test_embed_file_with_fake_provider() {
+        let pool = setup_db().await;
+        let contents: String = (0..2000).map(|i| format!("line {} of the sample file\n", i)).collect();
+        let path = std::env::temp_dir().join("embedding_handler_test.txt");
+        std::fs::write(&path, &contents).unwrap();
+
+        sqlx::query("INSERT INTO projects (name, description, embedding_provider) VALUES ('test_project', 'test_description', 'fake')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        sqlx::query("INSERT INTO file_entry (name, path, project_id) VALUES ('sample.txt', ?, 1)")
+            .bind(path.to_str().unwrap())
+            .execute(&pool)
+            .await
+            .expect("Failed to insert file.");
+
+        let project_manager = web::Data::new(Arc::new(Mutex::new(ProjectManager::new(pool.clone()))));
+        project_manager.lock().unwrap().add_blank_project(1, String::from("test_project"));
+
+        let result = embed_file(project_manager.clone(), web::Data::new(pool.clone()), web::Path::from(1), web::Query(EmbedOptions::default()), None).await;
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let ranges: Vec<(i64, i64)> = sqlx::query_as("SELECT start_byte, end_byte FROM file_embedding WHERE file_id = 1 ORDER BY start_byte")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        let expected: Vec<(i64, i64)> = ChunkingStrategy::default().chunk(&contents, &Cl100k).into_iter()
+            .map(|chunk| (chunk.start as i64, chunk.end as i64))
+            .collect();
+        assert_eq!(ranges, expected);
+        assert_eq!(project_manager.lock().unwrap().snapshot()[&1].embedding_counts[&1], expected.len());
+
+        let embedded_hash: Option<String> = sqlx::query_scalar("SELECT embedded_hash FROM file_entry WHERE id = 1")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(embedded_hash.is_some());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_embed_file_records_project_chunking() {
+        let pool = setup_db().await;
+        let contents: String = (0..500).map(|i| format!("Sentence {} of the sample file. ", i)).collect();
+        let path = std::env::temp_dir().join("embedding_handler_chunking_test.txt");
+        std::fs::write(&path, &contents).unwrap();
+
+        let strategy = ChunkingStrategy::SlidingWindow { window_tokens: 64, overlap_tokens: 16 };
+        sqlx::query("INSERT INTO projects (name, description, embedding_provider, chunking) VALUES ('test_project', 'test_description', 'fake', ?)")
+            .bind(strategy.to_json())
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        sqlx::query("INSERT INTO file_entry (name, path, project_id) VALUES ('sample.txt', ?, 1)")
+            .bind(path.to_str().unwrap())
+            .execute(&pool)
+            .await
+            .expect("Failed to insert file.");
+        sqlx::query("INSERT INTO file_version (file_id, version, path, content_hash, size) VALUES (1, 1, ?, '', 0)")
+            .bind(path.to_str().unwrap())
+            .execute(&pool)
+            .await
+            .expect("Failed to insert version.");
+
+        let project_manager = web::Data::new(Arc::new(Mutex::new(ProjectManager::new(pool.clone()))));
+        project_manager.lock().unwrap().add_blank_project(1, String::from("test_project"));
+
+        let result = embed_file(project_manager.clone(), web::Data::new(pool.clone()), web::Path::from(1), web::Query(EmbedOptions::default()), None).await;
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let ranges: Vec<(i64, i64)> = sqlx::query_as("SELECT start_byte, end_byte FROM file_embedding WHERE file_id = 1 ORDER BY start_byte")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        let expected: Vec<(i64, i64)> = strategy.chunk(&contents, &Cl100k).into_iter()
+            .map(|chunk| (chunk.start as i64, chunk.end as i64))
+            .collect();
+        assert_eq!(ranges, expected);
+
+        // The version remembers its strategy even if the project's setting changes later
+        let chunking: Option<String> = sqlx::query_scalar("SELECT chunking FROM file_version WHERE file_id = 1 AND version = 1")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(ChunkingStrategy::parse(&chunking.unwrap()).unwrap(), strategy);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_embed_source_file_by_symbol() {
+        let pool = setup_db().await;
+        let contents = "fn parse(input: &str) -> usize {\n    input.len()\n}\n\nfn main() {\n    println!(\"{}\", parse(\"x\"));\n}\n";
+        let path = std::env::temp_dir().join("embedding_handler_code_test.rs");
+        std::fs::write(&path, contents).unwrap();
+
+        sqlx::query("INSERT INTO projects (name, description, embedding_provider, chunking) VALUES ('test_project', 'test_description', 'fake', ?)")
+            .bind(ChunkingStrategy::Code { max_tokens: 512 }.to_json())
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        sqlx::query("INSERT INTO file_entry (name, path, project_id) VALUES ('src/main.rs', ?, 1)")
+            .bind(path.to_str().unwrap())
+            .execute(&pool)
+            .await
+            .expect("Failed to insert file.");
+
+        let project_manager = web::Data::new(Arc::new(Mutex::new(ProjectManager::new(pool.clone()))));
+        project_manager.lock().unwrap().add_blank_project(1, String::from("test_project"));
+
+        let result = embed_file(project_manager.clone(), web::Data::new(pool.clone()), web::Path::from(1), web::Query(EmbedOptions::default()), None).await;
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let symbols: Vec<(Option<String>, Option<i64>, Option<i64>)> = sqlx::query_as("SELECT symbol, start_line, end_line FROM file_embedding WHERE file_id = 1 ORDER BY start_byte")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(symbols, vec![
+            (Some(String::from("parse")), Some(1), Some(3)),
+            (Some(String::from("main")), Some(5), Some(7)),
+        ]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_retry_embeds_only_failed_chunks() {
+        let pool = setup_db().await;
+        let contents: String = (0..2000).map(|i| format!("line {} of the retried file\n", i)).collect();
+        let path = std::env::temp_dir().join("embedding_handler_retry_test.txt");
+        std::fs::write(&path, &contents).unwrap();
+
+        sqlx::query("INSERT INTO projects (name, description, embedding_provider) VALUES ('test_project', 'test_description', 'fake')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        sqlx::query("INSERT INTO file_entry (name, path, project_id) VALUES ('sample.txt', ?, 1)")
+            .bind(path.to_str().unwrap())
+            .execute(&pool)
+            .await
+            .expect("Failed to insert file.");
+
+        let project_manager = Arc::new(Mutex::new(ProjectManager::new(pool.clone())));
+        project_manager.lock().unwrap().add_blank_project(1, String::from("test_project"));
+
+        let report = run_embeddings_and_store(&project_manager, &pool, 1, false, None, None).await.unwrap().unwrap();
+        assert!(report.chunks > 2);
+
+        // As left behind by a provider that rejected the batch holding the first two chunks
+        sqlx::query("UPDATE file_chunk SET status = 'failed', error = 'rejected' WHERE file_id = 1 AND start_byte IN (SELECT start_byte FROM file_chunk WHERE file_id = 1 ORDER BY start_byte LIMIT 2)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM file_embedding WHERE file_id = 1 AND start_byte NOT IN (SELECT start_byte FROM file_chunk WHERE file_id = 1 AND status = 'embedded')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE file_entry SET embedded_hash = NULL WHERE id = 1")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let retried = run_embeddings_and_store(&project_manager, &pool, 1, false, None, None).await.unwrap().unwrap();
+        assert_eq!(retried.embedded, 2);
+        assert_eq!(retried.reused, report.chunks - 2);
+
+        let states: Vec<(String, i64)> = sqlx::query_as("SELECT status, COUNT(*) FROM file_chunk WHERE file_id = 1 GROUP BY status")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(states, vec![(String::from("embedded"), report.chunks as i64)]);
+
+        // Embedded already, unless forced
+        assert!(run_embeddings_and_store(&project_manager, &pool, 1, false, None, None).await.unwrap().is_none());
+        let forced = run_embeddings_and_store(&project_manager, &pool, 1, true, None, None).await.unwrap().unwrap();
+        assert_eq!(forced.embedded, report.chunks);
+        assert_eq!(forced.reused, 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_search_returns_chunk_text_and_location() {
+        let pool = setup_db().await;
+        let contents: String = (0..2000).map(|i| format!("entry {} of the searched file\n", i)).collect();
+        let path = std::env::temp_dir().join("embedding_handler_search_test.txt");
+        std::fs::write(&path, &contents).unwrap();
+
+        sqlx::query("INSERT INTO projects (name, description, embedding_provider) VALUES ('test_project', 'test_description', 'fake')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        sqlx::query("INSERT INTO file_entry (name, path, project_id) VALUES ('searched.txt', ?, 1)")
+            .bind(path.to_str().unwrap())
+            .execute(&pool)
+            .await
+            .expect("Failed to insert file.");
+        let project_manager = web::Data::new(Arc::new(Mutex::new(ProjectManager::new(pool.clone()))));
+        project_manager.lock().unwrap().add_blank_project(1, String::from("test_project"));
+        run_embeddings_and_store(&project_manager, &pool, 1, false, None, None).await.unwrap();
+
+        // Searching for the text of a chunk finds that chunk
+        let chunk = ChunkingStrategy::default().chunk(&contents, &Cl100k)[1].clone();
+        let text = &contents[chunk.start..chunk.end];
+        let search = |include_embedding: bool| web::Json(serde_json::from_value(json!({ "text": text, "include_embedding": include_embedding })).unwrap());
+        let result = get_similiar_text(project_manager.clone(), web::Data::new(pool.clone()), web::Path::from(1), search(false), None).await;
+        assert_eq!(result.status(), StatusCode::OK);
+        let hit: Value = serde_json::from_slice(&actix_web::body::to_bytes(result.into_body()).await.unwrap()).unwrap();
+        assert_eq!(hit["file_name"], "searched.txt");
+        assert_eq!(hit["text"], text);
+        assert_eq!(hit["start_byte"], chunk.start);
+        assert_eq!(hit["start_line"], contents[..chunk.start].matches('\n').count() + 1);
+        assert_eq!(hit["end_line"], contents[..chunk.end].matches('\n').count() + 1);
+        assert!(hit["score"].as_f64().unwrap() > 0.999);
+        assert!(hit.get("embedding").is_none());
+
+        let result = get_similiar_text(project_manager, web::Data::new(pool.clone()), web::Path::from(1), search(true), None).await;
+        let hit: Value = serde_json::from_slice(&actix_web::body::to_bytes(result.into_body()).await.unwrap()).unwrap();
+        assert!(hit["embedding"].as_array().is_some_and(|embedding| !embedding.is_empty()));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
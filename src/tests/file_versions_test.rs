@@ -0,0 +1,108 @@
+#[cfg(test)]
+mod tests {
+    use sqlx::SqlitePool;
+    use crate::utils::file_store;
+    use crate::utils::file_versions::{adopt_unversioned, store_version, PendingFile, StoreStatus};
+    use crate::utils::hash::sha256_hex;
+    use tokio::fs::read_to_string;
+
+    async fn setup_db() -> SqlitePool {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+
+        // Read SQL commands from file
+        let sql_commands = read_to_string("init.sql").await.expect("Could not read SQL file");
+
+        // Execute SQL commands
+        sqlx::query(&sql_commands).execute(&pool).await.expect("Could not execute SQL commands");
+
+        pool
+    }
+
+    fn write_temp(project_id: i64, name: &str, contents: &str) -> PendingFile {
+        let path = file_store::temp_path(project_id);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        PendingFile { name: String::from(name), temp_path: path, content_hash: sha256_hex(contents.as_bytes()), size: contents.len() as i64 }
+    }
+
+    #[actix_rt::test]
+    async fn test_store_versions_with_retention() {
+        let pool = setup_db().await;
+        // A high id keeps the test's files apart from real project data
+        let project_id: i64 = 990_031;
+
+        sqlx::query("INSERT INTO projects (id, name, description, max_file_versions) VALUES (?, 'versions', 'test', 2)")
+            .bind(project_id)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+
+        let mut statuses = Vec::new();
+        for contents in ["first", "second", "second", "third"] {
+            let file = write_temp(project_id, "notes.txt", contents);
+            let stored = store_version(&pool, project_id, &file, None, None)
+                .await
+                .expect("Failed to store version.");
+            statuses.push((stored.version, stored.status));
+        }
+
+        assert_eq!(statuses, vec![
+            (1, StoreStatus::Created),
+            (2, StoreStatus::Updated),
+            (2, StoreStatus::Unchanged),
+            (3, StoreStatus::Updated),
+        ]);
+
+        let versions: Vec<(i64, String)> = sqlx::query_as("SELECT version, path FROM file_version ORDER BY version")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(versions.iter().map(|(version, _)| *version).collect::<Vec<i64>>(), vec![2, 3]);
+        assert_eq!(std::fs::read_to_string(&versions[1].1).unwrap(), "third");
+
+        let (path, current_version): (String, i64) = sqlx::query_as("SELECT path, current_version FROM file_entry")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(current_version, 3);
+        assert_eq!(path, versions[1].1);
+
+        std::fs::remove_dir_all(file_store::project_dir(project_id)).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_adopt_unversioned_files() {
+        let pool = setup_db().await;
+        let project_id: i64 = 990_032;
+        sqlx::query("INSERT INTO projects (id, name, description) VALUES (?, 'legacy', 'test')")
+            .bind(project_id)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        // Uploads used to be stored directly in the project directory
+        let legacy_path = file_store::project_dir(project_id).join("notes.txt");
+        std::fs::create_dir_all(file_store::project_dir(project_id)).unwrap();
+        std::fs::write(&legacy_path, "old notes").unwrap();
+        sqlx::query("INSERT INTO file_entry (id, name, path, project_id) VALUES (1, 'notes.txt', ?, ?), (2, 'gone.txt', 'missing/gone.txt', ?)")
+            .bind(legacy_path.to_string_lossy().to_string())
+            .bind(project_id)
+            .bind(project_id)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert files.");
+
+        adopt_unversioned(&pool).await.unwrap();
+
+        let (path, content_hash): (String, String) = sqlx::query_as("SELECT path, content_hash FROM file_entry WHERE id = 1").fetch_one(&pool).await.unwrap();
+        let version: (i64, String, i64) = sqlx::query_as("SELECT version, path, size FROM file_version WHERE file_id = 1").fetch_one(&pool).await.unwrap();
+        assert_eq!(version, (1, path.clone(), 9));
+        assert_eq!(path, file_store::version_path(project_id, 1, 1, "notes.txt").to_string_lossy());
+        assert_eq!(content_hash, sha256_hex(b"old notes"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old notes");
+        assert!(!legacy_path.exists());
+        let versions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM file_version WHERE file_id = 2").fetch_one(&pool).await.unwrap();
+        assert_eq!(versions, 0);
+
+        std::fs::remove_dir_all(file_store::project_dir(project_id)).unwrap();
+    }
+}
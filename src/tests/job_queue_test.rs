@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod tests {
+    use sqlx::SqlitePool;
+    use crate::jobs::JobQueue;
+    use crate::memory_management::project_manager::ProjectManager;
+    use std::sync::{Arc, Mutex};
+    use tokio::fs::read_to_string;
+
+    async fn setup_db() -> SqlitePool {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+
+        // Read SQL commands from file
+        let sql_commands = read_to_string("init.sql").await.expect("Could not read SQL file");
+
+        // Execute SQL commands
+        sqlx::query(&sql_commands).execute(&pool).await.expect("Could not execute SQL commands");
+
+        pool
+    }
+
+    async fn setup_queue(pool: &SqlitePool, path: &str) -> JobQueue {
+        sqlx::query("INSERT INTO projects (name, description, embedding_provider) VALUES ('test_project', 'test_description', 'fake')")
+            .execute(pool)
+            .await
+            .expect("Failed to insert project.");
+        sqlx::query("INSERT INTO file_entry (name, path, project_id) VALUES ('sample.txt', ?, 1)")
+            .bind(path)
+            .execute(pool)
+            .await
+            .expect("Failed to insert file.");
+
+        let project_manager = Arc::new(Mutex::new(ProjectManager::new(pool.clone())));
+        project_manager.lock().unwrap().add_blank_project(1, String::from("test_project"));
+        JobQueue::new(pool.clone(), project_manager)
+    }
+
+    #[actix_rt::test]
+    async fn test_job_runs_to_completion() {
+        let pool = setup_db().await;
+        let contents: String = (0..2000).map(|i| format!("line {} of the sample file\n", i)).collect();
+        let path = std::env::temp_dir().join("job_queue_test.txt");
+        std::fs::write(&path, &contents).unwrap();
+        let queue = setup_queue(&pool, path.to_str().unwrap()).await;
+
+        let job_id = queue.enqueue(1, 1, false, None).await.unwrap();
+        // The file already has a queued job
+        assert_eq!(queue.enqueue(1, 1, false, None).await.unwrap(), job_id);
+        assert_eq!(queue.get(job_id).await.unwrap().unwrap().status, "queued");
+
+        assert!(queue.run_next().await.unwrap());
+        assert!(!queue.run_next().await.unwrap());
+
+        let job = queue.get(job_id).await.unwrap().unwrap();
+        assert_eq!(job.status, "completed");
+        assert!(job.chunks_total > 0);
+        assert_eq!(job.chunks_done, job.chunks_total);
+        assert!(job.tokens > 0);
+        assert!(job.error.is_none());
+        assert!(job.finished_at.is_some());
+
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM file_embedding WHERE file_id = 1")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(stored, job.chunks_total);
+
+        // A finished job can't be cancelled, and a new one can be queued for the file
+        assert!(!queue.cancel(job_id).await.unwrap());
+        assert_ne!(queue.enqueue(1, 1, false, None).await.unwrap(), job_id);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_failed_job_records_error() {
+        let pool = setup_db().await;
+        let queue = setup_queue(&pool, "/nonexistent/job_queue_test.txt").await;
+
+        let job_id = queue.enqueue(1, 1, false, None).await.unwrap();
+        assert!(queue.run_next().await.unwrap());
+
+        let job = queue.get(job_id).await.unwrap().unwrap();
+        assert_eq!(job.status, "failed");
+        assert!(job.error.is_some());
+    }
+
+    #[actix_rt::test]
+    async fn test_cancelled_job_is_not_run() {
+        let pool = setup_db().await;
+        let queue = setup_queue(&pool, "/nonexistent/job_queue_test.txt").await;
+
+        let job_id = queue.enqueue(1, 1, false, None).await.unwrap();
+        assert!(queue.cancel(job_id).await.unwrap());
+        assert!(!queue.run_next().await.unwrap());
+
+        let jobs = queue.list(1).await.unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].status, "cancelled");
+    }
+
+    #[actix_rt::test]
+    async fn test_resume_requeues_running_jobs() {
+        let pool = setup_db().await;
+        let queue = setup_queue(&pool, "/nonexistent/job_queue_test.txt").await;
+
+        let job_id = queue.enqueue(1, 1, false, None).await.unwrap();
+        // As left behind by a server that stopped in the middle of the job
+        sqlx::query("UPDATE embedding_job SET status = 'running', started_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(job_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(queue.resume().await.unwrap(), 1);
+        let job = queue.get(job_id).await.unwrap().unwrap();
+        assert_eq!(job.status, "queued");
+        assert!(job.started_at.is_none());
+    }
+}
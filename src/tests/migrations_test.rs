@@ -0,0 +1,55 @@
+#[cfg(test)]
+mod tests {
+    use sqlx::SqlitePool;
+    use crate::utils::migrations::{self, SCHEMA_VERSION};
+    use tokio::fs::read_to_string;
+
+    // The schema before any migration
+    const FIRST_SCHEMA: &str = r#"
+        CREATE TABLE projects (id INTEGER PRIMARY KEY AUTOINCREMENT, auto_load BOOLEAN DEFAULT 1, name TEXT NOT NULL, description TEXT NOT NULL);
+        CREATE TABLE users (id INTEGER PRIMARY KEY AUTOINCREMENT, username TEXT NOT NULL UNIQUE, hashed_password TEXT NOT NULL);
+        CREATE TABLE file_entry (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, path TEXT NOT NULL, project_id INTEGER NOT NULL);
+        CREATE TABLE user_project (user_id INTEGER NOT NULL, project_id INTEGER NOT NULL, permission_type TEXT NOT NULL, PRIMARY KEY (user_id, project_id));
+        CREATE TABLE file_embedding (file_id INTEGER NOT NULL, start_byte INTEGER NOT NULL, end_byte INTEGER NOT NULL, embedding BLOB NOT NULL, PRIMARY KEY (file_id, start_byte, end_byte));
+        CREATE INDEX idx_file_embedding_file_id ON file_embedding(file_id);
+    "#;
+
+    #[actix_rt::test]
+    async fn test_migrate_first_schema() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        sqlx::query(FIRST_SCHEMA).execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO projects (name, description) VALUES ('old', 'test')").execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO file_entry (name, path, project_id) VALUES ('a.txt', 'a.txt', 1), ('a.txt', 'copy/a.txt', 1)").execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO file_embedding (file_id, start_byte, end_byte, embedding) VALUES (1, 0, 3, X'5B315D')").execute(&pool).await.unwrap();
+
+        migrations::run(&pool).await.unwrap();
+        // A second run finds nothing left to do
+        migrations::run(&pool).await.unwrap();
+
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version").fetch_one(&pool).await.unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+        let project: (String, i64, String) = sqlx::query_as("SELECT index_type, max_file_versions, embedding_provider FROM projects").fetch_one(&pool).await.unwrap();
+        assert_eq!(project, (String::from("vptree"), 10, String::from("openai")));
+        let embedding: (i64, i64, i64) = sqlx::query_as("SELECT file_id, version, end_byte FROM file_embedding").fetch_one(&pool).await.unwrap();
+        assert_eq!(embedding, (1, 1, 3));
+        // A second version of the chunk fits next to the first now
+        sqlx::query("INSERT INTO file_embedding (file_id, version, start_byte, end_byte, embedding) VALUES (1, 2, 0, 3, X'5B315D')").execute(&pool).await.unwrap();
+        // Files of the same name are told apart
+        let names: Vec<String> = sqlx::query_scalar("SELECT name FROM file_entry ORDER BY id").fetch_all(&pool).await.unwrap();
+        assert_eq!(names, vec![String::from("a.txt"), String::from("a.txt (2)")]);
+        for table in ["file_version", "file_chunk", "embedding_cache", "token_usage", "embedding_job"] {
+            sqlx::query(&format!("SELECT COUNT(*) FROM {}", table)).fetch_one(&pool).await.unwrap();
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_migrate_current_schema() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        let sql_commands = read_to_string("init.sql").await.expect("Could not read SQL file");
+        sqlx::query(&sql_commands).execute(&pool).await.expect("Could not execute SQL commands");
+
+        migrations::run(&pool).await.unwrap();
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version").fetch_one(&pool).await.unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+    }
+}
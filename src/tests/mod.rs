@@ -1,2 +1,16 @@
 pub mod user_handler_test;
-pub mod project_handler_test;
\ No newline at end of file
+pub mod project_handler_test;
+pub mod project_manager_test;
+pub mod consistency_checker_test;
+pub mod chunking_test;
+pub mod file_versions_test;
+pub mod providers_test;
+pub mod embedding_handler_test;
+pub mod scheduler_test;
+pub mod job_queue_test;
+pub mod usage_test;
+pub mod parsing_test;
+pub mod archive_test;
+pub mod documents_test;
+pub mod download_test;
+pub mod migrations_test;
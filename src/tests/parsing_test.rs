@@ -0,0 +1,217 @@
+#[cfg(test)]
+mod tests {
+    use sqlx::SqlitePool;
+    use lopdf::content::{Content, Operation};
+    use lopdf::{dictionary, Document, Object, Stream};
+    use crate::chunking::strategy::ChunkingStrategy;
+    use crate::handlers::embedding_handler::run_embeddings_and_store;
+    use crate::memory_management::project_manager::ProjectManager;
+    use crate::parsing::{self, DocumentFormat, Section};
+    use crate::utils::file_store;
+    use crate::utils::file_versions::{store_version, PendingFile};
+    use crate::utils::hash::sha256_hex;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use tokio::fs::read_to_string;
+
+    async fn setup_db() -> SqlitePool {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+
+        // Read SQL commands from file
+        let sql_commands = read_to_string("init.sql").await.expect("Could not read SQL file");
+
+        // Execute SQL commands
+        sqlx::query(&sql_commands).execute(&pool).await.expect("Could not execute SQL commands");
+
+        pool
+    }
+
+    /// A PDF with one line of Helvetica text per page.
+    fn pdf(pages: &[&str]) -> Vec<u8> {
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let font_id = document.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Helvetica" });
+        let resources_id = document.add_object(dictionary! { "Font" => dictionary! { "F1" => font_id } });
+        let mut kids: Vec<Object> = Vec::new();
+        for text in pages {
+            let content = Content {
+                operations: vec![
+                    Operation::new("BT", vec![]),
+                    Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                    Operation::new("Td", vec![72.into(), 720.into()]),
+                    Operation::new("Tj", vec![Object::string_literal(*text)]),
+                    Operation::new("ET", vec![]),
+                ],
+            };
+            let content_id = document.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+            kids.push(document.add_object(dictionary! { "Type" => "Page", "Parent" => pages_id, "Contents" => content_id }).into());
+        }
+        let count = kids.len() as i64;
+        document.objects.insert(pages_id, Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => count,
+            "Resources" => resources_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        }));
+        let catalog_id = document.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        document.trailer.set("Root", catalog_id);
+        let mut bytes = Vec::new();
+        document.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn docx(document_xml: &str) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.start_file("word/document.xml", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(document_xml.as_bytes()).unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn section_texts<'a>(text: &'a str, sections: &[Section]) -> Vec<(Option<u32>, Option<String>, &'a str)> {
+        sections.iter().map(|section| (section.page, section.heading.clone(), &text[section.start..section.end])).collect()
+    }
+
+    #[test]
+    fn test_pdf_sections_follow_pages() {
+        let bytes = pdf(&["First page text", "Second page text"]);
+        assert_eq!(DocumentFormat::detect("report", &bytes), DocumentFormat::Pdf);
+
+        let parsed = parsing::parse(DocumentFormat::Pdf, &bytes).unwrap().unwrap();
+        assert_eq!(parsed.text, "First page text\n\nSecond page text\n");
+        assert_eq!(section_texts(&parsed.text, &parsed.sections), vec![
+            (Some(1), None, "First page text\n\n"),
+            (Some(2), None, "Second page text\n"),
+        ]);
+
+        assert!(parsing::parse(DocumentFormat::Pdf, b"not a pdf").is_err());
+    }
+
+    #[test]
+    fn test_docx_headings_start_sections() {
+        let bytes = docx(r#"<?xml version="1.0"?>
+            <w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>
+                <w:p><w:r><w:t>Preface</w:t></w:r></w:p>
+                <w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Install</w:t></w:r></w:p>
+                <w:p><w:r><w:t xml:space="preserve">Run the </w:t></w:r><w:r><w:t>installer &amp; wait.</w:t></w:r></w:p>
+            </w:body></w:document>"#);
+        assert_eq!(DocumentFormat::detect("manual.docx", &bytes), DocumentFormat::Docx);
+
+        let parsed = parsing::parse(DocumentFormat::Docx, &bytes).unwrap().unwrap();
+        assert_eq!(parsed.text, "Preface\n\nInstall\n\nRun the installer & wait.\n");
+        assert_eq!(section_texts(&parsed.text, &parsed.sections), vec![
+            (None, None, "Preface\n\n"),
+            (None, Some(String::from("Install")), "Install\n\nRun the installer & wait.\n"),
+        ]);
+    }
+
+    #[test]
+    fn test_html_and_markdown_drop_markup() {
+        let html = "<!DOCTYPE html><html><head><title>Guide</title><style>p { color: red }</style></head><body>\
+            <p>Intro   with <b>bold</b> text</p><h2>Setup</h2><ul><li>one</li><li>two</li></ul><script>track()</script></body></html>";
+        assert_eq!(DocumentFormat::detect("guide", html.as_bytes()), DocumentFormat::Html);
+        let parsed = parsing::parse(DocumentFormat::Html, html.as_bytes()).unwrap().unwrap();
+        assert_eq!(parsed.text, "Intro with bold text\n\nSetup\n\none\ntwo\n");
+        assert_eq!(parsed.sections[1].heading.as_deref(), Some("Setup"));
+
+        let markdown = "Intro\n\n# Setup `cargo`\n\nRun *it*.\n\n```\nfn main() {}\n```\n";
+        let parsed = parsing::parse(DocumentFormat::detect("README.md", markdown.as_bytes()), markdown.as_bytes()).unwrap().unwrap();
+        assert_eq!(parsed.text, "Intro\n\n# Setup cargo\n\nRun it.\n\n```\nfn main() {}\n```\n");
+        assert_eq!(section_texts(&parsed.text, &parsed.sections)[1], (None, Some(String::from("Setup cargo")), "# Setup cargo\n\nRun it.\n\n```\nfn main() {}\n```\n"));
+
+        assert!(parsing::parse(DocumentFormat::detect("main.rs", b"fn main() {}"), b"fn main() {}").unwrap().is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_document_is_embedded_as_extracted_text() {
+        let pool = setup_db().await;
+        // A high id keeps the test's files apart from real project data
+        let project_id: i64 = 990_044;
+        sqlx::query("INSERT INTO projects (id, name, description, embedding_provider) VALUES (?, 'documents', 'test', 'fake')")
+            .bind(project_id)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+
+        let paragraphs: String = (0..300).map(|i| format!("<p>Paragraph {} of the <em>handbook</em>.</p>", i)).collect();
+        let html = format!("<html><body><h1>Handbook</h1>{}</body></html>", paragraphs);
+        let temp = file_store::temp_path(project_id);
+        std::fs::create_dir_all(temp.parent().unwrap()).unwrap();
+        std::fs::write(&temp, &html).unwrap();
+        let file = PendingFile { name: String::from("handbook.html"), temp_path: temp, content_hash: sha256_hex(html.as_bytes()), size: html.len() as i64 };
+        let stored = store_version(&pool, project_id, &file, None, None)
+            .await
+            .expect("Failed to store version.");
+
+        let project_manager = Arc::new(Mutex::new(ProjectManager::new(pool.clone())));
+        project_manager.lock().unwrap().add_blank_project(project_id, String::from("documents"));
+        run_embeddings_and_store(&project_manager, &pool, stored.id, false, None, None).await.unwrap().unwrap();
+
+        // The version was parsed when it was first embedded
+        let (format, text_path): (Option<String>, Option<String>) = sqlx::query_as("SELECT format, text_path FROM file_version WHERE file_id = ?")
+            .bind(stored.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(format.as_deref(), Some("html"));
+        let text = std::fs::read_to_string(text_path.unwrap()).unwrap();
+        assert!(text.starts_with("Handbook\n\nParagraph 0 of the handbook.\n"));
+
+        let ranges: Vec<(i64, i64)> = sqlx::query_as("SELECT start_byte, end_byte FROM file_embedding WHERE file_id = ? ORDER BY start_byte")
+            .bind(stored.id)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert!(!ranges.is_empty());
+        assert_eq!(ranges.last().unwrap().1, text.len() as i64);
+
+        std::fs::remove_dir_all(file_store::project_dir(project_id)).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_markdown_chunks_follow_headings() {
+        let pool = setup_db().await;
+        // A high id keeps the test's files apart from real project data
+        let project_id: i64 = 990_144;
+        sqlx::query("INSERT INTO projects (id, name, description, embedding_provider) VALUES (?, 'markdown', 'test', 'fake')")
+            .bind(project_id)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+
+        let markdown = "Intro\n\nHello world.\n\n## Setup\n\n```sh\n# install deps\nnpm i\n```\n\n## Usage\n\nRun *it*.\n";
+        let temp = file_store::temp_path(project_id);
+        std::fs::create_dir_all(temp.parent().unwrap()).unwrap();
+        std::fs::write(&temp, markdown).unwrap();
+        let file = PendingFile { name: String::from("guide.md"), temp_path: temp, content_hash: sha256_hex(markdown.as_bytes()), size: markdown.len() as i64 };
+        let chunking = ChunkingStrategy::Markdown { max_tokens: 512 };
+        let stored = store_version(&pool, project_id, &file, Some(&chunking), None)
+            .await
+            .expect("Failed to store version.");
+
+        let project_manager = Arc::new(Mutex::new(ProjectManager::new(pool.clone())));
+        project_manager.lock().unwrap().add_blank_project(project_id, String::from("markdown"));
+        run_embeddings_and_store(&project_manager, &pool, stored.id, false, None, None).await.unwrap().unwrap();
+
+        let text_path: Option<String> = sqlx::query_scalar("SELECT text_path FROM file_version WHERE file_id = ?")
+            .bind(stored.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        let text = std::fs::read_to_string(text_path.unwrap()).unwrap();
+        let ranges: Vec<(i64, i64)> = sqlx::query_as("SELECT start_byte, end_byte FROM file_embedding WHERE file_id = ? ORDER BY start_byte")
+            .bind(stored.id)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        let chunks: Vec<&str> = ranges.iter().map(|(start, end)| &text[*start as usize..*end as usize]).collect();
+        // The `#` comment in the code block stays in the Setup chunk
+        assert_eq!(chunks, vec![
+            "Intro\n\nHello world.\n\n",
+            "## Setup\n\n```sh\n# install deps\nnpm i\n```\n\n",
+            "## Usage\n\nRun it.\n",
+        ]);
+
+        std::fs::remove_dir_all(file_store::project_dir(project_id)).unwrap();
+    }
+}
@@ -7,12 +7,18 @@ mod tests {
     use sqlx::SqlitePool;
     use crate::handlers::project_handler::*;
     use crate::models::project::Project;
+    use crate::models::project_update::ProjectUpdate;
+    use crate::memory_management::project_manager::ProjectManager;
+    use std::sync::{Arc, Mutex};
     use std::fs;
     use std::io::Cursor;
     use std::path::Path;
     use actix_multipart::{Field, Multipart};
     use tokio::fs::read_to_string;
     use futures::stream;
+    use crate::jobs::JobQueue;
+    use crate::utils::file_store;
+    use crate::utils::uploads::UploadLimits;
 
     async fn setup_db() -> SqlitePool {
         let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
@@ -26,6 +32,10 @@ mod tests {
         pool
     }
 
+    fn project_manager(pool: &SqlitePool) -> web::Data<Arc<Mutex<ProjectManager>>> {
+        web::Data::new(Arc::new(Mutex::new(ProjectManager::new(pool.clone()))))
+    }
+
     #[actix_rt::test]
     async fn test_add_project() {
         let pool = setup_db().await;
@@ -34,9 +44,10 @@ mod tests {
             id: None,
             name: String::from("test_project"),
             description: String::from("test_description"),
+            ..Default::default()
         };
 
-        let result = add_project(web::Data::new(pool.clone()), web::Json(new_project)).await;
+        let result = add_project(project_manager(&pool), web::Data::new(pool.clone()), web::Json(new_project)).await;
 
         assert_eq!(result.status(), StatusCode::OK);
 
@@ -57,9 +68,10 @@ mod tests {
             id: None,
             name: String::from("test_project"),
             description: String::from("test_description"),
+            ..Default::default()
         };
     
-        let result = add_project(web::Data::new(pool.clone()), web::Json(new_project)).await;
+        let result = add_project(project_manager(&pool), web::Data::new(pool.clone()), web::Json(new_project)).await;
     
         assert_eq!(result.status(), StatusCode::OK);
     
@@ -87,5 +99,280 @@ mod tests {
         assert_eq!(project.name, "test_project");
         assert_eq!(project.description, "test_description");
     }
-}
 
+    #[actix_rt::test]
+    async fn test_delete_file() {
+        let pool = setup_db().await;
+
+        sqlx::query("INSERT INTO projects (name, description) VALUES ('test_project', 'test_description')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        sqlx::query("INSERT INTO file_entry (name, path, project_id) VALUES ('a.txt', './project_data/1/a.txt', 1)")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert file.");
+        sqlx::query("INSERT INTO file_embedding (file_id, start_byte, end_byte, embedding) VALUES (1, 0, 3, X'5B315D')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert embedding.");
+
+        let mut project_manager = ProjectManager::new(pool.clone());
+        project_manager.add_blank_project(1, String::from("test_project"));
+        let project_manager = web::Data::new(Arc::new(Mutex::new(project_manager)));
+
+        let result = delete_file(project_manager.clone(), web::Data::new(pool.clone()), web::Path::from((1, 1))).await;
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let files: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM file_entry").fetch_one(&pool).await.unwrap();
+        let embeddings: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM file_embedding").fetch_one(&pool).await.unwrap();
+        assert_eq!(files, 0);
+        assert_eq!(embeddings, 0);
+
+        let result = delete_file(project_manager, web::Data::new(pool.clone()), web::Path::from((1, 1))).await;
+        assert_eq!(result.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_update_project() {
+        let pool = setup_db().await;
+        let project_manager = project_manager(&pool);
+
+        let new_project = Project {
+            id: None,
+            name: String::from("test_project"),
+            description: String::from("test_description"),
+            ..Default::default()
+        };
+        let result = add_project(project_manager.clone(), web::Data::new(pool.clone()), web::Json(new_project)).await;
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let update = ProjectUpdate {
+            name: Some(String::from("renamed_project")),
+            description: None,
+            auto_load: Some(false),
+            index_type: Some(String::from("flat")),
+            ..Default::default()
+        };
+        let result = update_project(project_manager.clone(), web::Data::new(pool.clone()), web::Path::from(1), web::Json(update)).await;
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let project = sqlx::query_as::<_, Project>("SELECT * FROM projects WHERE id = 1")
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to query database.");
+        assert_eq!(project.name, "renamed_project");
+        assert_eq!(project.description, "test_description");
+        assert_eq!(project.auto_load, Some(false));
+        assert_eq!(project.index_type, Some(String::from("flat")));
+
+        let update = ProjectUpdate { index_type: Some(String::from("btree")), ..Default::default() };
+        let result = update_project(project_manager, web::Data::new(pool.clone()), web::Path::from(1), web::Json(update)).await;
+        assert_eq!(result.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_add_project_embedding_provider() {
+        let pool = setup_db().await;
+
+        let unknown = Project {
+            name: String::from("unknown_provider"),
+            description: String::from("test_description"),
+            embedding_provider: Some(String::from("word2vec")),
+            ..Default::default()
+        };
+        let result = add_project(project_manager(&pool), web::Data::new(pool.clone()), web::Json(unknown)).await;
+        assert_eq!(result.status(), StatusCode::BAD_REQUEST);
+
+        let local = Project {
+            name: String::from("local_provider"),
+            description: String::from("test_description"),
+            embedding_provider: Some(String::from("openai_compatible")),
+            embedding_base_url: Some(String::from("http://localhost:8080/v1")),
+            ..Default::default()
+        };
+        let result = add_project(project_manager(&pool), web::Data::new(pool.clone()), web::Json(local)).await;
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let provider: String = sqlx::query_scalar("SELECT embedding_provider FROM projects WHERE name = 'local_provider'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(provider, "openai_compatible");
+    }
+
+    #[actix_rt::test]
+    async fn test_update_embedding_model_with_embeddings() {
+        let pool = setup_db().await;
+
+        sqlx::query("INSERT INTO projects (name, description, embedding_model) VALUES ('test_project', 'test_description', 'text-embedding-3-small')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        let project_manager = project_manager(&pool);
+
+        // No vectors yet, so switching models is allowed
+        let update = ProjectUpdate { embedding_model: Some(Some(String::from("text-embedding-3-large"))), embedding_dimensions: Some(Some(256)), ..Default::default() };
+        let result = update_project(project_manager.clone(), web::Data::new(pool.clone()), web::Path::from(1), web::Json(update)).await;
+        assert_eq!(result.status(), StatusCode::OK);
+
+        sqlx::query("INSERT INTO file_entry (name, path, project_id) VALUES ('a.txt', './project_data/1/a.txt', 1)")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert file.");
+        sqlx::query("INSERT INTO file_embedding (file_id, start_byte, end_byte, embedding) VALUES (1, 0, 3, X'5B315D')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert embedding.");
+
+        let update = ProjectUpdate { embedding_model: Some(Some(String::from("text-embedding-3-large"))), ..Default::default() };
+        let result = update_project(project_manager.clone(), web::Data::new(pool.clone()), web::Path::from(1), web::Json(update)).await;
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let update = ProjectUpdate { embedding_dimensions: Some(Some(1024)), ..Default::default() };
+        let result = update_project(project_manager, web::Data::new(pool.clone()), web::Path::from(1), web::Json(update)).await;
+        assert_eq!(result.status(), StatusCode::CONFLICT);
+
+        let dimensions: i64 = sqlx::query_scalar("SELECT embedding_dimensions FROM projects WHERE id = 1")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(dimensions, 256);
+    }
+
+    #[actix_rt::test]
+    async fn test_update_clears_nullable_settings() {
+        let pool = setup_db().await;
+
+        sqlx::query("INSERT INTO projects (name, description, embedding_model, monthly_token_quota) VALUES ('test_project', 'test_description', 'text-embedding-3-small', 1000)")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        let project_manager = project_manager(&pool);
+
+        // A missing field is left alone, an explicit null clears the setting
+        let update: ProjectUpdate = serde_json::from_str(r#"{"monthly_token_quota": null}"#).unwrap();
+        let result = update_project(project_manager.clone(), web::Data::new(pool.clone()), web::Path::from(1), web::Json(update)).await;
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let (model, quota): (Option<String>, Option<i64>) = sqlx::query_as("SELECT embedding_model, monthly_token_quota FROM projects WHERE id = 1")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(model, Some(String::from("text-embedding-3-small")));
+        assert_eq!(quota, None);
+
+        // openai_compatible can't lose its base URL
+        let update: ProjectUpdate = serde_json::from_str(r#"{"embedding_provider": "openai_compatible", "embedding_base_url": null}"#).unwrap();
+        let result = update_project(project_manager, web::Data::new(pool.clone()), web::Path::from(1), web::Json(update)).await;
+        assert_eq!(result.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_delete_project() {
+        let pool = setup_db().await;
+
+        sqlx::query("INSERT INTO projects (name, description) VALUES ('test_project', 'test_description')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        sqlx::query("INSERT INTO users (username, hashed_password) VALUES ('test_user', 'hash')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert user.");
+        sqlx::query("INSERT INTO user_project (user_id, project_id, permission_type) VALUES (1, 1, 'owner')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert permission.");
+        sqlx::query("INSERT INTO file_entry (name, path, project_id) VALUES ('a.txt', './project_data/1/a.txt', 1)")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert file.");
+        sqlx::query("INSERT INTO file_embedding (file_id, start_byte, end_byte, embedding) VALUES (1, 0, 3, X'5B315D')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert embedding.");
+
+        let project_manager = project_manager(&pool);
+        project_manager.lock().unwrap().add_blank_project(1, String::from("test_project"));
+
+        let result = delete_project(project_manager.clone(), web::Data::new(pool.clone()), web::Path::from(1)).await;
+        assert_eq!(result.status(), StatusCode::OK);
+
+        for table in ["projects", "user_project", "file_entry", "file_embedding"] {
+            let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table)).fetch_one(&pool).await.unwrap();
+            assert_eq!(count, 0, "{} was not cleaned up", table);
+        }
+        assert_eq!(project_manager.lock().unwrap().get_load_status(1), None);
+    }
+
+    /// A multipart form with its parts in the given order: the rest of the Content-Disposition, the part's
+    /// content type and its value.
+    fn multipart(parts: &[(&str, Option<&str>, &str)]) -> Multipart {
+        let mut body = String::new();
+        for (disposition, content_type, value) in parts {
+            body.push_str(&format!("--BOUNDARY\r\nContent-Disposition: form-data; {}\r\n", disposition));
+            if let Some(content_type) = content_type {
+                body.push_str(&format!("Content-Type: {}\r\n", content_type));
+            }
+            body.push_str(&format!("\r\n{}\r\n", value));
+        }
+        body.push_str("--BOUNDARY--\r\n");
+        let mut headers = http::header::HeaderMap::new();
+        headers.insert(http::header::CONTENT_TYPE, http::header::HeaderValue::from_static("multipart/form-data; boundary=BOUNDARY"));
+        Multipart::new(&headers, stream::iter(vec![Ok::<_, actix_web::error::PayloadError>(web::Bytes::from(body))]))
+    }
+
+    #[actix_rt::test]
+    async fn test_upload_fields_in_any_order() {
+        let pool = setup_db().await;
+        // A high id keeps the test's files apart from real project data
+        let project_id: i64 = 990_049;
+        sqlx::query("INSERT INTO projects (id, name, description) VALUES (?, 'uploads', 'test')")
+            .bind(project_id)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        let project_manager = project_manager(&pool);
+        project_manager.lock().unwrap().add_blank_project(project_id, String::from("uploads"));
+        let job_queue = web::Data::new(JobQueue::new(pool.clone(), project_manager.get_ref().clone()));
+        let send = |parts: &[(&str, Option<&str>, &str)]| {
+            upload(project_manager.clone(), web::Data::new(pool.clone()), job_queue.clone(), web::Path::from(project_id), multipart(parts), None)
+        };
+
+        // The name may follow the payload
+        let result = send(&[(r#"name="payload"; filename="draft.txt""#, Some("text/plain"), "first notes"), (r#"name="upload_name""#, None, "notes.txt")]).await;
+        assert_eq!(result.status(), StatusCode::OK);
+        // Without an upload_name the payload's file name is used
+        let result = send(&[(r#"name="payload"; filename="todo.md""#, Some("text/markdown"), "# Todo")]).await;
+        assert_eq!(result.status(), StatusCode::OK);
+
+        let result = send(&[(r#"name="upload_name""#, None, "photo.png"), (r#"name="payload"; filename="photo.png""#, Some("image/png"), "not really a png")]).await;
+        assert_eq!(result.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let result = send(&[(r#"name="payload""#, None, "nameless")]).await;
+        assert_eq!(result.status(), StatusCode::BAD_REQUEST);
+        let result = send(&[(r#"name="upload_name""#, None, "empty.txt")]).await;
+        assert_eq!(result.status(), StatusCode::BAD_REQUEST);
+
+        let names: Vec<String> = sqlx::query_scalar("SELECT name FROM file_entry WHERE project_id = ? ORDER BY id")
+            .bind(project_id)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(names, vec!["notes.txt", "todo.md"]);
+        // Rejected payloads leave no temporary files behind
+        let leftover = fs::read_dir(file_store::project_dir(project_id).join(".tmp")).unwrap().count();
+        assert_eq!(leftover, 0);
+
+        fs::remove_dir_all(file_store::project_dir(project_id)).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_upload_content_types() {
+        let limits = UploadLimits { max_file_bytes: 1024, max_project_bytes: None, allowed_types: vec![String::from("text/*"), String::from("application/pdf")] };
+        assert!(limits.allows_type("text/markdown; charset=utf-8"));
+        assert!(limits.allows_type("Application/PDF"));
+        assert!(!limits.allows_type("application/zip"));
+        assert!(!limits.allows_type("textual/plain"));
+    }
+}
@@ -0,0 +1,90 @@
+#[cfg(test)]
+mod tests {
+    use sqlx::SqlitePool;
+    use std::sync::{Arc, Mutex};
+    use crate::memory_management::project_manager::{ProjectManager, LoadStatus};
+    use crate::memory_management::project_store::Embedding;
+    use tokio::fs::read_to_string;
+
+    async fn setup_db() -> SqlitePool {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+
+        // Read SQL commands from file
+        let sql_commands = read_to_string("init.sql").await.expect("Could not read SQL file");
+
+        // Execute SQL commands
+        sqlx::query(&sql_commands).execute(&pool).await.expect("Could not execute SQL commands");
+
+        pool
+    }
+
+    #[actix_rt::test]
+    async fn test_load_projects_without_files() {
+        let pool = setup_db().await;
+
+        sqlx::query("INSERT INTO projects (name, description) VALUES ('empty_project', 'no files')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+
+        let project_manager = Arc::new(Mutex::new(ProjectManager::new(pool.clone())));
+        assert!(!project_manager.lock().unwrap().is_ready());
+
+        ProjectManager::load_projects(project_manager.clone()).await;
+
+        let project_manager = project_manager.lock().unwrap();
+        assert!(project_manager.is_ready());
+        assert_eq!(project_manager.get_load_status(1), Some(LoadStatus::Ready));
+    }
+
+    #[actix_rt::test]
+    async fn test_load_projects_reports_failure() {
+        let pool = setup_db().await;
+
+        sqlx::query("INSERT INTO projects (name, description) VALUES ('broken_project', 'bad embedding')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert project.");
+        sqlx::query("INSERT INTO file_entry (name, path, project_id) VALUES ('a.txt', './project_data/1/a.txt', 1)")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert file.");
+        sqlx::query("INSERT INTO file_embedding (file_id, start_byte, end_byte, embedding) VALUES (1, 0, 10, X'6E6F74206A736F6E')")
+            .execute(&pool)
+            .await
+            .expect("Failed to insert embedding.");
+
+        let project_manager = Arc::new(Mutex::new(ProjectManager::new(pool.clone())));
+        ProjectManager::load_projects(project_manager.clone()).await;
+
+        let project_manager = project_manager.lock().unwrap();
+        assert!(project_manager.is_ready());
+        assert!(matches!(project_manager.get_load_status(1), Some(LoadStatus::Failed(_))));
+    }
+
+    fn embedding(file_id: i64, start_byte: i64, vector: Vec<f64>) -> Embedding {
+        Embedding { embedding: vector, start_byte, end_byte: start_byte + 1, file_id, version: 1 }
+    }
+
+    #[actix_rt::test]
+    async fn test_replace_file_embeddings() {
+        let pool = setup_db().await;
+        let project_manager = Arc::new(Mutex::new(ProjectManager::new(pool.clone())));
+        project_manager.lock().unwrap().add_blank_project(1, String::from("test_project"));
+
+        let first = vec![embedding(1, 0, vec![1.0, 0.0]), embedding(1, 1, vec![0.0, 1.0])];
+        ProjectManager::replace_file_embeddings(&project_manager, 1, 1, first).await;
+        ProjectManager::replace_file_embeddings(&project_manager, 1, 2, vec![embedding(2, 0, vec![1.0, 1.0])]).await;
+        // Replacing a file only touches that file's embeddings
+        ProjectManager::replace_file_embeddings(&project_manager, 1, 1, vec![embedding(1, 5, vec![-1.0, 0.0])]).await;
+
+        let mut project_manager = project_manager.lock().unwrap();
+        let snapshot = project_manager.snapshot();
+        assert_eq!(snapshot[&1].embedding_counts[&1], 1);
+        assert_eq!(snapshot[&1].embedding_counts[&2], 1);
+
+        // The swapped-in index finds the new embeddings
+        let nearest = project_manager.get_most_similiar_embedding(1, &embedding(0, 0, vec![-1.0, 0.1])).unwrap();
+        assert_eq!((nearest.file_id, nearest.start_byte), (1, 5));
+    }
+}
@@ -0,0 +1,263 @@
+#[cfg(test)]
+mod tests {
+    use crate::providers::{build_provider, pack_batches, parse_retry_after, EmbeddingProvider, ProviderConfig, ProviderError};
+    use crate::providers::fake::{FakeProvider, DEFAULT_DIMENSIONS};
+    use crate::providers::resilience::{backoff, ResilienceConfig, ResilientProvider, Shared};
+    use crate::providers::cache::CachedProvider;
+    use crate::providers::scheduler::EmbeddingScheduler;
+    use crate::utils::hash::sha256_hex;
+    use sqlx::SqlitePool;
+    use tokio::fs::read_to_string;
+    use async_trait::async_trait;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Fails the first `failures` calls with the error from `error`, then answers like the fake provider.
+    struct FlakyProvider {
+        failures: usize,
+        calls: Arc<AtomicUsize>,
+        error: fn() -> ProviderError,
+    }
+
+    #[async_trait]
+    impl EmbeddingProvider for FlakyProvider {
+        fn name(&self) -> &'static str { "flaky" }
+        fn model(&self) -> &str { "flaky" }
+        fn dimensions(&self) -> Option<usize> { None }
+        fn max_batch_inputs(&self) -> usize { 16 }
+        fn max_batch_tokens(&self) -> usize { 1000 }
+
+        async fn embed_batch(&self, inputs: &[String]) -> Result<Vec<Vec<f64>>, ProviderError> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err((self.error)());
+            }
+            FakeProvider::default().embed_batch(inputs).await
+        }
+    }
+
+    async fn embed(provider: &dyn EmbeddingProvider, input: &str) -> Result<Vec<f64>, ProviderError> {
+        let mut embeddings = provider.embed_batch(&[input.to_string()]).await?;
+        assert_eq!(embeddings.len(), 1);
+        Ok(embeddings.pop().unwrap())
+    }
+
+    fn resilient(failures: usize, error: fn() -> ProviderError, config: ResilienceConfig) -> (ResilientProvider, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let flaky = FlakyProvider { failures, calls: calls.clone(), error };
+        let shared = Arc::new(Shared::new(&config));
+        (ResilientProvider::new(Box::new(flaky), shared, config), calls)
+    }
+
+    fn quick_retries(max_retries: u32) -> ResilienceConfig {
+        ResilienceConfig {
+            max_retries,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(20),
+            ..Default::default()
+        }
+    }
+
+    async fn setup_db() -> SqlitePool {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+
+        // Read SQL commands from file
+        let sql_commands = read_to_string("init.sql").await.expect("Could not read SQL file");
+
+        // Execute SQL commands
+        sqlx::query(&sql_commands).execute(&pool).await.expect("Could not execute SQL commands");
+
+        pool
+    }
+
+    fn cached(pool: &SqlitePool, model: &str, max_entries: i64) -> (CachedProvider, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let flaky = FlakyProvider { failures: 0, calls: calls.clone(), error: || ProviderError::InvalidResponse(String::new()) };
+        (CachedProvider::new(Box::new(flaky), pool.clone(), String::from(model), max_entries), calls)
+    }
+
+    fn config(provider: &str, base_url: Option<&str>) -> ProviderConfig {
+        ProviderConfig {
+            embedding_provider: String::from(provider),
+            embedding_base_url: base_url.map(String::from),
+            embedding_model: None,
+            embedding_dimensions: None,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_fake_provider_is_deterministic() {
+        let provider = FakeProvider::default();
+        let first = embed(&provider, "the same text").await.unwrap();
+        let second = embed(&provider, "the same text").await.unwrap();
+        let other = embed(&provider, "different text").await.unwrap();
+
+        assert_eq!(first.len(), DEFAULT_DIMENSIONS);
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        let norm: f64 = first.iter().map(|x| x * x).sum::<f64>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_build_provider() {
+        assert_eq!(build_provider(&config("fake", None)).unwrap().name(), "fake");
+        assert_eq!(build_provider(&config("ollama", None)).unwrap().name(), "ollama");
+        assert_eq!(build_provider(&config("openai_compatible", Some("http://localhost:8080/v1"))).unwrap().name(), "openai_compatible");
+        assert!(build_provider(&config("openai_compatible", None)).is_err());
+        assert!(build_provider(&config("word2vec", None)).is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_configured_dimensions() {
+        let mut fake = config("fake", None);
+        fake.embedding_dimensions = Some(64);
+        let provider = build_provider(&fake).unwrap();
+        assert_eq!(provider.dimensions(), Some(64));
+        assert_eq!(embed(&provider, "text").await.unwrap().len(), 64);
+
+        let mut local = config("ollama", None);
+        local.embedding_model = Some(String::from("mxbai-embed-large"));
+        assert_eq!(build_provider(&local).unwrap().model(), "mxbai-embed-large");
+
+        fake.embedding_dimensions = Some(0);
+        assert!(build_provider(&fake).is_err());
+    }
+
+    #[test]
+    fn test_pack_batches() {
+        // Input limit
+        assert_eq!(pack_batches(&[10, 10, 10, 10, 10], 2, 1000), vec![0..2, 2..4, 4..5]);
+        // Token limit
+        assert_eq!(pack_batches(&[10, 10, 10, 10, 10], 100, 25), vec![0..2, 2..4, 4..5]);
+        // An oversized input goes alone
+        assert_eq!(pack_batches(&[50, 10, 10], 100, 25), vec![0..1, 1..3]);
+        assert!(pack_batches(&[], 100, 25).is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_fake_batch_matches_single() {
+        let provider = FakeProvider::default();
+        let inputs = vec![String::from("first"), String::from("second")];
+        let batch = provider.embed_batch(&inputs).await.unwrap();
+        assert_eq!(batch[0], embed(&provider, "first").await.unwrap());
+        assert_eq!(batch[1], embed(&provider, "second").await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_retries_rate_limits_until_success() {
+        let rate_limited = || ProviderError::RateLimited { retry_after: Some(Duration::from_millis(2)), message: String::from("slow down") };
+        let (provider, calls) = resilient(2, rate_limited, quick_retries(3));
+        assert!(embed(&provider, "text").await.is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Out of retries
+        let (provider, calls) = resilient(5, rate_limited, quick_retries(1));
+        assert!(matches!(embed(&provider, "text").await, Err(ProviderError::RateLimited { .. })));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[actix_rt::test]
+    async fn test_rejected_requests_are_not_retried() {
+        let rejected = || ProviderError::Rejected { status: 400, message: String::from("input too long") };
+        let (provider, calls) = resilient(1, rejected, quick_retries(3));
+        assert!(matches!(embed(&provider, "text").await, Err(ProviderError::Rejected { status: 400, .. })));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // A Retry-After beyond max_delay is not waited for
+        let too_long = || ProviderError::Unavailable { status: 503, retry_after: Some(Duration::from_secs(3600)), message: String::new() };
+        let (provider, calls) = resilient(1, too_long, quick_retries(3));
+        assert!(embed(&provider, "text").await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_backoff_gives_the_scheduler_slot_back() {
+        let rate_limited = || ProviderError::RateLimited { retry_after: Some(Duration::from_millis(200)), message: String::from("slow down") };
+        let config = ResilienceConfig { max_delay: Duration::from_secs(1), ..quick_retries(1) };
+        let scheduler = EmbeddingScheduler::new(1);
+        let (provider, calls) = resilient(1, rate_limited, config);
+        let provider = provider.scheduled(scheduler.clone(), 1);
+
+        // Another project gets the only slot while the first request waits to be retried
+        let other = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let permit = tokio::time::timeout(Duration::from_millis(50), scheduler.acquire(2)).await;
+            assert!(permit.is_ok());
+        };
+        let inputs = vec![String::from("text")];
+        let (result, ()) = futures::join!(provider.embed_batch(&inputs), other);
+        assert!(result.is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(scheduler.running(), 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_circuit_opens_after_repeated_failures() {
+        let unavailable = || ProviderError::Unavailable { status: 502, retry_after: None, message: String::from("bad gateway") };
+        let config = ResilienceConfig { failure_threshold: 2, cooldown: Duration::from_secs(60), ..quick_retries(0) };
+        let (provider, calls) = resilient(usize::MAX, unavailable, config);
+
+        assert!(matches!(embed(&provider, "text").await, Err(ProviderError::Unavailable { .. })));
+        assert!(matches!(embed(&provider, "text").await, Err(ProviderError::Unavailable { .. })));
+        assert!(matches!(embed(&provider, "text").await, Err(ProviderError::CircuitOpen { .. })));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_backoff_and_retry_after() {
+        let delay = backoff(3, Duration::from_millis(100), Duration::from_secs(10), None);
+        assert!(delay >= Duration::from_millis(400) && delay <= Duration::from_millis(800));
+        assert!(backoff(30, Duration::from_millis(100), Duration::from_secs(10), None) <= Duration::from_secs(10));
+        assert_eq!(backoff(0, Duration::from_millis(100), Duration::from_secs(10), Some(Duration::from_secs(5))), Duration::from_secs(5));
+
+        assert_eq!(parse_retry_after("7"), Some(Duration::from_secs(7)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[actix_rt::test]
+    async fn test_cache_serves_repeated_text() {
+        let pool = setup_db().await;
+        let (provider, calls) = cached(&pool, "flaky a", 100);
+        let inputs = vec![String::from("MIT License"), String::from("Copyright (c)")];
+
+        let first = provider.embed_batch(&inputs).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let second = provider.embed_batch(&inputs).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(first, second);
+
+        // Only the new input goes to the provider, and the results keep input order
+        let mixed = vec![String::from("new text"), String::from("MIT License")];
+        let embeddings = provider.embed_batch(&mixed).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(embeddings, FakeProvider::default().embed_batch(&mixed).await.unwrap());
+
+        // Another model has a cache of its own
+        let (other, other_calls) = cached(&pool, "flaky b", 100);
+        other.embed_batch(&inputs).await.unwrap();
+        assert_eq!(other_calls.load(Ordering::SeqCst), 1);
+
+        let hits: i64 = sqlx::query_scalar("SELECT SUM(hits) FROM embedding_cache WHERE model = 'flaky a'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(hits, 3);
+    }
+
+    #[actix_rt::test]
+    async fn test_cache_evicts_least_recently_used() {
+        let pool = setup_db().await;
+        let (provider, _) = cached(&pool, "flaky", 2);
+        for text in ["one", "two", "three"] {
+            provider.embed_batch(&[String::from(text)]).await.unwrap();
+        }
+
+        let kept: Vec<String> = sqlx::query_scalar("SELECT text_hash FROM embedding_cache ORDER BY rowid")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(kept, vec![sha256_hex(b"two"), sha256_hex(b"three")]);
+    }
+}
@@ -0,0 +1,47 @@
+#[cfg(test)]
+mod tests {
+    use crate::providers::scheduler::EmbeddingScheduler;
+    use futures::future::join_all;
+    use std::sync::{Arc, Mutex};
+
+    #[actix_rt::test]
+    async fn test_slots_alternate_between_projects() {
+        let scheduler = EmbeddingScheduler::new(1);
+        let first = scheduler.acquire(1).await;
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        // Project 1 queues three requests before project 2 queues one
+        let waiters = [1, 1, 1, 2].into_iter().map(|project_id| {
+            let scheduler = scheduler.clone();
+            let order = order.clone();
+            async move {
+                let _permit = scheduler.acquire(project_id).await;
+                order.lock().unwrap().push(project_id);
+                tokio::task::yield_now().await;
+            }
+        });
+        let release = async {
+            tokio::task::yield_now().await;
+            drop(first);
+        };
+        futures::join!(join_all(waiters), release);
+
+        assert_eq!(*order.lock().unwrap(), vec![1, 2, 1, 1]);
+        assert_eq!(scheduler.running(), 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_cancelled_waiter_gives_up_its_turn() {
+        let scheduler = EmbeddingScheduler::new(1);
+        let first = scheduler.acquire(1).await;
+        {
+            let mut cancelled = Box::pin(scheduler.acquire(2));
+            assert!(futures::poll!(&mut cancelled).is_pending());
+        }
+        drop(first);
+        assert_eq!(scheduler.running(), 0);
+
+        let _permit = scheduler.acquire(3).await;
+        assert_eq!(scheduler.running(), 1);
+    }
+}
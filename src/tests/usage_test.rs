@@ -0,0 +1,131 @@
+#[cfg(test)]
+mod tests {
+    use actix_web::{web, FromRequest, HttpMessage};
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+    use sqlx::SqlitePool;
+    use crate::handlers::embedding_handler::{embed_file, run_embeddings_and_store, EmbedError, EmbedOptions};
+    use crate::memory_management::project_manager::ProjectManager;
+    use crate::providers::usage::{self, UsageFilter, UsageKind};
+    use std::sync::{Arc, Mutex};
+    use tokio::fs::read_to_string;
+
+    async fn setup_db() -> SqlitePool {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+
+        // Read SQL commands from file
+        let sql_commands = read_to_string("init.sql").await.expect("Could not read SQL file");
+
+        // Execute SQL commands
+        sqlx::query(&sql_commands).execute(&pool).await.expect("Could not execute SQL commands");
+
+        pool
+    }
+
+    async fn setup_project(pool: &SqlitePool, path: &str, quota: Option<i64>) -> Arc<Mutex<ProjectManager>> {
+        sqlx::query("INSERT INTO projects (name, description, embedding_provider, monthly_token_quota) VALUES ('test_project', 'test_description', 'fake', ?)")
+            .bind(quota)
+            .execute(pool)
+            .await
+            .expect("Failed to insert project.");
+        sqlx::query("INSERT INTO file_entry (name, path, project_id) VALUES ('sample.txt', ?, 1)")
+            .bind(path)
+            .execute(pool)
+            .await
+            .expect("Failed to insert file.");
+
+        let project_manager = Arc::new(Mutex::new(ProjectManager::new(pool.clone())));
+        project_manager.lock().unwrap().add_blank_project(1, String::from("test_project"));
+        project_manager
+    }
+
+    #[actix_rt::test]
+    async fn test_embedding_records_usage() {
+        let pool = setup_db().await;
+        let contents: String = (0..2000).map(|i| format!("line {} of the metered file\n", i)).collect();
+        let path = std::env::temp_dir().join("usage_test.txt");
+        std::fs::write(&path, &contents).unwrap();
+        let project_manager = setup_project(&pool, path.to_str().unwrap(), None).await;
+
+        run_embeddings_and_store(&project_manager, &pool, 1, false, Some(7), None).await.unwrap().unwrap();
+
+        let report = usage::report(&pool, &UsageFilter::default()).await.unwrap();
+        assert_eq!(report.rows.len(), 1);
+        assert_eq!(report.rows[0].project_id, 1);
+        assert_eq!(report.rows[0].model, "fake");
+        assert!(report.tokens > 0);
+        assert_eq!(report.estimated_cost_usd, 0.0);
+
+        let by_user = usage::report(&pool, &UsageFilter { user_id: Some(7), ..Default::default() }).await.unwrap();
+        assert_eq!(by_user.tokens, report.tokens);
+        let other_user = usage::report(&pool, &UsageFilter { user_id: Some(8), ..Default::default() }).await.unwrap();
+        assert!(other_user.rows.is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_exceeded_quota_rejects_embedding() {
+        let pool = setup_db().await;
+        let path = std::env::temp_dir().join("usage_quota_test.txt");
+        std::fs::write(&path, "some text to embed").unwrap();
+        let project_manager = setup_project(&pool, path.to_str().unwrap(), Some(100)).await;
+
+        usage::record(&pool, 1, None, "fake", "fake", UsageKind::Search, 100).await.unwrap();
+
+        let result = run_embeddings_and_store(&project_manager, &pool, 1, false, None, None).await;
+        match result {
+            Err(EmbedError::QuotaExceeded(quota)) => {
+                assert_eq!(quota.scope, "project");
+                assert_eq!(quota.limit, 100);
+                assert_eq!(quota.used, 100);
+            },
+            other => panic!("expected an exceeded quota, got {:?}", other.map(|_| ())),
+        }
+
+        let response = embed_file(web::Data::new(project_manager), web::Data::new(pool.clone()), web::Path::from(1), web::Query(EmbedOptions::default()), None).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_embedding_as_user() {
+        let pool = setup_db().await;
+        let path = std::env::temp_dir().join("usage_user_test.txt");
+        std::fs::write(&path, "some text to embed for a user").unwrap();
+        let project_manager = web::Data::new(setup_project(&pool, path.to_str().unwrap(), None).await);
+        let db_pool = web::Data::new(pool.clone());
+        // Far above what the other tests use, as the variable is seen by the whole process
+        std::env::set_var("USER_MONTHLY_TOKEN_QUOTA", "1000000000");
+
+        // The id the authentication middleware leaves in the request's extensions
+        let req = TestRequest::default().to_http_request();
+        req.extensions_mut().insert(9_i64);
+        let user = web::ReqData::<i64>::extract(&req).await.unwrap();
+        let response = embed_file(project_manager.clone(), db_pool.clone(), web::Path::from(1), web::Query(EmbedOptions::default()), Some(user)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let users: Vec<Option<i64>> = sqlx::query_scalar("SELECT user_id FROM token_usage")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(users, vec![Some(9)]);
+
+        usage::record(&pool, 1, Some(9), "fake", "fake", UsageKind::Search, 1_000_000_000).await.unwrap();
+        let user = web::ReqData::<i64>::extract(&req).await.unwrap();
+        let response = embed_file(project_manager, db_pool, web::Path::from(1), web::Query(EmbedOptions { force: true }), Some(user)).await;
+        std::env::remove_var("USER_MONTHLY_TOKEN_QUOTA");
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(String::from_utf8(body.to_vec()).unwrap().contains("for user 9"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_prices() {
+        assert_eq!(usage::price_per_million_tokens("openai", "text-embedding-3-small"), Some(0.02));
+        assert_eq!(usage::price_per_million_tokens("ollama", "nomic-embed-text"), Some(0.0));
+        assert_eq!(usage::price_per_million_tokens("openai_compatible", "my-model"), None);
+    }
+}
@@ -11,7 +11,8 @@ mod tests {
     use crate::models::user_response::DatabaseUser;
     use crate::models::credentials::Credentials;
     use crate::models::reset_password_credentials::ResetPasswordCredentials;
-    use crate::handlers::embeddings::get_embedding;
+    use crate::providers::EmbeddingProvider;
+    use crate::providers::openai::OpenAiProvider;
 
     use tokio::fs::read_to_string; // Imported read_to_string
 
@@ -142,6 +143,10 @@ mod tests {
     #[actix_rt::test]
     async fn test_rnd() {
         dotenv().ok();
-        get_embedding(String::from("this is a test")).await;
+        let provider = OpenAiProvider::from_env().unwrap();
+        let embeddings = provider.embed_batch(&[String::from("this is a test")]).await.unwrap();
+        assert_eq!(embeddings.len(), 1);
+        // text-embedding-ada-002 always returns 1536 dimensions
+        assert_eq!(embeddings[0].len(), 1536);
     }
 }
@@ -0,0 +1,262 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use crate::utils::file_store;
+use crate::utils::file_versions::PendingFile;
+
+pub const DEFAULT_MAX_FILES: usize = 10_000;
+pub const DEFAULT_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Expanded archives may be at most this many times larger than the upload, once past `RATIO_FREE_BYTES`.
+pub const MAX_COMPRESSION_RATIO: u64 = 100;
+const RATIO_FREE_BYTES: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub fn detect(name: &str) -> Option<ArchiveFormat> {
+        let name = name.to_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// How much an archive may expand to, from `ARCHIVE_MAX_FILES` and `ARCHIVE_MAX_BYTES`.
+#[derive(Debug, Clone, Copy)]
+pub struct ArchiveLimits {
+    pub max_files: usize,
+    pub max_bytes: u64,
+    pub max_ratio: u64,
+}
+
+impl ArchiveLimits {
+    pub fn from_env() -> ArchiveLimits {
+        ArchiveLimits {
+            max_files: std::env::var("ARCHIVE_MAX_FILES").ok().and_then(|value| value.parse().ok()).unwrap_or(DEFAULT_MAX_FILES),
+            max_bytes: std::env::var("ARCHIVE_MAX_BYTES").ok().and_then(|value| value.parse().ok()).unwrap_or(DEFAULT_MAX_BYTES),
+            max_ratio: MAX_COMPRESSION_RATIO,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Unreadable(String),
+    TooLarge(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArchiveError::Unreadable(e) => write!(f, "Could not read archive: {}", e),
+            ArchiveError::TooLarge(e) => write!(f, "Archive too large: {}", e),
+            ArchiveError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for ArchiveError {
+    fn from(e: std::io::Error) -> ArchiveError {
+        ArchiveError::Io(e)
+    }
+}
+
+/// An entry that was left out, and why.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Skipped {
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Expanded {
+    /// Named by their relative path inside the archive, with `/` separators.
+    pub members: Vec<PendingFile>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// Removes the temporary files of members that won't be stored.
+pub fn discard(members: impl IntoIterator<Item = PendingFile>) {
+    for member in members {
+        let _ = std::fs::remove_file(&member.temp_path);
+    }
+}
+
+/// The relative path of an entry, or `None` when it would land outside the directory it is expanded into:
+/// absolute paths, drive letters and `..` components.
+pub fn member_path(raw: &str) -> Option<String> {
+    let raw = raw.replace('\\', "/");
+    if raw.starts_with('/') {
+        return None;
+    }
+    let mut components = Vec::new();
+    for component in raw.split('/') {
+        match component {
+            "" | "." => continue,
+            ".." => return None,
+            component if component.contains(':') => return None,
+            component => components.push(component),
+        }
+    }
+    if components.is_empty() {
+        None
+    } else {
+        Some(components.join("/"))
+    }
+}
+
+/// Files archivers add on the side, which aren't part of the content.
+fn is_metadata(name: &str) -> bool {
+    name.starts_with("__MACOSX/") || name == ".DS_Store" || name.ends_with("/.DS_Store")
+}
+
+/// Writes out every regular file of the archive at `path` to a temporary file of the project. Directories,
+/// links and entries with unsafe paths are skipped. Expansion stops with `TooLarge`, and nothing is kept,
+/// once the archive holds more files or bytes than `limits` allow; sizes are counted while the data is read,
+/// not taken from the archive's headers.
+pub fn expand(format: ArchiveFormat, path: &Path, project_id: i64, limits: &ArchiveLimits) -> Result<Expanded, ArchiveError> {
+    let archive_size = std::fs::metadata(path)?.len();
+    let mut expander = Expander { project_id, limits, archive_size, total: 0, expanded: Expanded::default() };
+    let result = match format {
+        ArchiveFormat::Zip => expand_zip(path, &mut expander),
+        ArchiveFormat::TarGz => expand_tar_gz(path, &mut expander),
+    };
+    match result {
+        Ok(()) => Ok(expander.expanded),
+        Err(e) => {
+            discard(expander.expanded.members);
+            Err(e)
+        }
+    }
+}
+
+fn expand_zip(path: &Path, expander: &mut Expander) -> Result<(), ArchiveError> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(|e| ArchiveError::Unreadable(e.to_string()))?;
+    for i in 0..archive.len() {
+        let entry = match archive.by_index(i) {
+            Ok(entry) => entry,
+            Err(zip::result::ZipError::UnsupportedArchive(e)) => {
+                expander.skip(format!("entry {}", i), e);
+                continue;
+            },
+            Err(e) => return Err(ArchiveError::Unreadable(e.to_string())),
+        };
+        let name = entry.name().to_string();
+        // Symbolic links are stored as files holding the target, marked by their Unix mode
+        let is_link = entry.unix_mode().is_some_and(|mode| mode & 0o170000 == 0o120000);
+        if entry.is_dir() {
+            continue;
+        }
+        if is_link {
+            expander.skip(name, "not a regular file");
+            continue;
+        }
+        expander.add(&name, entry)?;
+    }
+    Ok(())
+}
+
+fn expand_tar_gz(path: &Path, expander: &mut Expander) -> Result<(), ArchiveError> {
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(File::open(path)?));
+    let entries = archive.entries().map_err(|e| ArchiveError::Unreadable(e.to_string()))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| ArchiveError::Unreadable(e.to_string()))?;
+        let name = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+        match entry.header().entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Continuous => expander.add(&name, entry)?,
+            tar::EntryType::Directory => {},
+            // Extended headers only describe the entry after them
+            tar::EntryType::XHeader | tar::EntryType::XGlobalHeader | tar::EntryType::GNULongName | tar::EntryType::GNULongLink => {},
+            _ => expander.skip(name, "not a regular file"),
+        }
+    }
+    Ok(())
+}
+
+struct Expander<'a> {
+    project_id: i64,
+    limits: &'a ArchiveLimits,
+    archive_size: u64,
+    total: u64,
+    expanded: Expanded,
+}
+
+impl Expander<'_> {
+    fn skip(&mut self, name: String, reason: impl fmt::Display) {
+        self.expanded.skipped.push(Skipped { name, reason: reason.to_string() });
+    }
+
+    fn add(&mut self, raw_name: &str, mut reader: impl Read) -> Result<(), ArchiveError> {
+        let name = match member_path(raw_name) {
+            Some(name) => name,
+            None => {
+                self.skip(raw_name.to_string(), "unsafe path");
+                return Ok(());
+            }
+        };
+        if is_metadata(&name) {
+            return Ok(());
+        }
+        if self.expanded.members.len() >= self.limits.max_files {
+            return Err(ArchiveError::TooLarge(format!("more than {} files", self.limits.max_files)));
+        }
+
+        let temp_path = file_store::temp_path(self.project_id);
+        std::fs::create_dir_all(temp_path.parent().unwrap())?;
+        let mut file = File::create(&temp_path)?;
+        let mut hasher = Sha256::new();
+        let mut size: u64 = 0;
+        let mut buffer = vec![0; 64 * 1024];
+        let result = loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break Ok(()),
+                Ok(read) => read,
+                Err(e) => break Err(ArchiveError::Unreadable(format!("{}: {}", name, e))),
+            };
+            size += read as u64;
+            self.total += read as u64;
+            if let Err(e) = self.check_total() {
+                break Err(e);
+            }
+            hasher.update(&buffer[..read]);
+            if let Err(e) = file.write_all(&buffer[..read]) {
+                break Err(ArchiveError::Io(e));
+            }
+        };
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(e);
+        }
+
+        self.expanded.members.push(PendingFile {
+            name,
+            temp_path,
+            content_hash: format!("{:x}", hasher.finalize()),
+            size: size as i64,
+        });
+        Ok(())
+    }
+
+    fn check_total(&self) -> Result<(), ArchiveError> {
+        if self.total > self.limits.max_bytes {
+            return Err(ArchiveError::TooLarge(format!("expands to more than {} bytes", self.limits.max_bytes)));
+        }
+        if self.total > RATIO_FREE_BYTES && self.total / self.archive_size.max(1) > self.limits.max_ratio {
+            return Err(ArchiveError::TooLarge(format!("expands to more than {} times its size", self.limits.max_ratio)));
+        }
+        Ok(())
+    }
+}
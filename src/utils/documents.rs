@@ -0,0 +1,173 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sqlx::SqlitePool;
+use std::sync::{Arc, Mutex};
+use crate::chunking::strategy::ChunkingStrategy;
+use crate::memory_management::project_manager::ProjectManager;
+use crate::parsing::DocumentFormat;
+use crate::parsing::rows::Rows;
+use crate::utils::file_store;
+use crate::utils::file_versions::{self, DocumentFields, PendingFile, StoreStatus, StoredFile};
+use crate::utils::hash::sha256_hex;
+
+/// Text stored in a project without a file upload: a row of an import or a document sent as JSON.
+#[derive(Debug)]
+pub struct TextDocument {
+    pub name: String,
+    /// The caller's id for the document. Storing a document with an id that is already in the project replaces
+    /// that document's text and metadata, whatever its name.
+    pub external_id: Option<String>,
+    pub text: String,
+    /// Replaces the document's metadata; `None` keeps what it had.
+    pub metadata: Option<Map<String, Value>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DocumentError {
+    /// The name belongs to another document of the project.
+    NameTaken(String),
+    Failed(String),
+}
+
+/// Stores the text as the newest version of the document, like an upload of a plain text file, and makes the
+/// project's in-memory store follow it. The text is embedded as it is, never parsed.
+pub async fn upsert(project_manager: &Arc<Mutex<ProjectManager>>, db_pool: &SqlitePool, project_id: i64, document: &TextDocument, chunking: Option<&ChunkingStrategy>) -> Result<StoredFile, DocumentError> {
+    let failed = |e: sqlx::Error| DocumentError::Failed(e.to_string());
+
+    // A document keeps the name it was created with
+    let existing: Option<String> = match &document.external_id {
+        Some(external_id) => sqlx::query_scalar("SELECT name FROM file_entry WHERE project_id = ? AND external_id = ?")
+            .bind(project_id)
+            .bind(external_id)
+            .fetch_optional(db_pool)
+            .await
+            .map_err(failed)?,
+        None => None,
+    };
+    let name = match existing {
+        Some(name) => name,
+        None => {
+            let taken: Option<i64> = sqlx::query_scalar("SELECT id FROM file_entry WHERE project_id = ? AND name = ? AND external_id IS NOT ?")
+                .bind(project_id)
+                .bind(&document.name)
+                .bind(&document.external_id)
+                .fetch_optional(db_pool)
+                .await
+                .map_err(failed)?;
+            if taken.is_some() {
+                return Err(DocumentError::NameTaken(document.name.clone()));
+            }
+            document.name.clone()
+        },
+    };
+
+    let temp_path = file_store::temp_path(project_id);
+    std::fs::create_dir_all(temp_path.parent().unwrap())
+        .and_then(|_| std::fs::write(&temp_path, &document.text))
+        .map_err(|e| DocumentError::Failed(e.to_string()))?;
+    let file = PendingFile { name, temp_path, content_hash: sha256_hex(document.text.as_bytes()), size: document.text.len() as i64 };
+    let fields = DocumentFields {
+        external_id: document.external_id.as_deref(),
+        metadata: document.metadata.as_ref().map(|metadata| Value::Object(metadata.clone()).to_string()),
+    };
+    let stored = file_versions::store_version(db_pool, project_id, &file, chunking, Some(&fields)).await
+        .map_err(DocumentError::Failed)?;
+    if stored.status != StoreStatus::Unchanged {
+        file_versions::save_text(db_pool, project_id, stored.id, stored.version, DocumentFormat::Text, None).await
+            .map_err(DocumentError::Failed)?;
+    }
+
+    ProjectManager::track_version(project_manager, project_id, stored.id, stored.status).await;
+    Ok(stored)
+}
+
+/// Which columns of an import make up each document.
+#[derive(Debug, Clone)]
+pub struct RowSelection {
+    /// Holds the external id, which also names the document.
+    pub id_column: String,
+    /// Joined with blank lines into the embedded text. When empty, every column but the id and metadata ones.
+    pub text_columns: Vec<String>,
+    /// Stored as the document's metadata, which searches can filter on.
+    pub metadata_columns: Vec<String>,
+}
+
+impl RowSelection {
+    /// Checks that the selected columns exist and fills in the default text columns.
+    pub fn resolve(mut self, columns: &[String]) -> Result<RowSelection, String> {
+        let mut selected = std::iter::once(&self.id_column).chain(&self.text_columns).chain(&self.metadata_columns);
+        if let Some(column) = selected.find(|column| !columns.contains(*column)) {
+            return Err(format!("Unknown column {}", column));
+        }
+        if self.text_columns.is_empty() {
+            self.text_columns = columns.iter()
+                .filter(|column| **column != self.id_column && !self.metadata_columns.contains(*column))
+                .cloned()
+                .collect();
+        }
+        Ok(self)
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportedRow {
+    pub line: usize,
+    pub external_id: String,
+    #[serde(flatten)]
+    pub stored: StoredFile,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SkippedRow {
+    pub line: usize,
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct RowImport {
+    pub rows: Vec<ImportedRow>,
+    pub skipped: Vec<SkippedRow>,
+    pub job_ids: Vec<i64>,
+}
+
+/// Stores each row as a document named after its external id, so importing the same rows again only makes new
+/// versions of the rows that changed. Rows without an id or text, and lines that couldn't be read, are skipped.
+pub async fn import_rows(project_manager: &Arc<Mutex<ProjectManager>>, db_pool: &SqlitePool, project_id: i64, rows: Rows, selection: &RowSelection) -> Result<RowImport, DocumentError> {
+    let mut import = RowImport::default();
+    import.skipped.extend(rows.invalid.into_iter().map(|(line, reason)| SkippedRow { line, reason }));
+
+    for row in rows.rows {
+        let external_id = match row.get_text(&selection.id_column).filter(|id| !id.trim().is_empty()) {
+            Some(external_id) => external_id.trim().to_string(),
+            None => {
+                import.skipped.push(SkippedRow { line: row.line, reason: format!("no {}", selection.id_column) });
+                continue;
+            }
+        };
+        let text = selection.text_columns.iter()
+            .filter_map(|column| row.get_text(column))
+            .filter(|value| !value.trim().is_empty())
+            .collect::<Vec<String>>()
+            .join("\n\n");
+        if text.is_empty() {
+            import.skipped.push(SkippedRow { line: row.line, reason: String::from("no text") });
+            continue;
+        }
+        let metadata = if selection.metadata_columns.is_empty() {
+            None
+        } else {
+            Some(selection.metadata_columns.iter()
+                .filter_map(|column| row.values.get(column).map(|value| (column.clone(), value.clone())))
+                .collect())
+        };
+
+        let document = TextDocument { name: external_id.clone(), external_id: Some(external_id), text, metadata };
+        match upsert(project_manager, db_pool, project_id, &document, None).await {
+            Ok(stored) => import.rows.push(ImportedRow { line: row.line, external_id: document.name, stored }),
+            Err(DocumentError::NameTaken(name)) => import.skipped.push(SkippedRow { line: row.line, reason: format!("{} is the name of another file", name) }),
+            Err(e) => return Err(e),
+        }
+    }
+    import.skipped.sort_by_key(|skipped| skipped.line);
+    Ok(import)
+}
@@ -0,0 +1,177 @@
+use sqlx::{SqlitePool};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+
+pub const PROJECT_DATA_DIR: &str = "./project_data";
+
+/// Deletes hold this shared from parking a directory in the trash until the trash is emptied or restored;
+/// an fsck repair holds it exclusively so it never recovers trash that a delete is still working on.
+pub static TRASH_LOCK: RwLock<()> = RwLock::const_new(());
+
+pub fn project_dir(project_id: i64) -> PathBuf {
+    PathBuf::from(format!("{}/{}", PROJECT_DATA_DIR, project_id))
+}
+
+/// Every stored version of a file lives in its own directory under the project.
+pub fn file_dir(project_id: i64, file_id: i64) -> PathBuf {
+    project_dir(project_id).join("files").join(file_id.to_string())
+}
+
+pub fn version_path(project_id: i64, file_id: i64, version: i64, name: &str) -> PathBuf {
+    file_dir(project_id, file_id).join(format!("{}-{}", version, sanitize_filename::sanitize(name)))
+}
+
+/// The text extracted from a version of a document. Version blobs are always named `{version}-{name}`, so the
+/// two can't collide.
+pub fn text_path(project_id: i64, file_id: i64, version: i64) -> PathBuf {
+    file_dir(project_id, file_id).join(format!("{}.txt", version))
+}
+
+/// A fresh path for an upload in progress. Uploads are written here and renamed into place once complete.
+pub fn temp_path(project_id: i64) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    project_dir(project_id).join(".tmp").join(format!("{}-{}", nanos, count))
+}
+
+/// Deleted files are first moved here and only removed once their database rows are gone, so a
+/// crash at any point leaves something `recover_trash` can finish or undo.
+pub fn trash_path(project_id: i64, file_id: i64) -> PathBuf {
+    project_dir(project_id).join(".trash").join(file_id.to_string())
+}
+
+/// Moves all stored versions of a file into the project's trash. Returns false if there was nothing to move.
+pub fn move_to_trash(project_id: i64, file_id: i64) -> std::io::Result<bool> {
+    let dir = file_dir(project_id, file_id);
+    if !dir.exists() {
+        return Ok(false);
+    }
+    let trash = trash_path(project_id, file_id);
+    std::fs::create_dir_all(trash.parent().unwrap())?;
+    std::fs::rename(dir, &trash)?;
+    Ok(true)
+}
+
+pub fn restore_from_trash(project_id: i64, file_id: i64) -> std::io::Result<()> {
+    std::fs::rename(trash_path(project_id, file_id), file_dir(project_id, file_id))
+}
+
+pub fn empty_trash(project_id: i64, file_id: i64) {
+    let trash = trash_path(project_id, file_id);
+    if let Err(e) = std::fs::remove_dir_all(&trash) {
+        eprintln!("Failed to remove {}: {}", trash.display(), e);
+    }
+}
+
+/// A deleted project's directory is parked here until its rows are gone, like `trash_path` for files.
+pub fn project_trash_path(project_id: i64) -> PathBuf {
+    PathBuf::from(format!("{}/.deleted-{}", PROJECT_DATA_DIR, project_id))
+}
+
+pub fn move_project_to_trash(project_id: i64) -> std::io::Result<bool> {
+    let dir = project_dir(project_id);
+    if !dir.exists() {
+        return Ok(false);
+    }
+    std::fs::rename(dir, project_trash_path(project_id))?;
+    Ok(true)
+}
+
+pub fn restore_project_from_trash(project_id: i64) -> std::io::Result<()> {
+    std::fs::rename(project_trash_path(project_id), project_dir(project_id))
+}
+
+pub fn empty_project_trash(project_id: i64) {
+    let trash = project_trash_path(project_id);
+    if let Err(e) = std::fs::remove_dir_all(&trash) {
+        eprintln!("Failed to remove {}: {}", trash.display(), e);
+    }
+}
+
+/// Lists the ids of deleted projects whose directories are still parked in the trash.
+pub fn list_project_trash() -> Vec<i64> {
+    let mut trashed = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(PROJECT_DATA_DIR) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(Ok(project_id)) = name.strip_prefix(".deleted-").map(|id| id.parse::<i64>()) {
+                trashed.push(project_id);
+            }
+        }
+    }
+    trashed.sort();
+    trashed
+}
+
+pub async fn recover_trashed_project(db_pool: &SqlitePool, project_id: i64) -> Result<(), sqlx::Error> {
+    let exists: Option<i64> = sqlx::query_scalar("SELECT id FROM projects WHERE id = ?")
+        .bind(project_id)
+        .fetch_optional(db_pool)
+        .await?;
+
+    match exists {
+        Some(_) => {
+            if let Err(e) = restore_project_from_trash(project_id) {
+                eprintln!("Failed to restore project {}: {}", project_id, e);
+            }
+        },
+        None => empty_project_trash(project_id),
+    }
+    Ok(())
+}
+
+/// Lists the (project id, file id) pairs currently sitting in a trash directory.
+pub fn list_trash() -> Vec<(i64, i64)> {
+    let mut trashed = Vec::new();
+    let projects = match std::fs::read_dir(PROJECT_DATA_DIR) {
+        Ok(projects) => projects,
+        Err(_) => return trashed,
+    };
+    for project in projects.flatten() {
+        let project_id = match project.file_name().to_string_lossy().parse::<i64>() {
+            Ok(project_id) => project_id,
+            Err(_) => continue,
+        };
+        if let Ok(files) = std::fs::read_dir(project.path().join(".trash")) {
+            for file in files.flatten() {
+                if let Ok(file_id) = file.file_name().to_string_lossy().parse::<i64>() {
+                    trashed.push((project_id, file_id));
+                }
+            }
+        }
+    }
+    trashed.sort();
+    trashed
+}
+
+/// Finishes or rolls back a delete interrupted by a crash: if the file's row is still in the
+/// database the delete never committed and the file is put back, otherwise the file is removed.
+pub async fn recover_trashed_file(db_pool: &SqlitePool, project_id: i64, file_id: i64) -> Result<(), sqlx::Error> {
+    let exists: Option<i64> = sqlx::query_scalar("SELECT id FROM file_entry WHERE id = ?")
+        .bind(file_id)
+        .fetch_optional(db_pool)
+        .await?;
+
+    match exists {
+        Some(_) => {
+            if let Err(e) = restore_from_trash(project_id, file_id) {
+                eprintln!("Failed to restore file {}: {}", file_id, e);
+            }
+        },
+        None => empty_trash(project_id, file_id),
+    }
+    Ok(())
+}
+
+pub async fn recover_trash(db_pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    for project_id in list_project_trash() {
+        recover_trashed_project(db_pool, project_id).await?;
+    }
+    for (project_id, file_id) in list_trash() {
+        recover_trashed_file(db_pool, project_id, file_id).await?;
+    }
+    Ok(())
+}
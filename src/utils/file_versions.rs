@@ -0,0 +1,319 @@
+use sqlx::{SqlitePool};
+use sqlx::Acquire;
+use serde::Serialize;
+use std::path::PathBuf;
+use crate::utils::file_store;
+use crate::utils::hash::sha256_hex;
+use crate::chunking::strategy::ChunkingStrategy;
+use crate::parsing::{DocumentFormat, ParsedDocument};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreStatus {
+    Created,
+    Updated,
+    Unchanged,
+}
+
+#[derive(Serialize, Debug)]
+pub struct StoredFile {
+    pub id: i64,
+    pub version: i64,
+    pub content_hash: String,
+    pub status: StoreStatus,
+}
+
+/// A fully written upload in a temporary file, waiting to be stored as a version of `name`.
+#[derive(Debug)]
+pub struct PendingFile {
+    pub name: String,
+    pub temp_path: PathBuf,
+    pub content_hash: String,
+    pub size: i64,
+}
+
+/// What a text document sets on its file together with a new version.
+#[derive(Debug)]
+pub struct DocumentFields<'a> {
+    pub external_id: Option<&'a str>,
+    /// JSON object; `None` keeps the file's metadata.
+    pub metadata: Option<String>,
+}
+
+/// Records the fully written `file` as the newest version of its name in the project.
+/// The file becomes the current version unless its content matches the current version, in which
+/// case the upload is discarded. Versions beyond the project's `max_file_versions` are pruned.
+/// `chunking` is kept with the version and overrides the project's strategy when it is embedded, and `document`
+/// is written to the file in the same transaction as the version.
+///
+/// The file is looked up, or created, by the first statement of the transaction, which holds the database's
+/// write lock from then on, so concurrent uploads of the same name get consecutive versions of one file.
+pub async fn store_version(db_pool: &SqlitePool, project_id: i64, file: &PendingFile, chunking: Option<&ChunkingStrategy>, document: Option<&DocumentFields<'_>>) -> Result<StoredFile, String> {
+    let PendingFile { name, temp_path, content_hash, size } = file;
+    let content_hash = content_hash.clone();
+    let chunking = chunking.map(|chunking| chunking.to_json());
+    let mut conn = db_pool.acquire().await.map_err(|e| e.to_string())?;
+    let mut transaction = conn.begin().await.map_err(|e| e.to_string())?;
+
+    let created = sqlx::query(
+        r#"
+        INSERT INTO file_entry (name, path, project_id, content_hash, current_version)
+        VALUES (?, '', ?, ?, 1)
+        ON CONFLICT (project_id, name) DO NOTHING;
+        "#,
+    )
+    .bind(name)
+    .bind(project_id)
+    .bind(&content_hash)
+    .execute(&mut transaction)
+    .await
+    .map_err(|e| e.to_string())?
+    .rows_affected() > 0;
+    let (file_id, current_hash, current_version): (i64, Option<String>, i64) = sqlx::query_as(
+        r#"
+        SELECT id, content_hash, current_version FROM file_entry WHERE project_id = ? AND name = ?
+        "#,
+    )
+    .bind(project_id)
+    .bind(name)
+    .fetch_one(&mut transaction)
+    .await
+    .map_err(|e| e.to_string())?;
+    if let Some(document) = document {
+        sqlx::query("UPDATE file_entry SET external_id = ?, metadata = COALESCE(?, metadata) WHERE id = ?")
+            .bind(document.external_id)
+            .bind(&document.metadata)
+            .bind(file_id)
+            .execute(&mut transaction)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    if !created && current_hash.as_deref() == Some(content_hash.as_str()) {
+        if chunking.is_some() {
+            // Same content chunked differently: the next embed_file redoes the chunks
+            let result = async {
+                let result = sqlx::query(
+                    r#"
+                    UPDATE file_entry SET embedded_hash = NULL
+                    WHERE id = ? AND EXISTS (SELECT 1 FROM file_version WHERE file_id = ? AND version = ? AND chunking IS NOT ?)
+                    "#,
+                )
+                .bind(file_id)
+                .bind(file_id)
+                .bind(current_version)
+                .bind(&chunking)
+                .execute(&mut transaction)
+                .await?;
+                if result.rows_affected() > 0 {
+                    sqlx::query("DELETE FROM file_chunk WHERE file_id = ? AND version = ?")
+                        .bind(file_id)
+                        .bind(current_version)
+                        .execute(&mut transaction)
+                        .await?;
+                }
+                sqlx::query("UPDATE file_version SET chunking = ? WHERE file_id = ? AND version = ?")
+                    .bind(&chunking)
+                    .bind(file_id)
+                    .bind(current_version)
+                    .execute(&mut transaction)
+                    .await
+            }.await;
+            result.map_err(|e| e.to_string())?;
+        }
+        transaction.commit().await.map_err(|e| e.to_string())?;
+        let _ = std::fs::remove_file(temp_path);
+        return Ok(StoredFile { id: file_id, version: current_version, content_hash, status: StoreStatus::Unchanged });
+    }
+
+    let (version, status) = if created {
+        (1, StoreStatus::Created)
+    } else {
+        let latest: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM file_version WHERE file_id = ?")
+            .bind(file_id)
+            .fetch_one(&mut transaction)
+            .await
+            .map_err(|e| e.to_string())?;
+        (latest.unwrap_or(0) + 1, StoreStatus::Updated)
+    };
+
+    let path = file_store::version_path(project_id, file_id, version, name);
+    std::fs::create_dir_all(file_store::file_dir(project_id, file_id)).map_err(|e| e.to_string())?;
+    std::fs::rename(temp_path, &path).map_err(|e| e.to_string())?;
+    let path = path.to_string_lossy().to_string();
+
+    let result = async {
+        sqlx::query(
+            r#"
+            INSERT INTO file_version (file_id, version, path, content_hash, size, chunking)
+            VALUES (?, ?, ?, ?, ?, ?);
+            "#,
+        )
+        .bind(file_id)
+        .bind(version)
+        .bind(&path)
+        .bind(&content_hash)
+        .bind(size)
+        .bind(&chunking)
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query("UPDATE file_entry SET path = ?, content_hash = ?, current_version = ? WHERE id = ?")
+            .bind(&path)
+            .bind(&content_hash)
+            .bind(version)
+            .bind(file_id)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await
+    }.await;
+
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&path);
+        return Err(e.to_string());
+    }
+
+    if let Err(e) = prune_versions(db_pool, project_id, file_id).await {
+        eprintln!("Failed to prune versions of file {}: {}", file_id, e);
+    }
+
+    Ok(StoredFile { id: file_id, version, content_hash, status })
+}
+
+/// Gives files uploaded before versioning, which sit directly in the project directory without `file_version`
+/// rows, their current version under `files/{file_id}/` so they are stored like every other file. Files that
+/// are missing on disk are left for `fsck` to report.
+pub async fn adopt_unversioned(db_pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let files: Vec<(i64, i64, String, String, i64)> = sqlx::query_as(
+        r#"
+        SELECT id, project_id, name, path, current_version FROM file_entry
+        WHERE NOT EXISTS (SELECT 1 FROM file_version WHERE file_version.file_id = file_entry.id)
+        "#,
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    for (file_id, project_id, name, path, version) in files {
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("Not adopting file {} at {}: {}", file_id, path, e);
+                continue;
+            }
+        };
+        let stored_path = file_store::version_path(project_id, file_id, version, &name);
+        if let Err(e) = std::fs::create_dir_all(file_store::file_dir(project_id, file_id)).and_then(|_| std::fs::rename(&path, &stored_path)) {
+            eprintln!("Failed to move {}: {}", path, e);
+            continue;
+        }
+        let stored = stored_path.to_string_lossy().to_string();
+        let content_hash = sha256_hex(&bytes);
+
+        let mut conn = db_pool.acquire().await?;
+        let mut transaction = conn.begin().await?;
+        let result = async {
+            sqlx::query("INSERT INTO file_version (file_id, version, path, content_hash, size) VALUES (?, ?, ?, ?, ?)")
+                .bind(file_id)
+                .bind(version)
+                .bind(&stored)
+                .bind(&content_hash)
+                .bind(bytes.len() as i64)
+                .execute(&mut transaction)
+                .await?;
+            sqlx::query("UPDATE file_entry SET path = ?, content_hash = COALESCE(content_hash, ?) WHERE id = ?")
+                .bind(&stored)
+                .bind(&content_hash)
+                .bind(file_id)
+                .execute(&mut transaction)
+                .await?;
+            transaction.commit().await
+        }.await;
+        if let Err(e) = result {
+            let _ = std::fs::rename(&stored_path, &path);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Records the format of a stored version and, for documents, writes their extracted text next to it. Chunks of
+/// the version refer to the extracted text from then on.
+pub async fn save_text(db_pool: &SqlitePool, project_id: i64, file_id: i64, version: i64, format: DocumentFormat, parsed: Option<&ParsedDocument>) -> Result<(), String> {
+    let text_path = match parsed {
+        Some(parsed) => {
+            let path = file_store::text_path(project_id, file_id, version);
+            std::fs::create_dir_all(file_store::file_dir(project_id, file_id)).map_err(|e| e.to_string())?;
+            std::fs::write(&path, &parsed.text).map_err(|e| e.to_string())?;
+            Some(path.to_string_lossy().to_string())
+        },
+        None => None,
+    };
+    let sections = parsed.map(|parsed| serde_json::to_string(&parsed.sections)).transpose().map_err(|e| e.to_string())?;
+
+    let result = sqlx::query("UPDATE file_version SET format = ?, text_path = ?, sections = ? WHERE file_id = ? AND version = ?")
+        .bind(format.as_str())
+        .bind(&text_path)
+        .bind(&sections)
+        .bind(file_id)
+        .bind(version)
+        .execute(db_pool)
+        .await;
+    if let Err(e) = result {
+        if let Some(text_path) = &text_path {
+            let _ = std::fs::remove_file(text_path);
+        }
+        return Err(e.to_string());
+    }
+    Ok(())
+}
+
+/// Removes the oldest versions of a file, with their embeddings and blobs, until at most the
+/// project's `max_file_versions` remain. The current version is always kept.
+pub async fn prune_versions(db_pool: &SqlitePool, project_id: i64, file_id: i64) -> Result<(), sqlx::Error> {
+    let max_versions: i64 = sqlx::query_scalar("SELECT max_file_versions FROM projects WHERE id = ?")
+        .bind(project_id)
+        .fetch_one(db_pool)
+        .await?;
+
+    let expired: Vec<(i64, String, Option<String>)> = sqlx::query_as(
+        r#"
+        SELECT version, path, text_path FROM file_version
+        WHERE file_id = ?
+            AND version != (SELECT current_version FROM file_entry WHERE id = ?)
+        ORDER BY version DESC
+        LIMIT -1 OFFSET ?
+        "#,
+    )
+    .bind(file_id)
+    .bind(file_id)
+    .bind(std::cmp::max(max_versions - 1, 0))
+    .fetch_all(db_pool)
+    .await?;
+
+    for (version, path, text_path) in expired {
+        let mut conn = db_pool.acquire().await?;
+        let mut transaction = conn.begin().await?;
+        sqlx::query("DELETE FROM file_embedding WHERE file_id = ? AND version = ?")
+            .bind(file_id)
+            .bind(version)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("DELETE FROM file_chunk WHERE file_id = ? AND version = ?")
+            .bind(file_id)
+            .bind(version)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("DELETE FROM file_version WHERE file_id = ? AND version = ?")
+            .bind(file_id)
+            .bind(version)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+
+        for path in std::iter::once(path).chain(text_path) {
+            if let Err(e) = std::fs::remove_file(&path) {
+                eprintln!("Failed to remove {}: {}", path, e);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,6 @@
+use sha2::{Digest, Sha256};
+
+/// Hex-encoded SHA-256 of `data`, used for file content hashes and chunk hashes.
+pub fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
@@ -0,0 +1,204 @@
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use sqlx::Acquire;
+
+/// The number of migrations; `PRAGMA user_version` holds how many a database has been through.
+pub const SCHEMA_VERSION: i64 = 2;
+
+/// Brings a database created by an older init.sql up to the current schema. New databases are created from
+/// init.sql, which always has the current schema, and go through the same migrations without changes: each one
+/// only adds the columns, tables and indexes that are missing.
+pub async fn run(db_pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let mut conn = db_pool.acquire().await?;
+    let applied: i64 = sqlx::query_scalar("PRAGMA user_version").fetch_one(&mut conn).await?;
+    for version in applied + 1..=SCHEMA_VERSION {
+        let mut transaction = conn.begin().await?;
+        match version {
+            1 => versioned_embeddings(&mut transaction).await?,
+            _ => unique_file_names(&mut transaction).await?,
+        }
+        sqlx::query(&format!("PRAGMA user_version = {}", version))
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+    }
+    Ok(())
+}
+
+async fn add_columns(transaction: &mut Transaction<'_, Sqlite>, table: &str, columns: &[(&str, &str)]) -> Result<(), sqlx::Error> {
+    for (column, definition) in columns {
+        let exists: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?")
+            .bind(table)
+            .bind(column)
+            .fetch_one(&mut *transaction)
+            .await?;
+        if exists == 0 {
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+                .execute(&mut *transaction)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// From the first schema to file versions, per-project embedding settings, chunk tracking, the embedding cache,
+/// token usage, jobs and documents.
+async fn versioned_embeddings(transaction: &mut Transaction<'_, Sqlite>) -> Result<(), sqlx::Error> {
+    add_columns(transaction, "projects", &[
+        ("index_type", "TEXT NOT NULL DEFAULT 'vptree'"),
+        ("max_file_versions", "INTEGER NOT NULL DEFAULT 10"),
+        ("embedding_provider", "TEXT NOT NULL DEFAULT 'openai'"),
+        ("embedding_base_url", "TEXT"),
+        ("embedding_model", "TEXT"),
+        ("embedding_dimensions", "INTEGER"),
+        ("chunking", "TEXT"),
+        ("monthly_token_quota", "INTEGER"),
+    ]).await?;
+    add_columns(transaction, "file_entry", &[
+        ("content_hash", "TEXT"),
+        ("embedded_hash", "TEXT"),
+        ("current_version", "INTEGER NOT NULL DEFAULT 1"),
+        ("external_id", "TEXT"),
+        ("metadata", "TEXT"),
+    ]).await?;
+    add_columns(transaction, "file_embedding", &[
+        ("chunk_hash", "TEXT"),
+        ("symbol", "TEXT"),
+        ("start_line", "INTEGER"),
+        ("end_line", "INTEGER"),
+    ]).await?;
+
+    // Embeddings belong to a version of their file, which is part of the key. SQLite can't change a primary
+    // key, so the table is copied; the embeddings there are of each file's first (and only) version.
+    let versioned: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_info('file_embedding') WHERE name = 'version'")
+        .fetch_one(&mut *transaction)
+        .await?;
+    if versioned == 0 {
+        sqlx::query(
+            r#"
+            CREATE TABLE file_embedding_versioned (
+                file_id INTEGER NOT NULL,
+                version INTEGER NOT NULL DEFAULT 1,
+                start_byte INTEGER NOT NULL,
+                end_byte INTEGER NOT NULL,
+                chunk_hash TEXT,
+                symbol TEXT,
+                start_line INTEGER,
+                end_line INTEGER,
+                embedding BLOB NOT NULL,
+                FOREIGN KEY (file_id) REFERENCES file_entry(id),
+                PRIMARY KEY (file_id, version, start_byte, end_byte)
+            );
+            INSERT INTO file_embedding_versioned (file_id, start_byte, end_byte, chunk_hash, symbol, start_line, end_line, embedding)
+                SELECT file_id, start_byte, end_byte, chunk_hash, symbol, start_line, end_line, embedding FROM file_embedding;
+            DROP TABLE file_embedding;
+            ALTER TABLE file_embedding_versioned RENAME TO file_embedding;
+            "#,
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS file_version (
+            file_id INTEGER NOT NULL,
+            version INTEGER NOT NULL,
+            path TEXT NOT NULL,
+            content_hash TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (file_id, version),
+            FOREIGN KEY (file_id) REFERENCES file_entry(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS file_chunk (
+            file_id INTEGER NOT NULL,
+            version INTEGER NOT NULL,
+            start_byte INTEGER NOT NULL,
+            end_byte INTEGER NOT NULL,
+            chunk_hash TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            error TEXT,
+            FOREIGN KEY (file_id) REFERENCES file_entry(id),
+            PRIMARY KEY (file_id, version, start_byte, end_byte)
+        );
+
+        CREATE TABLE IF NOT EXISTS embedding_cache (
+            model TEXT NOT NULL,
+            text_hash TEXT NOT NULL,
+            embedding BLOB NOT NULL,
+            hits INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            last_used_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (model, text_hash)
+        );
+
+        CREATE TABLE IF NOT EXISTS token_usage (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            project_id INTEGER NOT NULL,
+            user_id INTEGER,
+            provider TEXT NOT NULL,
+            model TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            tokens INTEGER NOT NULL,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS embedding_job (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            project_id INTEGER NOT NULL,
+            file_id INTEGER NOT NULL,
+            status TEXT NOT NULL DEFAULT 'queued',
+            chunks_done INTEGER NOT NULL DEFAULT 0,
+            chunks_total INTEGER NOT NULL DEFAULT 0,
+            tokens INTEGER NOT NULL DEFAULT 0,
+            error TEXT,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            started_at TEXT,
+            finished_at TEXT,
+            FOREIGN KEY (project_id) REFERENCES projects(id)
+        );
+        "#,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    add_columns(transaction, "file_version", &[
+        ("chunking", "TEXT"),
+        ("format", "TEXT"),
+        ("text_path", "TEXT"),
+        ("sections", "TEXT"),
+    ]).await?;
+    add_columns(transaction, "embedding_job", &[
+        ("force", "BOOLEAN NOT NULL DEFAULT 0"),
+        ("user_id", "INTEGER"),
+    ]).await?;
+
+    sqlx::query(
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_file_embedding_file_id ON file_embedding(file_id);
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_file_entry_external_id ON file_entry(project_id, external_id);
+        CREATE INDEX IF NOT EXISTS idx_embedding_job_status ON embedding_job(status);
+        CREATE INDEX IF NOT EXISTS idx_embedding_cache_last_used_at ON embedding_cache(last_used_at);
+        CREATE INDEX IF NOT EXISTS idx_token_usage_project_id ON token_usage(project_id, created_at);
+        CREATE INDEX IF NOT EXISTS idx_token_usage_user_id ON token_usage(user_id, created_at);
+        "#,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+/// Uploads find their file by name, which becomes unique within a project. Older databases may hold several files of
+/// the same name; all but the first are renamed after their id so none of them is lost.
+async fn unique_file_names(transaction: &mut Transaction<'_, Sqlite>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE file_entry SET name = name || ' (' || id || ')'
+        WHERE id NOT IN (SELECT MIN(id) FROM file_entry GROUP BY project_id, name);
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_file_entry_name ON file_entry(project_id, name);
+        "#,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
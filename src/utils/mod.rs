@@ -1 +1,9 @@
-pub mod middleware;
\ No newline at end of file
+pub mod middleware;
+pub mod file_store;
+pub mod hash;
+pub mod file_versions;
+pub mod archive;
+pub mod documents;
+pub mod uploads;
+pub mod permissions;
+pub mod migrations;
\ No newline at end of file
@@ -0,0 +1,16 @@
+use sqlx::SqlitePool;
+
+/// Whether the user may read the project's files, which takes a `user_project` row of any permission type.
+/// Requests without a user, which only reach the handlers while authentication is off, aren't checked.
+pub async fn can_read_project(db_pool: &SqlitePool, project_id: i64, user_id: Option<i64>) -> Result<bool, sqlx::Error> {
+    let user_id = match user_id {
+        Some(user_id) => user_id,
+        None => return Ok(true),
+    };
+    let permission: Option<String> = sqlx::query_scalar("SELECT permission_type FROM user_project WHERE user_id = ? AND project_id = ?")
+        .bind(user_id)
+        .bind(project_id)
+        .fetch_optional(db_pool)
+        .await?;
+    Ok(permission.is_some())
+}
@@ -0,0 +1,67 @@
+use sqlx::SqlitePool;
+
+pub const DEFAULT_MAX_UPLOAD_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Content types accepted when `UPLOAD_ALLOWED_TYPES` isn't set: text, the documents that are parsed, archives
+/// and unlabelled binary data.
+pub const DEFAULT_ALLOWED_TYPES: &[&str] = &[
+    "text/*",
+    "application/json",
+    "application/x-ndjson",
+    "application/xml",
+    "application/pdf",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "application/zip",
+    "application/x-zip-compressed",
+    "application/gzip",
+    "application/x-gzip",
+    "application/x-tar",
+    "application/octet-stream",
+];
+
+/// What may be uploaded, from `UPLOAD_MAX_BYTES`, `PROJECT_MAX_BYTES` and `UPLOAD_ALLOWED_TYPES` (a comma
+/// separated list where `type/*` matches every subtype).
+#[derive(Debug, Clone)]
+pub struct UploadLimits {
+    pub max_file_bytes: u64,
+    /// The most all stored versions of a project's files may add up to; unlimited when `None`.
+    pub max_project_bytes: Option<u64>,
+    pub allowed_types: Vec<String>,
+}
+
+impl UploadLimits {
+    pub fn from_env() -> UploadLimits {
+        let allowed_types = match std::env::var("UPLOAD_ALLOWED_TYPES") {
+            Ok(types) => types.split(',').map(|content_type| content_type.trim().to_lowercase()).filter(|content_type| !content_type.is_empty()).collect(),
+            Err(_) => DEFAULT_ALLOWED_TYPES.iter().map(|content_type| content_type.to_string()).collect(),
+        };
+        UploadLimits {
+            max_file_bytes: std::env::var("UPLOAD_MAX_BYTES").ok().and_then(|value| value.parse().ok()).unwrap_or(DEFAULT_MAX_UPLOAD_BYTES),
+            max_project_bytes: std::env::var("PROJECT_MAX_BYTES").ok().and_then(|value| value.parse().ok()),
+            allowed_types,
+        }
+    }
+
+    /// Whether a content type such as `text/markdown; charset=utf-8` is allowed. Parameters are ignored.
+    pub fn allows_type(&self, content_type: &str) -> bool {
+        let content_type = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+        let main_type = content_type.split('/').next().unwrap_or("");
+        self.allowed_types.iter().any(|allowed| {
+            *allowed == content_type || *allowed == "*/*" || allowed.strip_suffix("/*") == Some(main_type)
+        })
+    }
+}
+
+/// Bytes taken by every stored version of the project's files.
+pub async fn project_bytes(db_pool: &SqlitePool, project_id: i64) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(SUM(file_version.size), 0) FROM file_version
+        JOIN file_entry ON file_entry.id = file_version.file_id
+        WHERE file_entry.project_id = ?
+        "#,
+    )
+    .bind(project_id)
+    .fetch_one(db_pool)
+    .await
+}